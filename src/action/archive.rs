@@ -0,0 +1,519 @@
+use std::{
+    collections::HashSet,
+    fs::{self, File, Metadata},
+    io::{self, BufWriter, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, Local, Timelike};
+use flate2::{write::GzEncoder, Compression};
+use serde_json::json;
+use tar::HeaderMode;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::{
+    config::Config, dir_entry::DirEntry, error::print_error, error_codes::ExitCode,
+    filter::format_bytes, output::ReportFormat, walk,
+};
+
+//归档格式，根据归档文件的扩展名确定
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+impl ArchiveFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_lowercase();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveFormat::Tar)
+        } else if name.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else {
+            None
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ArchiveFormat::Tar => "tar",
+            ArchiveFormat::TarGz => "tar.gz",
+            ArchiveFormat::Zip => "zip",
+        }
+    }
+}
+
+/*
+把匹配的条目写入归档文件（--archive）。
+1.归档中的路径相对于条目所属的搜索路径。匹配的目录连同其中的内容一起写入，
+  与搜索时一样跳过隐藏的和被忽略的条目；位于另一个匹配目录中的条目不会重复写入。
+2.保留权限和修改时间，符号链接以链接的形式保存，不跟随。
+  tar 可以保存管道、设备等特殊文件；zip 不能，这些文件作为错误报告。
+3.写入的每个路径输出到 stdout，结束后按 --stats 的格式在 stderr 输出归档摘要。
+*/
+#[derive(Debug)]
+pub struct Archiver {
+    path: PathBuf,
+    format: ArchiveFormat,
+}
+
+//归档摘要
+#[derive(Default)]
+struct Summary {
+    files: u64,
+    dirs: u64,
+    symlinks: u64,
+    input_bytes: u64,
+    errors: u64,
+}
+
+impl Archiver {
+    pub fn new(path: PathBuf) -> Result<Self> {
+        let format = ArchiveFormat::from_path(&path).ok_or_else(|| {
+            anyhow!(
+                "无法根据'{}'确定归档格式，支持 .tar、.tar.gz、.tgz 和 .zip",
+                path.display()
+            )
+        })?;
+        Ok(Self { path, format })
+    }
+
+    pub fn run<W: Write>(
+        &self,
+        w: &mut W,
+        mut entries: Vec<DirEntry>,
+        config: &Config,
+    ) -> ExitCode {
+        entries.sort();
+        let dirs: HashSet<PathBuf> = entries
+            .iter()
+            .filter(|entry| entry.file_type().is_some_and(|ft| ft.is_dir()))
+            .map(|entry| entry.path().to_path_buf())
+            .collect();
+
+        let mut writer = match self.create() {
+            Ok(writer) => writer,
+            Err(err) => {
+                print_error(format!("无法创建归档'{}': {}", self.path.display(), err));
+                return ExitCode::GeneralError;
+            }
+        };
+        //归档文件本身可能位于搜索路径或者匹配的目录中
+        let archive_path = self.path.canonicalize().ok();
+
+        let mut summary = Summary::default();
+        for entry in &entries {
+            let source = entry.path();
+            if source.ancestors().skip(1).any(|dir| dirs.contains(dir))
+                || is_same_file(source, archive_path.as_deref())
+            {
+                continue;
+            }
+            let name = match entry.root() {
+                Some(root) => source.strip_prefix(root).unwrap_or(source),
+                None => Path::new(source.file_name().unwrap_or(source.as_os_str())),
+            };
+            let added = self.add(
+                &mut *writer,
+                w,
+                (source, name),
+                config,
+                archive_path.as_deref(),
+                &mut summary,
+            );
+            if let Err(err) = added {
+                print_error(format!("无法写入输出: {err}"));
+                return ExitCode::GeneralError;
+            }
+        }
+
+        if let Err(err) = writer.finish() {
+            print_error(format!("无法写入归档'{}': {}", self.path.display(), err));
+            return ExitCode::GeneralError;
+        }
+
+        let archive_bytes = fs::metadata(&self.path).map_or(0, |m| m.len());
+        let stderr = io::stderr();
+        let _ = match config.stats.unwrap_or(ReportFormat::Text) {
+            ReportFormat::Text => self.print_text(&mut stderr.lock(), &summary, archive_bytes),
+            ReportFormat::Json => self.print_json(&mut stderr.lock(), &summary, archive_bytes),
+        };
+
+        if summary.errors > 0 {
+            ExitCode::GeneralError
+        } else {
+            ExitCode::Success
+        }
+    }
+
+    fn create(&self) -> io::Result<Box<dyn ArchiveWriter>> {
+        let file = BufWriter::new(File::create(&self.path)?);
+        Ok(match self.format {
+            ArchiveFormat::Tar => Box::new(TarWriter::new(file)),
+            ArchiveFormat::TarGz => {
+                Box::new(TarWriter::new(GzEncoder::new(file, Compression::default())))
+            }
+            ArchiveFormat::Zip => Box::new(ZipWriter::new(file)),
+        })
+    }
+
+    //写入一个匹配的条目，目录会按搜索时的忽略规则写入其中的内容，但不会写入归档文件本身。
+    // 单个条目写入失败只记录错误，只有写入 stdout 失败时才返回 Err。
+    fn add<W: Write>(
+        &self,
+        writer: &mut dyn ArchiveWriter,
+        w: &mut W,
+        entry: (&Path, &Path),
+        config: &Config,
+        archive_path: Option<&Path>,
+        summary: &mut Summary,
+    ) -> io::Result<()> {
+        let (source, name) = entry;
+        if !source.symlink_metadata().is_ok_and(|m| m.is_dir()) {
+            return self.add_one(writer, w, source, name, summary);
+        }
+
+        //归档按链接本身保存符号链接，所以不跟随
+        let mut builder = walk::walk_builder(config, source);
+        builder
+            .follow_links(false)
+            .sort_by_file_name(|a, b| a.cmp(b));
+        for child in builder.build() {
+            let child = match child {
+                Ok(child) => child,
+                Err(err) => {
+                    print_error(format!("无法读取'{}': {}", source.display(), err));
+                    summary.errors += 1;
+                    continue;
+                }
+            };
+            let path = child.path();
+            if is_same_file(path, archive_path) {
+                continue;
+            }
+            //目录本身的相对路径为空，join 会在名称后面多加一个 /
+            let child_name = match path.strip_prefix(source) {
+                Ok(relative) if relative.as_os_str().is_empty() => name.to_path_buf(),
+                Ok(relative) => name.join(relative),
+                Err(_) => continue,
+            };
+            self.add_one(writer, w, path, &child_name, summary)?;
+        }
+        Ok(())
+    }
+
+    //写入一个文件、目录或符号链接，不包括目录中的内容
+    fn add_one<W: Write>(
+        &self,
+        writer: &mut dyn ArchiveWriter,
+        w: &mut W,
+        source: &Path,
+        name: &Path,
+        summary: &mut Summary,
+    ) -> io::Result<()> {
+        let result = source.symlink_metadata().and_then(|metadata| {
+            let file_type = metadata.file_type();
+            if file_type.is_symlink() {
+                writer.add_symlink(name, &fs::read_link(source)?, &metadata)?;
+                summary.symlinks += 1;
+            } else if file_type.is_dir() {
+                writer.add_dir(name, &metadata)?;
+                summary.dirs += 1;
+            } else {
+                writer.add_file(name, source, &metadata)?;
+                summary.files += 1;
+                summary.input_bytes += metadata.len();
+            }
+            Ok(())
+        });
+
+        match result {
+            Ok(()) => writeln!(w, "{}", name.to_string_lossy()),
+            Err(err) => {
+                print_error(format!("无法归档'{}': {}", source.display(), err));
+                summary.errors += 1;
+                Ok(())
+            }
+        }
+    }
+
+    fn print_text<W: Write>(
+        &self,
+        w: &mut W,
+        summary: &Summary,
+        archive_bytes: u64,
+    ) -> io::Result<()> {
+        writeln!(
+            w,
+            "Archive:     {} ({})",
+            self.path.to_string_lossy(),
+            self.format.name()
+        )?;
+        writeln!(
+            w,
+            "Added:       {} files, {} directories, {} symlinks",
+            summary.files, summary.dirs, summary.symlinks
+        )?;
+        writeln!(
+            w,
+            "Input size:  {} ({} bytes)",
+            format_bytes(summary.input_bytes),
+            summary.input_bytes
+        )?;
+        writeln!(
+            w,
+            "Output size: {} ({} bytes)",
+            format_bytes(archive_bytes),
+            archive_bytes
+        )?;
+        writeln!(w, "Errors:      {}", summary.errors)
+    }
+
+    fn print_json<W: Write>(
+        &self,
+        w: &mut W,
+        summary: &Summary,
+        archive_bytes: u64,
+    ) -> io::Result<()> {
+        let report = json!({
+            "archive": self.path.to_string_lossy(),
+            "format": self.format.name(),
+            "added": {
+                "files": summary.files,
+                "directories": summary.dirs,
+                "symlinks": summary.symlinks,
+            },
+            "input_bytes": summary.input_bytes,
+            "output_bytes": archive_bytes,
+            "errors": summary.errors,
+        });
+        writeln!(w, "{report}")
+    }
+}
+
+//不同归档格式的写入方式
+trait ArchiveWriter {
+    fn add_file(&mut self, name: &Path, source: &Path, metadata: &Metadata) -> io::Result<()>;
+    fn add_dir(&mut self, name: &Path, metadata: &Metadata) -> io::Result<()>;
+    fn add_symlink(&mut self, name: &Path, target: &Path, metadata: &Metadata) -> io::Result<()>;
+    fn finish(self: Box<Self>) -> io::Result<()>;
+}
+
+struct TarWriter<W: Write> {
+    builder: tar::Builder<W>,
+}
+
+impl<W: Write> TarWriter<W> {
+    fn new(w: W) -> Self {
+        let mut builder = tar::Builder::new(w);
+        builder.mode(HeaderMode::Complete);
+        builder.follow_symlinks(false);
+        Self { builder }
+    }
+}
+
+//tar 的 append_path_with_name 会根据元数据写入文件、目录或符号链接，并保留权限和修改时间
+impl<W: Write> ArchiveWriter for TarWriter<W> {
+    fn add_file(&mut self, name: &Path, source: &Path, _: &Metadata) -> io::Result<()> {
+        self.builder.append_path_with_name(source, name)
+    }
+
+    fn add_dir(&mut self, name: &Path, metadata: &Metadata) -> io::Result<()> {
+        let mut header = tar::Header::new_gnu();
+        header.set_metadata_in_mode(metadata, HeaderMode::Complete);
+        header.set_size(0);
+        self.builder.append_data(&mut header, name, io::empty())
+    }
+
+    fn add_symlink(&mut self, name: &Path, target: &Path, metadata: &Metadata) -> io::Result<()> {
+        let mut header = tar::Header::new_gnu();
+        header.set_metadata_in_mode(metadata, HeaderMode::Complete);
+        header.set_size(0);
+        self.builder.append_link(&mut header, name, target)
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        self.builder.into_inner()?.flush()
+    }
+}
+
+impl<W: Write + io::Seek> ArchiveWriter for ZipWriter<W> {
+    fn add_file(&mut self, name: &Path, source: &Path, metadata: &Metadata) -> io::Result<()> {
+        //打开管道会一直阻塞，打开套接字会失败，所以在开始写入之前检查
+        if !metadata.is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "zip 归档不能保存管道、套接字和设备文件",
+            ));
+        }
+        let options = zip_options(metadata)
+            .compression_method(CompressionMethod::Deflated)
+            .large_file(metadata.len() >= u32::MAX as u64);
+        self.start_file(zip_name(name), options)?;
+        io::copy(&mut File::open(source)?, self)?;
+        Ok(())
+    }
+
+    fn add_dir(&mut self, name: &Path, metadata: &Metadata) -> io::Result<()> {
+        self.add_directory(zip_name(name), zip_options(metadata))?;
+        Ok(())
+    }
+
+    fn add_symlink(&mut self, name: &Path, target: &Path, metadata: &Metadata) -> io::Result<()> {
+        ZipWriter::add_symlink(
+            self,
+            zip_name(name),
+            zip_name(target),
+            zip_options(metadata),
+        )?;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        ZipWriter::finish(*self)?.flush()
+    }
+}
+
+//path 是否就是归档文件本身。先比较文件名，避免对每个条目都求规范路径
+fn is_same_file(path: &Path, archive_path: Option<&Path>) -> bool {
+    archive_path.is_some_and(|archive| {
+        path.file_name() == archive.file_name()
+            && path.canonicalize().is_ok_and(|path| path == archive)
+    })
+}
+
+//zip 中的路径总是使用 / 分隔
+fn zip_name(path: &Path) -> String {
+    let mut name = String::new();
+    for component in path.components() {
+        match component {
+            Component::RootDir => name.push('/'),
+            Component::Normal(part) => {
+                if !name.is_empty() && !name.ends_with('/') {
+                    name.push('/');
+                }
+                name.push_str(&part.to_string_lossy());
+            }
+            Component::ParentDir => {
+                if !name.is_empty() && !name.ends_with('/') {
+                    name.push('/');
+                }
+                name.push_str("..");
+            }
+            Component::CurDir | Component::Prefix(_) => {}
+        }
+    }
+    name
+}
+
+//zip 只能保存 1980 到 2107 年之间的修改时间，超出范围时使用默认时间
+fn zip_options(metadata: &Metadata) -> SimpleFileOptions {
+    let mut options = SimpleFileOptions::default();
+    if let Some(mode) = unix_mode(metadata) {
+        options = options.unix_permissions(mode);
+    }
+    if let Ok(modified) = metadata.modified() {
+        let time = DateTime::<Local>::from(modified);
+        let converted = zip::DateTime::from_date_and_time(
+            u16::try_from(time.year()).unwrap_or(0),
+            time.month() as u8,
+            time.day() as u8,
+            time.hour() as u8,
+            time.minute() as u8,
+            time.second() as u8,
+        );
+        if let Ok(converted) = converted {
+            options = options.last_modified_time(converted);
+        }
+    }
+    options
+}
+
+#[cfg(unix)]
+fn unix_mode(metadata: &Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn unix_mode(_: &Metadata) -> Option<u32> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{create_tree, search};
+
+    fn tar_names(path: &Path) -> Vec<String> {
+        let mut archive = tar::Archive::new(File::open(path).unwrap());
+        archive
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn skips_archive_inside_matched_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        create_tree(root, &["build/a.o", "build/sub/b.o"]);
+
+        let output = root.join("build/out.tar");
+        let archiver = Archiver::new(output.clone()).unwrap();
+        let entries = search(root, "^build$", Config::default());
+        let exit_code = archiver.run(&mut Vec::new(), entries, &Config::default());
+        assert_eq!(exit_code, ExitCode::Success);
+        assert_eq!(
+            tar_names(&output),
+            ["build", "build/a.o", "build/sub", "build/sub/b.o"]
+        );
+    }
+
+    #[test]
+    fn matched_directory_follows_ignore_rules() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        create_tree(root, &["logs/a.log", "logs/.hidden", "logs/skip.tmp"]);
+        fs::write(root.join(".fdignore"), "*.tmp\n").unwrap();
+
+        let output = dir.path().join("out.tar");
+        let archiver = Archiver::new(output.clone()).unwrap();
+        let config = Config {
+            ignore_hidden: true,
+            read_fdignore: true,
+            ..Default::default()
+        };
+        let entries = search(root, "^logs$", Config::default());
+        archiver.run(&mut Vec::new(), entries, &config);
+        assert_eq!(tar_names(&output), ["logs", "logs/a.log"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn zip_reports_special_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("src");
+        create_tree(&root, &["a.txt"]);
+        let status = std::process::Command::new("mkfifo")
+            .arg(root.join("pipe"))
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let output = dir.path().join("out.zip");
+        let archiver = Archiver::new(output.clone()).unwrap();
+        let entries = search(&root, "", Config::default());
+        let exit_code = archiver.run(&mut Vec::new(), entries, &Config::default());
+        assert_eq!(exit_code, ExitCode::GeneralError);
+
+        let archive = zip::ZipArchive::new(File::open(&output).unwrap()).unwrap();
+        assert_eq!(archive.file_names().collect::<Vec<_>>(), ["a.txt"]);
+    }
+}
@@ -0,0 +1,215 @@
+use std::{
+    cmp::Reverse,
+    collections::HashSet,
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use crate::{
+    config::Config, dir_entry::DirEntry, error::print_error, error_codes::ExitCode, trash,
+};
+
+use super::confirm;
+
+/*
+删除匹配的条目（--delete），或者移到回收站（--trash，to_trash 为 true）。
+1.默认只列出将被删除的条目；指定了 --yes，或者在终端中确认后才会真正删除。
+2.按深度从深到浅删除，先删除目录中的条目，再删除目录本身。
+  只删除匹配的条目：目录中还有未匹配的条目（包括隐藏的和被忽略的）时目录不会被删除，并报告错误；
+  recursive 为 true（--recursive）时匹配的目录连同其中的全部内容一起删除。
+  移到回收站时，位于另一个匹配目录中的条目随目录一起移动，不单独放入回收站。
+3.搜索路径本身永远不会被删除。
+4.某个条目删除失败时继续删除其余条目，最后以非零退出码结束。
+*/
+pub fn run<W: Write>(
+    w: &mut W,
+    entries: Vec<DirEntry>,
+    config: &Config,
+    dry_run: bool,
+    to_trash: bool,
+    recursive: bool,
+) -> ExitCode {
+    let mut targets = Vec::with_capacity(entries.len());
+    let mut refused = false;
+    for entry in entries {
+        if entry.root() == Some(entry.path()) {
+            print_error(format!("拒绝删除搜索路径'{}'", entry.path().display()));
+            refused = true;
+            continue;
+        }
+        let is_dir = entry.file_type().is_some_and(|ft| ft.is_dir());
+        targets.push((entry.into_stripped_path(config), is_dir));
+    }
+    targets.sort_by(|(a, _), (b, _)| a.cmp(b));
+    if to_trash || recursive {
+        remove_nested(&mut targets);
+    }
+    let (verb, option) = if to_trash {
+        ("移到回收站", "--trash")
+    } else {
+        ("删除", "--delete")
+    };
+
+    if let Err(err) = print_targets(w, &targets, to_trash || recursive) {
+        print_error(format!("无法写入输出: {err}"));
+        return ExitCode::GeneralError;
+    }
+    if targets.is_empty() {
+        return if refused {
+            ExitCode::GeneralError
+        } else {
+            ExitCode::Success
+        };
+    }
+
+    if dry_run && !confirm(&format!("确定将以上 {} 个条目{}吗？", targets.len(), verb)) {
+        eprintln!(
+            "{} 个条目将被{}。以上只是预览，没有修改任何文件，使用 {} --yes 执行",
+            targets.len(),
+            verb,
+            option
+        );
+        return if refused {
+            ExitCode::GeneralError
+        } else {
+            ExitCode::Success
+        };
+    }
+
+    targets.sort_by_key(|(path, _)| Reverse(path.components().count()));
+    let mut failed = refused;
+    for (path, is_dir) in targets {
+        let result = if to_trash {
+            trash::trash(&path)
+        } else if is_dir && recursive {
+            fs::remove_dir_all(&path)
+        } else if is_dir {
+            fs::remove_dir(&path)
+        } else {
+            fs::remove_file(&path)
+        };
+        match result {
+            Ok(()) => {}
+            //同时匹配了目录和其中的条目时，条目可能已经随目录一起删除了
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) if err.kind() == io::ErrorKind::DirectoryNotEmpty => {
+                print_error(format!(
+                    "无法删除'{}': 目录中还有未匹配的条目，使用 --recursive 连同其中的全部内容一起删除",
+                    path.display()
+                ));
+                failed = true;
+            }
+            Err(err) => {
+                print_error(format!("无法将'{}'{}: {}", path.display(), verb, err));
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        ExitCode::GeneralError
+    } else {
+        ExitCode::Success
+    }
+}
+
+//去掉位于另一个目标目录中的条目
+fn remove_nested(targets: &mut Vec<(PathBuf, bool)>) {
+    let dirs: HashSet<PathBuf> = targets
+        .iter()
+        .filter(|(_, is_dir)| *is_dir)
+        .map(|(path, _)| path.clone())
+        .collect();
+    targets.retain(|(path, _)| !path.ancestors().skip(1).any(|dir| dirs.contains(dir)));
+}
+
+//目录连同其中的内容一起删除时，目录后面加上路径分隔符作为提醒
+fn print_targets<W: Write>(
+    w: &mut W,
+    targets: &[(PathBuf, bool)],
+    with_contents: bool,
+) -> io::Result<()> {
+    for (path, is_dir) in targets {
+        if *is_dir && with_contents {
+            writeln!(w, "{}{}", path.to_string_lossy(), std::path::MAIN_SEPARATOR)?;
+        } else {
+            writeln!(w, "{}", path.to_string_lossy())?;
+        }
+    }
+    w.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{create_tree, list_tree, search};
+
+    fn delete(entries: Vec<DirEntry>, recursive: bool) -> ExitCode {
+        run(
+            &mut Vec::new(),
+            entries,
+            &Config::default(),
+            false,
+            false,
+            recursive,
+        )
+    }
+
+    #[test]
+    fn refuses_search_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("logs");
+        create_tree(&root, &["a.log"]);
+
+        let config = Config {
+            include_roots: true,
+            ..Default::default()
+        };
+        let entries = search(&root, "", config);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(delete(entries, true), ExitCode::GeneralError);
+        assert!(root.is_dir());
+        assert_eq!(list_tree(&root), Vec::<String>::new());
+    }
+
+    #[test]
+    fn deletes_children_before_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        create_tree(root, &["build/out/a.o", "build/b.o", "src/main.c"]);
+
+        let entries = search(root, r"^(build|out|.*\.o)$", Config::default());
+        assert_eq!(delete(entries, false), ExitCode::Success);
+        assert_eq!(list_tree(root), ["src/", "src/main.c"]);
+    }
+
+    #[test]
+    fn keeps_directory_with_unmatched_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        create_tree(
+            root,
+            &["build/a.o", "build/.keep", "build/notes.txt", "c.o"],
+        );
+
+        //.keep 和 notes.txt 没有匹配，build 不能被删除，其余条目照常删除
+        let entries = search(root, r"^(build|.*\.o)$", Config::default());
+        assert_eq!(delete(entries, false), ExitCode::GeneralError);
+        assert_eq!(
+            list_tree(root),
+            ["build/", "build/.keep", "build/notes.txt"]
+        );
+    }
+
+    #[test]
+    fn recursive_deletes_directory_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        create_tree(root, &["build/a.o", "build/.keep", "src/main.c"]);
+
+        let entries = search(root, r"^build$", Config::default());
+        assert_eq!(delete(entries, true), ExitCode::Success);
+        assert_eq!(list_tree(root), ["src/", "src/main.c"]);
+    }
+}
@@ -0,0 +1,132 @@
+use std::{
+    ffi::OsString,
+    io::Write,
+    path::PathBuf,
+    process::{Command, ExitStatus},
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+    config::Config, dir_entry::DirEntry, error::print_error, error_codes::ExitCode,
+    fmt::FormatTemplate,
+};
+
+//一次命令行中结果路径的总长度上限，远小于常见系统的 ARG_MAX，给环境变量留出空间
+const MAX_BATCH_BYTES: usize = 0x10000;
+
+/*
+对结果执行命令（find-compat 的 -exec）：
+1.每个条目执行一次（-exec ... ;）：每个参数都是格式模板，{} 等占位符替换为该条目的信息。
+2.批量执行（-exec ... {} +）：{} 必须是最后一个参数，替换为尽可能多的结果路径，
+  路径太多时分成几批执行。
+命令的输出直接写到标准输出。某次执行失败时继续执行其余的，最后以非零退出码结束。
+*/
+#[derive(Debug)]
+pub struct Exec {
+    program: FormatTemplate,
+    args: Vec<FormatTemplate>,
+    batch: bool,
+}
+
+impl Exec {
+    pub fn new(command: &[&str], batch: bool) -> Result<Self> {
+        let (program, mut args) = command
+            .split_first()
+            .ok_or_else(|| anyhow!("-exec 缺少要执行的命令"))?;
+        if batch {
+            //最后一个 {} 由结果路径代替，不作为模板
+            args = match args.split_last() {
+                Some((&"{}", rest)) => rest,
+                _ => return Err(anyhow!("-exec ... + 的最后一个参数必须是 {{}}")),
+            };
+        }
+        let parse = |arg: &&str| FormatTemplate::parse(arg);
+        let program = parse(program)?;
+        let args: Vec<_> = args.iter().map(parse).collect::<Result<_>>()?;
+        if batch && (program.has_tokens() || args.iter().any(FormatTemplate::has_tokens)) {
+            return Err(anyhow!("-exec ... + 中只有最后一个参数可以是 {{}}"));
+        }
+        Ok(Self {
+            program,
+            args,
+            batch,
+        })
+    }
+
+    pub fn run<W: Write>(
+        &self,
+        w: &mut W,
+        mut entries: Vec<DirEntry>,
+        config: &Config,
+    ) -> ExitCode {
+        //命令的输出与已经写入 w 的内容按顺序出现
+        if w.flush().is_err() {
+            return ExitCode::GeneralError;
+        }
+        entries.sort();
+
+        let mut failed = false;
+        if self.batch {
+            //批量执行时命令和其他参数都是固定的文本，与条目无关
+            let Some(first) = entries.first() else {
+                return ExitCode::Success;
+            };
+            let program = self.program.generate(first.path(), first, None);
+            let args: Vec<OsString> = self
+                .args
+                .iter()
+                .map(|arg| arg.generate(first.path(), first, None))
+                .collect();
+            let paths: Vec<PathBuf> = entries
+                .into_iter()
+                .map(|entry| entry.into_stripped_path(config))
+                .collect();
+            for batch in batches(&paths) {
+                let status = Command::new(&program).args(&args).args(batch).status();
+                failed |= !check_status(&program, status);
+            }
+        } else {
+            for entry in &entries {
+                let path = entry.stripped_path(config);
+                let program = self.program.generate(path, entry, None);
+                let status = Command::new(&program)
+                    .args(self.args.iter().map(|arg| arg.generate(path, entry, None)))
+                    .status();
+                failed |= !check_status(&program, status);
+            }
+        }
+
+        if failed {
+            ExitCode::GeneralError
+        } else {
+            ExitCode::Success
+        }
+    }
+}
+
+//按路径总长度把结果分成若干批，每批至少一个路径
+fn batches(paths: &[PathBuf]) -> Vec<&[PathBuf]> {
+    let mut batches = Vec::new();
+    let (mut start, mut bytes) = (0, 0);
+    for (i, path) in paths.iter().enumerate() {
+        let len = path.as_os_str().len() + 1;
+        if i > start && bytes + len > MAX_BATCH_BYTES {
+            batches.push(&paths[start..i]);
+            (start, bytes) = (i, 0);
+        }
+        bytes += len;
+    }
+    batches.push(&paths[start..]);
+    batches
+}
+
+fn check_status(program: &OsString, status: std::io::Result<ExitStatus>) -> bool {
+    match status {
+        Ok(status) => status.success(),
+        Err(err) => {
+            print_error(format!("无法执行'{}': {}", program.to_string_lossy(), err));
+            false
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use std::io::{self, BufRead, IsTerminal, Write};
+
+mod archive;
+mod delete;
+mod exec;
+mod rename;
+mod restore;
+mod transfer;
+
+pub use self::archive::{ArchiveFormat, Archiver};
+pub use self::exec::Exec;
+pub use self::rename::Renamer;
+pub use self::restore::trash_restore;
+pub use self::transfer::{Collision, Transfer, TransferMode};
+
+use crate::{config::Config, dir_entry::DirEntry, error_codes::ExitCode};
+
+/*
+对全部搜索结果执行的操作。这些操作需要先拿到所有结果，检查无误后才会修改文件系统：
+1.Rename：按搜索模式和替换字符串批量重命名（--rename）。
+2.Delete：删除匹配的条目（--delete），recursive 为 true 时连同匹配目录中的全部内容（--recursive）。
+3.Trash：把匹配的条目移到回收站（--trash）。
+4.Transfer：把匹配的条目复制、移动或链接到目标目录（--copy-to、--move-to、--link-to）。
+  移动和覆盖已有的条目同样会改动原来的文件，所以默认也只预览。
+5.Archive：把匹配的条目写入 tar、tar.gz 或 zip 归档（--archive），只写入新文件，直接执行。
+6.Exec：对匹配的条目执行命令（find-compat 的 -exec），直接执行。
+*/
+#[derive(Debug)]
+pub enum Action {
+    Rename(Renamer),
+    Delete { recursive: bool },
+    Trash,
+    Transfer(Transfer),
+    Archive(Archiver),
+    Exec(Exec),
+}
+
+impl Action {
+    ///执行操作。dry_run 为 true 时只输出将要进行的修改，不改动任何文件。
+    pub fn run<W: Write>(
+        &self,
+        w: &mut W,
+        entries: Vec<DirEntry>,
+        config: &Config,
+        dry_run: bool,
+    ) -> ExitCode {
+        match self {
+            Action::Rename(renamer) => renamer.run(w, entries, config, dry_run),
+            Action::Delete { recursive } => {
+                delete::run(w, entries, config, dry_run, false, *recursive)
+            }
+            Action::Trash => delete::run(w, entries, config, dry_run, true, false),
+            Action::Transfer(transfer) => transfer.run(w, entries, config, dry_run),
+            Action::Archive(archiver) => archiver.run(w, entries, config),
+            Action::Exec(exec) => exec.run(w, entries, config),
+        }
+    }
+}
+
+///在终端中询问用户是否继续，只有回答 y 或 yes 时返回 true。
+/// 标准输入或标准错误不是终端时不询问，直接返回 false。
+pub fn confirm(prompt: &str) -> bool {
+    if !io::stdin().is_terminal() || !io::stderr().is_terminal() {
+        return false;
+    }
+    eprint!("{prompt} [y/N] ");
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
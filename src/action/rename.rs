@@ -0,0 +1,365 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsString,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+use regex::bytes::Regex;
+
+use crate::{config::Config, dir_entry::DirEntry, error::print_error, error_codes::ExitCode};
+
+///一次重命名：from 和 to 位于同一个目录中
+#[derive(Debug)]
+struct Rename {
+    from: PathBuf,
+    to: PathBuf,
+}
+
+/*
+批量重命名（--rename）。搜索模式在文件名中的第一处匹配会被替换字符串替换，
+替换字符串中可以用 $1、${name} 引用捕获组。
+执行前会检查所有的重命名：
+1.两个文件不能被重命名为同一个名称。
+2.新名称不能与已有的、不会被重命名走的文件冲突。
+新名称恰好是另一个待重命名文件的旧名称时（例如 a→b、b→a），先把这一批文件改为临时名称，再改为新名称。
+*/
+#[derive(Debug)]
+pub struct Renamer {
+    regex: Regex,
+    replacement: String,
+}
+
+impl Renamer {
+    pub fn new(regex: Regex, replacement: String) -> Self {
+        Self { regex, replacement }
+    }
+
+    pub fn run<W: Write>(
+        &self,
+        w: &mut W,
+        entries: Vec<DirEntry>,
+        config: &Config,
+        dry_run: bool,
+    ) -> ExitCode {
+        let renames = match self.plan(entries, config) {
+            Ok(renames) => renames,
+            Err(errors) => {
+                for err in errors {
+                    print_error(err);
+                }
+                print_error("存在冲突，没有重命名任何文件");
+                return ExitCode::GeneralError;
+            }
+        };
+
+        if let Err(err) = print_plan(w, &renames) {
+            print_error(format!("无法写入输出: {err}"));
+            return ExitCode::GeneralError;
+        }
+
+        if dry_run {
+            eprintln!(
+                "{} 个条目将被重命名。以上只是预览，没有修改任何文件，使用 --yes 执行重命名",
+                renames.len()
+            );
+            return ExitCode::Success;
+        }
+
+        execute(renames)
+    }
+
+    //计算每个条目的新名称并检查冲突，有冲突时返回所有的错误信息
+    fn plan(&self, entries: Vec<DirEntry>, config: &Config) -> Result<Vec<Rename>, Vec<String>> {
+        let mut renames = Vec::new();
+        let mut errors = Vec::new();
+
+        for entry in entries {
+            let from = entry.into_stripped_path(config);
+            match self.new_name(&from) {
+                Ok(Some(name)) => {
+                    let to = from.with_file_name(name);
+                    renames.push(Rename { from, to });
+                }
+                Ok(None) => {}
+                Err(err) => errors.push(err),
+            }
+        }
+        renames.sort_by(|a, b| a.from.cmp(&b.from));
+
+        let sources: HashSet<&Path> = renames.iter().map(|r| r.from.as_path()).collect();
+        let mut by_target: HashMap<&Path, Vec<&Path>> = HashMap::new();
+        for rename in &renames {
+            by_target.entry(&rename.to).or_default().push(&rename.from);
+        }
+
+        for rename in &renames {
+            let sources_of_target = &by_target[rename.to.as_path()];
+            if sources_of_target.len() > 1 {
+                //同一组冲突只报告一次
+                if sources_of_target[0] == rename.from {
+                    errors.push(format!(
+                        "{} 都会被重命名为'{}'",
+                        sources_of_target
+                            .iter()
+                            .map(|path| format!("'{}'", path.display()))
+                            .collect::<Vec<_>>()
+                            .join("、"),
+                        rename.to.display()
+                    ));
+                }
+            } else if !sources.contains(rename.to.as_path()) && rename.to.symlink_metadata().is_ok()
+            {
+                errors.push(format!(
+                    "无法将'{}'重命名为'{}': 目标已存在",
+                    rename.from.display(),
+                    rename.to.display()
+                ));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(renames)
+        } else {
+            Err(errors)
+        }
+    }
+
+    //替换后的文件名，与原来相同时返回 None
+    fn new_name(&self, path: &Path) -> Result<Option<OsString>, String> {
+        let Some(name) = path.file_name() else {
+            return Ok(None);
+        };
+        let Some(name) = name.to_str() else {
+            return Err(format!(
+                "无法重命名'{}': 文件名不是有效的 UTF-8",
+                path.display()
+            ));
+        };
+
+        let replaced = self
+            .regex
+            .replace(name.as_bytes(), self.replacement.as_bytes());
+        if *replaced == *name.as_bytes() {
+            return Ok(None);
+        }
+        let new_name = String::from_utf8(replaced.into_owned())
+            .map_err(|_| format!("无法重命名'{}': 新名称不是有效的 UTF-8", path.display()))?;
+
+        if new_name.is_empty()
+            || new_name == "."
+            || new_name == ".."
+            || new_name.contains(std::path::is_separator)
+        {
+            return Err(format!(
+                "无法将'{}'重命名为'{}': 新名称不是有效的文件名",
+                path.display(),
+                new_name
+            ));
+        }
+        Ok(Some(OsString::from(new_name)))
+    }
+}
+
+fn print_plan<W: Write>(w: &mut W, renames: &[Rename]) -> io::Result<()> {
+    for rename in renames {
+        writeln!(
+            w,
+            "{} -> {}",
+            rename.from.to_string_lossy(),
+            rename.to.to_string_lossy()
+        )?;
+    }
+    w.flush()
+}
+
+/*
+执行重命名。按深度从深到浅分批进行，这样重命名目录之前，其中的条目已经改好了名称，
+而同一批条目的父目录都还没有被重命名，路径保持有效。
+*/
+fn execute(mut renames: Vec<Rename>) -> ExitCode {
+    renames.sort_by_key(|rename| std::cmp::Reverse(rename.from.components().count()));
+
+    let mut failed = false;
+    for batch in renames.chunk_by(|a, b| a.from.components().count() == b.from.components().count())
+    {
+        let sources: HashSet<&Path> = batch.iter().map(|r| r.from.as_path()).collect();
+        let chained = batch.iter().any(|r| sources.contains(r.to.as_path()));
+
+        let result = if chained {
+            rename_via_temporary(batch)
+        } else {
+            rename_directly(batch)
+        };
+        failed |= result.is_err();
+    }
+
+    if failed {
+        ExitCode::GeneralError
+    } else {
+        ExitCode::Success
+    }
+}
+
+fn rename_directly(batch: &[Rename]) -> Result<(), ()> {
+    let mut result = Ok(());
+    for rename in batch {
+        //检查之后目标可能又被创建了，fs::rename 会直接覆盖它，所以再检查一次
+        if rename.to.symlink_metadata().is_ok() {
+            print_error(format!(
+                "无法将'{}'重命名为'{}': 目标已存在",
+                rename.from.display(),
+                rename.to.display()
+            ));
+            result = Err(());
+            continue;
+        }
+        if let Err(err) = fs::rename(&rename.from, &rename.to) {
+            print_error(format!(
+                "无法将'{}'重命名为'{}': {}",
+                rename.from.display(),
+                rename.to.display(),
+                err
+            ));
+            result = Err(());
+        }
+    }
+    result
+}
+
+//先把这一批全部改为临时名称，再改为新名称，这样 a→b、b→a 这样的循环也能正确完成
+fn rename_via_temporary(batch: &[Rename]) -> Result<(), ()> {
+    let mut temporaries: Vec<PathBuf> = Vec::with_capacity(batch.len());
+    for (i, rename) in batch.iter().enumerate() {
+        let temporary = temporary_name(&rename.from, i);
+        if let Err(err) = fs::rename(&rename.from, &temporary) {
+            print_error(format!(
+                "无法将'{}'重命名为临时名称: {}",
+                rename.from.display(),
+                err
+            ));
+            //恢复已经改为临时名称的条目，不留下改了一半的结果
+            for (rename, temporary) in batch.iter().zip(&temporaries) {
+                if let Err(err) = fs::rename(temporary, &rename.from) {
+                    print_error(format!(
+                        "无法将'{}'恢复为'{}': {}",
+                        temporary.display(),
+                        rename.from.display(),
+                        err
+                    ));
+                }
+            }
+            return Err(());
+        }
+        temporaries.push(temporary);
+    }
+
+    let mut result = Ok(());
+    for (rename, temporary) in batch.iter().zip(&temporaries) {
+        if let Err(err) = fs::rename(temporary, &rename.to) {
+            print_error(format!(
+                "无法将'{}'重命名为'{}': {}，原文件'{}'现在位于'{}'",
+                temporary.display(),
+                rename.to.display(),
+                err,
+                rename.from.display(),
+                temporary.display()
+            ));
+            result = Err(());
+        }
+    }
+    result
+}
+
+//与 path 位于同一目录中、不存在的临时名称
+fn temporary_name(path: &Path, index: usize) -> PathBuf {
+    let mut attempt = 0;
+    loop {
+        let name = format!(".file-find-rename-{}-{}-{}", process::id(), index, attempt);
+        let temporary = path.with_file_name(name);
+        if temporary.symlink_metadata().is_err() {
+            return temporary;
+        }
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{create_tree, list_tree, search};
+
+    //选出名称匹配 pattern 的条目，把其中 regex 的第一处匹配替换为 replacement
+    fn rename(root: &Path, pattern: &str, regex: &str, replacement: &str) -> ExitCode {
+        let entries = search(root, pattern, Config::default());
+        Renamer::new(Regex::new(regex).unwrap(), replacement.to_owned()).run(
+            &mut Vec::new(),
+            entries,
+            &Config::default(),
+            false,
+        )
+    }
+
+    fn content(path: PathBuf) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn swaps_names_in_a_cycle() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        create_tree(root, &["ab", "ba"]);
+
+        assert_eq!(
+            rename(root, "^(ab|ba)$", "^(.)(.)$", "$2$1"),
+            ExitCode::Success
+        );
+        assert_eq!(list_tree(root), ["ab", "ba"]);
+        assert_eq!(content(root.join("ab")), "ba");
+        assert_eq!(content(root.join("ba")), "ab");
+    }
+
+    #[test]
+    fn renames_a_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        create_tree(root, &["f", "f.1"]);
+
+        //f→f.1，f.1→f.1.1
+        assert_eq!(rename(root, "^f", "$", ".1"), ExitCode::Success);
+        assert_eq!(list_tree(root), ["f.1", "f.1.1"]);
+        assert_eq!(content(root.join("f.1")), "f");
+        assert_eq!(content(root.join("f.1.1")), "f.1");
+    }
+
+    #[test]
+    fn refuses_to_overwrite_an_unmatched_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        create_tree(root, &["a.txt", "b.txt", "b.log"]);
+
+        //b.log 不在搜索结果中，a.txt 不能被重命名为它；有冲突时整批都不执行
+        assert_eq!(
+            rename(root, r"\.txt$", r"^a\.txt$", "b.log"),
+            ExitCode::GeneralError
+        );
+        assert_eq!(
+            rename(root, r"\.txt$", r"\.txt$", ".log"),
+            ExitCode::GeneralError
+        );
+        assert_eq!(list_tree(root), ["a.txt", "b.log", "b.txt"]);
+        assert_eq!(content(root.join("b.log")), "b.log");
+    }
+
+    #[test]
+    fn refuses_two_sources_with_the_same_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        create_tree(root, &["a1", "a2"]);
+
+        assert_eq!(rename(root, "^a", r"\d", ""), ExitCode::GeneralError);
+        assert_eq!(list_tree(root), ["a1", "a2"]);
+    }
+}
@@ -0,0 +1,71 @@
+use std::io::{self, Write};
+
+use regex::bytes::Regex;
+
+use crate::{error::print_error, error_codes::ExitCode, trash};
+
+use super::confirm;
+
+/*
+从回收站恢复条目（trash-restore 子命令）。
+1.模式与条目原来的完整路径匹配。
+2.默认只列出将被恢复的条目；指定了 --yes，或者在终端中确认后才会真正恢复。
+3.同一个路径被多次删除时，恢复最近删除的那一个，其余的会因为原位置已存在而报错。
+*/
+pub fn trash_restore<W: Write>(w: &mut W, pattern: &Regex, dry_run: bool) -> ExitCode {
+    let mut items: Vec<_> = trash::trashed_items()
+        .into_iter()
+        .filter(|item| pattern.is_match(item.original_path.as_os_str().as_encoded_bytes()))
+        .collect();
+    items.sort_by(|a, b| {
+        a.original_path
+            .cmp(&b.original_path)
+            .then_with(|| b.deletion_date.cmp(&a.deletion_date))
+    });
+
+    if let Err(err) = print_items(w, &items) {
+        print_error(format!("无法写入输出: {err}"));
+        return ExitCode::GeneralError;
+    }
+    if items.is_empty() {
+        return ExitCode::HasResult(false);
+    }
+
+    if dry_run && !confirm(&format!("确定恢复以上 {} 个条目吗？", items.len())) {
+        eprintln!(
+            "{} 个条目将被恢复。以上只是预览，没有修改任何文件，使用 --yes 执行恢复",
+            items.len()
+        );
+        return ExitCode::Success;
+    }
+
+    let mut failed = false;
+    for item in &items {
+        if let Err(err) = item.restore() {
+            print_error(format!(
+                "无法恢复'{}': {}",
+                item.original_path.display(),
+                err
+            ));
+            failed = true;
+        }
+    }
+
+    if failed {
+        ExitCode::GeneralError
+    } else {
+        ExitCode::Success
+    }
+}
+
+fn print_items<W: Write>(w: &mut W, items: &[trash::TrashedItem]) -> io::Result<()> {
+    for item in items {
+        writeln!(
+            w,
+            "{}\t{}",
+            item.deletion_date,
+            item.original_path.to_string_lossy()
+        )?;
+    }
+    w.flush()
+}
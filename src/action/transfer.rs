@@ -0,0 +1,412 @@
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    fs::{self, File, FileTimes, Metadata},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config::Config,
+    dir_entry::DirEntry,
+    error::{print_error, print_warning},
+    error_codes::ExitCode,
+};
+
+/*
+--copy-to、--move-to、--link-to 的操作方式：
+1.Copy：复制，保留修改时间和权限。
+2.Move：移动，目标位于另一个文件系统时改为复制后删除源文件。
+3.Link：在目标位置创建指向源文件绝对路径的符号链接。
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransferMode {
+    Copy,
+    Move,
+    Link,
+}
+
+/*
+目标位置已经存在同名条目时的处理方式（--on-collision）：
+1.Error：报告错误并跳过该条目，最后以非零退出码结束。
+2.Skip：安静地跳过该条目。
+3.Overwrite：删除已有的条目后再放入。
+4.Rename：在文件名和扩展名之间加上编号，例如 a.1.txt。
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Collision {
+    #[default]
+    Error,
+    Skip,
+    Overwrite,
+    Rename,
+}
+
+/*
+把匹配的条目复制、移动或链接到目标目录中。
+默认在目标目录中重建条目相对于搜索路径的目录结构，flatten 为 true 时直接放在目标目录下。
+位于另一个匹配目录中的条目随目录一起处理，不单独处理；目标就是条目自己时跳过。
+默认只输出将要进行的操作，dry_run 为 false 时才会执行。
+*/
+#[derive(Debug)]
+pub struct Transfer {
+    mode: TransferMode,
+    destination: PathBuf,
+    flatten: bool,
+    on_collision: Collision,
+}
+
+impl Transfer {
+    pub fn new(
+        mode: TransferMode,
+        destination: PathBuf,
+        flatten: bool,
+        on_collision: Collision,
+    ) -> Self {
+        Self {
+            mode,
+            destination,
+            flatten,
+            on_collision,
+        }
+    }
+
+    pub fn run<W: Write>(
+        &self,
+        w: &mut W,
+        mut entries: Vec<DirEntry>,
+        config: &Config,
+        dry_run: bool,
+    ) -> ExitCode {
+        entries.sort();
+        let dirs: HashSet<PathBuf> = entries
+            .iter()
+            .filter(|entry| entry.file_type().is_some_and(|ft| ft.is_dir()))
+            .map(|entry| entry.path().to_path_buf())
+            .collect();
+
+        if !dry_run {
+            if let Err(err) = fs::create_dir_all(&self.destination) {
+                print_error(format!(
+                    "无法创建目标目录'{}': {}",
+                    self.destination.display(),
+                    err
+                ));
+                return ExitCode::GeneralError;
+            }
+        }
+        //预览时目标目录可能还不存在
+        let canonical_destination = self
+            .destination
+            .canonicalize()
+            .or_else(|_| std::path::absolute(&self.destination))
+            .ok();
+
+        let mut failed = false;
+        let mut count = 0;
+        //本次已经安排的目标位置，预览时它们还不存在，也要当作已经被占用
+        let mut taken = HashSet::new();
+        for entry in &entries {
+            let source = entry.path();
+            if source.ancestors().skip(1).any(|dir| dirs.contains(dir)) {
+                continue;
+            }
+            //复制目录到它自己里面会无限递归
+            if self.mode != TransferMode::Link
+                && canonical_destination
+                    .as_deref()
+                    .zip(canonical_entry(source))
+                    .is_some_and(|(dest, source)| dest.starts_with(source))
+            {
+                print_error(format!("无法处理'{}': 目标目录位于其中", source.display()));
+                failed = true;
+                continue;
+            }
+
+            let Some(target) = self.target_of(entry) else {
+                continue;
+            };
+            //目标就是条目自己时（例如目标目录就是搜索路径），按冲突策略处理会先删除条目本身
+            if canonical_entry(&target)
+                .is_some_and(|target| Some(target) == canonical_entry(source))
+            {
+                print_warning(format!("跳过'{}': 目标就是它自己", source.display()));
+                continue;
+            }
+
+            let result = self
+                .plan(&target, &mut taken)
+                .and_then(|target| match target {
+                    Some(target) if !dry_run => {
+                        self.transfer(source, &target).map(|_| Some(target))
+                    }
+                    target => Ok(target),
+                });
+            match result {
+                Ok(Some(target)) => {
+                    count += 1;
+                    let written = writeln!(
+                        w,
+                        "{} -> {}",
+                        entry.stripped_path(config).to_string_lossy(),
+                        target.to_string_lossy()
+                    );
+                    if let Err(err) = written {
+                        print_error(format!("无法写入输出: {err}"));
+                        return ExitCode::GeneralError;
+                    }
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    print_error(format!(
+                        "无法将'{}'{}到'{}': {}",
+                        source.display(),
+                        self.verb(),
+                        target.display(),
+                        err
+                    ));
+                    failed = true;
+                }
+            }
+        }
+
+        if dry_run {
+            eprintln!(
+                "{} 个条目将被{}。以上只是预览，没有修改任何文件，使用 --yes 执行",
+                count,
+                self.verb()
+            );
+        }
+        if failed {
+            ExitCode::GeneralError
+        } else {
+            ExitCode::Success
+        }
+    }
+
+    fn verb(&self) -> &'static str {
+        match self.mode {
+            TransferMode::Copy => "复制",
+            TransferMode::Move => "移动",
+            TransferMode::Link => "链接",
+        }
+    }
+
+    //条目在目标目录中的位置
+    fn target_of(&self, entry: &DirEntry) -> Option<PathBuf> {
+        let path = entry.path();
+        let relative = match entry.root() {
+            Some(root) if !self.flatten => path.strip_prefix(root).ok()?,
+            _ => Path::new(path.file_name()?),
+        };
+        Some(self.destination.join(relative))
+    }
+
+    //按冲突策略决定条目实际放入的位置，不修改文件系统。按冲突策略跳过时返回 Ok(None)
+    fn plan(&self, target: &Path, taken: &mut HashSet<PathBuf>) -> io::Result<Option<PathBuf>> {
+        let occupied = |path: &Path| path.symlink_metadata().is_ok() || taken.contains(path);
+        let mut target = target.to_path_buf();
+        if occupied(&target) {
+            match self.on_collision {
+                Collision::Error => {
+                    return Err(io::Error::new(io::ErrorKind::AlreadyExists, "目标已存在"))
+                }
+                Collision::Skip => return Ok(None),
+                Collision::Overwrite => {}
+                Collision::Rename => target = numbered(&target, occupied),
+            }
+        }
+        taken.insert(target.clone());
+        Ok(Some(target))
+    }
+
+    //把条目放入 plan 决定的位置，已有的条目按冲突策略被覆盖
+    fn transfer(&self, source: &Path, target: &Path) -> io::Result<()> {
+        if target.symlink_metadata().is_ok() {
+            remove(target)?;
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        match self.mode {
+            TransferMode::Copy => copy(source, target),
+            TransferMode::Move => match fs::rename(source, target) {
+                Ok(()) => Ok(()),
+                Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+                    copy(source, target)?;
+                    remove(source)
+                }
+                Err(err) => Err(err),
+            },
+            TransferMode::Link => symlink(&std::path::absolute(source)?, target),
+        }
+    }
+}
+
+//条目本身（而不是符号链接指向的目标）的规范路径：父目录规范化后加上文件名。不存在时返回 None
+fn canonical_entry(path: &Path) -> Option<PathBuf> {
+    path.symlink_metadata().ok()?;
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => {
+            let parent = if parent.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                parent
+            };
+            Some(parent.canonicalize().ok()?.join(name))
+        }
+        _ => path.canonicalize().ok(),
+    }
+}
+
+//在文件名和扩展名之间加上编号，找到第一个没有被占用的名称
+fn numbered(path: &Path, occupied: impl Fn(&Path) -> bool) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default();
+    let extension = path.extension();
+    for n in 1.. {
+        let mut name = OsString::from(stem);
+        name.push(format!(".{n}"));
+        if let Some(extension) = extension {
+            name.push(".");
+            name.push(extension);
+        }
+        let candidate = path.with_file_name(name);
+        if !occupied(&candidate) {
+            return candidate;
+        }
+    }
+    unreachable!()
+}
+
+fn remove(path: &Path) -> io::Result<()> {
+    if path.symlink_metadata()?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+//复制文件、符号链接或整个目录，保留修改时间和权限
+fn copy(source: &Path, target: &Path) -> io::Result<()> {
+    let metadata = source.symlink_metadata()?;
+    let file_type = metadata.file_type();
+    if file_type.is_symlink() {
+        return symlink(&fs::read_link(source)?, target);
+    }
+
+    if file_type.is_dir() {
+        fs::create_dir(target)?;
+        for child in fs::read_dir(source)? {
+            let child = child?;
+            copy(&child.path(), &target.join(child.file_name()))?;
+        }
+        fs::set_permissions(target, metadata.permissions())?;
+    } else {
+        fs::copy(source, target)?;
+    }
+    preserve_times(target, &metadata)
+}
+
+//目录的修改时间要在复制完其中的内容之后再设置，否则会被覆盖。
+// 复制的文件可能是只读的，所以以只读方式打开来设置时间。
+fn preserve_times(target: &Path, metadata: &Metadata) -> io::Result<()> {
+    let mut times = FileTimes::new().set_modified(metadata.modified()?);
+    if let Ok(accessed) = metadata.accessed() {
+        times = times.set_accessed(accessed);
+    }
+    File::open(target)?.set_times(times)
+}
+
+#[cfg(unix)]
+fn symlink(original: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(original, link)
+}
+
+#[cfg(windows)]
+fn symlink(original: &Path, link: &Path) -> io::Result<()> {
+    if original.is_dir() {
+        std::os::windows::fs::symlink_dir(original, link)
+    } else {
+        std::os::windows::fs::symlink_file(original, link)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{create_tree, list_tree, search};
+
+    fn transfer(
+        mode: TransferMode,
+        dest: &Path,
+        flatten: bool,
+        on_collision: Collision,
+    ) -> Transfer {
+        Transfer::new(mode, dest.to_path_buf(), flatten, on_collision)
+    }
+
+    #[test]
+    fn target_is_source_is_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        create_tree(root, &["a/x.txt", "y.txt"]);
+
+        for mode in [TransferMode::Move, TransferMode::Copy, TransferMode::Link] {
+            let entries = search(root, r"\.txt$", Config::default());
+            let transfer = transfer(mode, root, false, Collision::Overwrite);
+            let mut out = Vec::new();
+            transfer.run(&mut out, entries, &Config::default(), false);
+            assert!(out.is_empty());
+            assert_eq!(list_tree(root), ["a/", "a/x.txt", "y.txt"]);
+            assert_eq!(fs::read_to_string(root.join("a/x.txt")).unwrap(), "a/x.txt");
+        }
+    }
+
+    #[test]
+    fn flatten_into_root_keeps_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        create_tree(root, &["y.txt"]);
+
+        let entries = search(root, r"^y\.txt$", Config::default());
+        let transfer = transfer(TransferMode::Move, root, true, Collision::Overwrite);
+        transfer.run(&mut Vec::new(), entries, &Config::default(), false);
+        assert_eq!(fs::read_to_string(root.join("y.txt")).unwrap(), "y.txt");
+    }
+
+    #[test]
+    fn dry_run_plans_without_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("src");
+        let dest = dir.path().join("dest");
+        create_tree(&root, &["a/x.txt", "b/x.txt"]);
+
+        let entries = search(&root, r"\.txt$", Config::default());
+        let transfer = transfer(TransferMode::Move, &dest, true, Collision::Rename);
+        let mut out = Vec::new();
+        let exit_code = transfer.run(&mut out, entries, &Config::default(), true);
+        assert_eq!(exit_code, ExitCode::Success);
+
+        //两个同名文件在预览中也得到不同的名称
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(&format!("{}", dest.join("x.txt").display())));
+        assert!(out.contains(&format!("{}", dest.join("x.1.txt").display())));
+        assert!(!dest.exists());
+        assert_eq!(list_tree(&root), ["a/", "a/x.txt", "b/", "b/x.txt"]);
+    }
+
+    #[test]
+    fn overwrite_replaces_existing_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("src");
+        let dest = dir.path().join("dest");
+        create_tree(&root, &["x.txt"]);
+        create_tree(&dest, &["x.txt/"]);
+
+        let entries = search(&root, r"\.txt$", Config::default());
+        let transfer = transfer(TransferMode::Copy, &dest, false, Collision::Overwrite);
+        transfer.run(&mut Vec::new(), entries, &Config::default(), false);
+        assert_eq!(fs::read_to_string(dest.join("x.txt")).unwrap(), "x.txt");
+    }
+}
@@ -0,0 +1,348 @@
+use std::{
+    env,
+    ffi::OsStr,
+    io::{IsTerminal, Write},
+    path::{Path, PathBuf, MAIN_SEPARATOR},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, Result};
+use clap::Parser; // 引入派生宏
+use lscolors::LsColors;
+use regex::bytes::Regex;
+
+use crate::action::{Action, Archiver, Renamer, Transfer, TransferMode};
+use crate::cli::{Command, IndexCommand, Opts};
+use crate::config::Config;
+use crate::error::print_warning;
+use crate::error_codes::ExitCode;
+use crate::filetypes::FileType;
+#[cfg(unix)]
+use crate::filter::OwnerFilter;
+use crate::filter::{CommandFilter, ContentFilter, FilterExpr, GitFilter, SizeFilter, TimeFilter};
+use crate::fmt::FormatTemplate;
+use crate::index::Index;
+use crate::{action, filesystem, filter, find_compat, index, regex_helper, walk};
+
+///命令行程序的入口：解析命令行参数，执行子命令或者搜索
+pub fn run() -> Result<ExitCode> {
+    //通过名为 find 的链接调用时直接进入 find 兼容模式
+    let mut args = env::args_os();
+    if args
+        .next()
+        .is_some_and(|arg0| Path::new(&arg0).file_stem() == Some(OsStr::new("find")))
+    {
+        let args = args
+            .map(|arg| {
+                arg.into_string()
+                    .map_err(|arg| anyhow!("参数'{}'不是有效的 UTF-8", arg.to_string_lossy()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        return find_compat::run(&args);
+    }
+
+    let opts = Opts::parse(); // 自动解析命令行参数
+
+    if let Some(Command::TrashRestore { ref pattern, yes }) = opts.command {
+        let regex = regex_helper::build_regex(pattern)?;
+        return Ok(action::trash_restore(&mut std::io::stdout(), &regex, !yes));
+    }
+    if let Some(Command::FindCompat { ref args }) = opts.command {
+        return find_compat::run(args);
+    }
+    if let Some(Command::Index { ref command }) = opts.command {
+        return run_index(command);
+    }
+    if let Some(Command::Serve {
+        ref socket,
+        ref roots,
+        hidden,
+        no_ignore,
+    }) = opts.command
+    {
+        #[cfg(target_os = "linux")]
+        return crate::serve::serve(Path::new(socket), roots, hidden, no_ignore);
+        #[cfg(not(target_os = "linux"))]
+        return Err(anyhow!("serve 只支持 Linux"));
+    }
+
+    #[cfg(target_os = "linux")]
+    if let Some(ref socket) = opts.daemon {
+        if let Some(exit_code) = crate::serve::query(Path::new(socket))? {
+            return Ok(exit_code);
+        }
+    }
+
+    search(&opts, std::io::stdout(), |_| {})
+}
+
+///按命令行选项搜索，结果写入 stdout。serve 通过 customize 改用内存中的索引和客户端的颜色设置
+pub fn search<W: Write + Send>(
+    opts: &Opts,
+    stdout: W,
+    customize: impl FnOnce(&mut Config),
+) -> Result<ExitCode> {
+    let search_path = PathBuf::from(&opts.path);
+    if !filesystem::is_existing_directory(&search_path) {
+        return Err(anyhow!("搜索路径'{}'不是一个目录", search_path.display()));
+    }
+
+    //没有子命令时 clap 保证提供了 --pattern
+    let pattern = opts.pattern.as_deref().unwrap_or_default();
+    let case_sensitive = regex_helper::pattern_has_uppercase_char(pattern);
+    let regex = regex_helper::build_regex(pattern)?;
+
+    let mut config = construct_config(opts, &regex, case_sensitive)?;
+    customize(&mut config);
+    walk::scan_to(&[search_path], vec![regex], config, stdout)
+}
+
+//建立或增量更新索引，写入索引文件后输出统计信息
+fn run_index(command: &IndexCommand) -> Result<ExitCode> {
+    let (database, (index, stats)) = match command {
+        IndexCommand::Build {
+            path,
+            hidden,
+            no_ignore,
+            database,
+        } => (
+            database_path(database.as_deref())?,
+            Index::build(Path::new(path), *hidden, *no_ignore)?,
+        ),
+        IndexCommand::Update { database } => {
+            let database = database_path(database.as_deref())?;
+            let update = Index::load(&database)?.update()?;
+            (database, update)
+        }
+    };
+    index.save(&database)?;
+
+    println!(
+        "已索引'{}'中的 {} 个条目（读取 {} 个目录，沿用 {} 个目录）",
+        index.root().display(),
+        stats.entries,
+        stats.dirs_read,
+        stats.dirs_reused
+    );
+    Ok(ExitCode::Success)
+}
+
+fn database_path(database: Option<&str>) -> Result<PathBuf> {
+    database.map_or_else(index::default_database, |database| {
+        Ok(PathBuf::from(database))
+    })
+}
+
+//加载 --from-index 指定的索引，搜索路径必须位于索引的根目录中，并且索引要包含这次搜索会遍历的条目
+fn index_from(opts: &Opts) -> Result<Option<Index>> {
+    let Some(ref database) = opts.from_index else {
+        return Ok(None);
+    };
+    let index = Index::load(&database_path(database.as_deref())?)?;
+    let search_path = Path::new(&opts.path).canonicalize()?;
+    if !search_path.starts_with(index.root()) {
+        return Err(anyhow!(
+            "搜索路径'{}'不在索引的目录'{}'中",
+            opts.path,
+            index.root().display()
+        ));
+    }
+    //索引中没有的条目不会出现在结果中，选项不一致时直接报错，避免静默地漏掉结果
+    if !index.covers(opts.hidden, opts.no_ignore) {
+        return Err(anyhow!(
+            "索引'{}'建立时的 --hidden、--no-ignore 选项与这次搜索不一致，请使用相同的选项或者重新建立索引",
+            index.root().display()
+        ));
+    }
+    if index.is_stale(&search_path) {
+        print_warning("索引可能已经过时，可以运行 file-find index update 更新");
+    }
+    Ok(Some(index))
+}
+
+fn construct_config(opts: &Opts, regex: &Regex, case_sensitive: bool) -> Result<Config> {
+    let path_separator = filesystem::defaault_path_separator();
+    let actual_path_separator = path_separator
+        .clone()
+        .unwrap_or_else(|| MAIN_SEPARATOR.to_string());
+
+    let ls_colors = if std::io::stdout().is_terminal() {
+        Some(LsColors::from_env().unwrap_or_default())
+    } else {
+        None
+    };
+
+    let threads = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+
+    let config = Config {
+        case_sensitive,
+        search_full_path: false,
+        ignore_hidden: !opts.hidden,
+        read_fdignore: !opts.no_ignore,
+        read_vcsignore: !opts.no_ignore,
+        follow_links: false,
+        strip_cwd_prefix: opts.path == ".",
+        base_dir: None,
+        include_roots: false,
+        hyperlink: false,
+        format: opts
+            .format
+            .as_deref()
+            .map(|format| FormatTemplate::parse_with_regex(format, Some(regex)))
+            .transpose()?,
+        path_separator,
+        actual_path_separator,
+        ls_colors,
+        null_separator: false,
+        max_depth: opts.max_depth,
+        threads: opts.threads.unwrap_or(threads).max(1),
+        file_types: opts.filetype.as_deref().map(FileType::from_values),
+        size_constraints: opts
+            .size
+            .iter()
+            .map(|s| SizeFilter::from_string(s))
+            .collect::<Result<_>>()?,
+        time_constraints: time_constraints_from(opts)?,
+        #[cfg(unix)]
+        owner_constraint: opts
+            .owner
+            .as_deref()
+            .map(OwnerFilter::from_string)
+            .transpose()?,
+        git_filter: git_filter_from(opts)?,
+        filter_expr: opts
+            .expr
+            .as_deref()
+            .map(FilterExpr::from_string)
+            .transpose()?,
+        stats: opts.stats,
+        count: opts.count || opts.count_by.is_some(),
+        count_by: opts.count_by,
+        max_results: if opts.quit {
+            Some(1)
+        } else {
+            opts.limit
+                .filter(|&limit| limit > 0)
+                .map(|limit| usize::try_from(limit).unwrap_or(usize::MAX))
+        },
+        quit: opts.quit,
+        content_filter: content_filter_from(opts)?,
+        command_filter: command_filter_from(opts)?,
+        show_line_number: opts.line_number,
+        duplicates: opts.duplicates,
+        search_archives: opts.search_archives,
+        max_archive_depth: opts.max_archive_depth.unwrap_or(3).max(1),
+        action: action_from(opts, regex)?,
+        dry_run: !opts.yes,
+        watch: opts.watch,
+        watch_removed: opts.watch_removed,
+        index: index_from(opts)?.map(Arc::new),
+    };
+    config.validate()?;
+    Ok(config)
+}
+
+fn action_from(opts: &Opts, regex: &Regex) -> Result<Option<Action>> {
+    if let Some(ref replacement) = opts.rename {
+        return Ok(Some(Action::Rename(Renamer::new(
+            regex.clone(),
+            replacement.clone(),
+        ))));
+    }
+    if opts.trash {
+        return Ok(Some(Action::Trash));
+    }
+    if let Some(ref archive) = opts.archive {
+        return Archiver::new(PathBuf::from(archive))
+            .map(|archiver| Some(Action::Archive(archiver)));
+    }
+    let transfer = [
+        (TransferMode::Copy, &opts.copy_to),
+        (TransferMode::Move, &opts.move_to),
+        (TransferMode::Link, &opts.link_to),
+    ]
+    .into_iter()
+    .find_map(|(mode, destination)| destination.as_ref().map(|dest| (mode, dest)));
+    if let Some((mode, destination)) = transfer {
+        return Ok(Some(Action::Transfer(Transfer::new(
+            mode,
+            PathBuf::from(destination),
+            opts.flatten,
+            opts.on_collision.unwrap_or_default(),
+        ))));
+    }
+    Ok(opts.delete.then_some(Action::Delete {
+        recursive: opts.recursive,
+    }))
+}
+
+fn content_filter_from(opts: &Opts) -> Result<Option<ContentFilter>> {
+    let Some(ref pattern) = opts.contains else {
+        return Ok(None);
+    };
+    let max_scan_bytes = opts
+        .max_scan_bytes
+        .as_deref()
+        .map(filter::parse_size)
+        .transpose()?;
+    let case_sensitive = regex_helper::pattern_has_uppercase_char(pattern);
+    ContentFilter::new(pattern, case_sensitive, max_scan_bytes, opts.binary).map(Some)
+}
+
+fn git_filter_from(opts: &Opts) -> Result<Option<GitFilter>> {
+    //--git-changed-since 相当于从该版本到 HEAD 的提交范围
+    let revisions = match (&opts.git_changed_since, &opts.git_changed_in) {
+        (Some(since), _) => Some(format!("{since}..HEAD")),
+        (None, range) => range.clone(),
+    };
+    if opts.git_status.is_empty() && !opts.git_tracked && revisions.is_none() {
+        return Ok(None);
+    }
+    GitFilter::new(
+        &[PathBuf::from(&opts.path)],
+        &opts.git_status,
+        opts.git_tracked,
+        revisions.as_deref(),
+    )
+    .map(Some)
+}
+
+fn command_filter_from(opts: &Opts) -> Result<Option<CommandFilter>> {
+    let Some(ref command) = opts.filter_exec else {
+        return Ok(None);
+    };
+    let timeout = opts
+        .filter_timeout
+        .as_deref()
+        .map(|t| {
+            humantime::parse_duration(t)
+                .map_err(|_| anyhow!("'{}'不是有效的时间长度，例如 10s、2min", t))
+        })
+        .transpose()?;
+    let cache = if opts.filter_cache {
+        let dir = filesystem::cache_dir().ok_or_else(|| anyhow!("无法确定缓存文件的位置"))?;
+        Some(dir.join("filter-exec"))
+    } else {
+        None
+    };
+    CommandFilter::new(command, timeout, cache).map(Some)
+}
+
+fn time_constraints_from(opts: &Opts) -> Result<Vec<TimeFilter>> {
+    let now = SystemTime::now();
+    let mut time_constraints = Vec::new();
+    if let Some(ref t) = opts.changed_within {
+        let filter = TimeFilter::after(&now, t)
+            .ok_or_else(|| anyhow!("'{}'不是有效的日期或时间长度。输入file-find -h获取帮助", t))?;
+        time_constraints.push(filter);
+    }
+    if let Some(ref t) = opts.changed_before {
+        let filter = TimeFilter::before(&now, t)
+            .ok_or_else(|| anyhow!("'{}'不是有效的日期或时间长度。输入file-find -h获取帮助", t))?;
+        time_constraints.push(filter);
+    }
+    Ok(time_constraints)
+}
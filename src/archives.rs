@@ -0,0 +1,272 @@
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    fs::File,
+    io::{self, BufReader, Cursor, Read, Seek},
+    path::{Component, Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use chrono::{Local, NaiveDate, TimeZone};
+use flate2::read::GzDecoder;
+use zip::ZipArchive;
+
+use crate::action::ArchiveFormat;
+
+//嵌套的归档需要先读入内存，超过这个大小的嵌套归档不会被展开
+const MAX_NESTED_ARCHIVE_LEN: u64 = 256 * 1024 * 1024;
+
+//归档中条目的类型。Other 只出现在索引中，表示设备、套接字、管道等其他类型
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemberKind {
+    File,
+    Dir,
+    Symlink,
+    Other,
+}
+
+/*
+归档中的一个条目（--search-archives）。
+path 是虚拟路径，由归档文件的路径、"!" 和条目在归档中的路径组成，例如 outer.zip!/inner/path，
+嵌套的归档会再加一层，例如 outer.zip!/lib.tar.gz!/src/main.rs。
+depth 是条目在归档（包括嵌套的归档）中的层数，归档中顶层的条目为 1。
+*/
+#[derive(Clone, Debug)]
+pub struct ArchiveMember {
+    pub path: PathBuf,
+    pub kind: MemberKind,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    pub mode: Option<u32>,
+    pub depth: usize,
+}
+
+//根据文件名判断能否作为归档展开，.crate 文件就是 tar.gz
+fn format_of(path: &Path) -> Option<ArchiveFormat> {
+    if path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("crate"))
+    {
+        return Some(ArchiveFormat::TarGz);
+    }
+    ArchiveFormat::from_path(path)
+}
+
+pub fn is_archive(path: &Path) -> bool {
+    format_of(path).is_some()
+}
+
+///列出归档中的所有条目。max_nesting 是最多展开的归档层数，1 表示不展开嵌套的归档。
+/// 归档中没有单独记录的父目录也会作为条目返回。
+pub fn members(path: &Path, max_nesting: usize) -> io::Result<Vec<ArchiveMember>> {
+    let Some(format) = format_of(path) else {
+        return Ok(Vec::new());
+    };
+    let mut reader = MemberReader {
+        members: Vec::new(),
+        dirs: HashSet::new(),
+        max_nesting,
+    };
+    let file = BufReader::new(File::open(path)?);
+    reader.read(format, file, &virtual_root(path), 0, 1)?;
+    Ok(reader.members)
+}
+
+//归档本身在虚拟路径中的部分，例如 outer.zip!
+fn virtual_root(path: &Path) -> PathBuf {
+    let mut root = OsString::from(path.as_os_str());
+    root.push("!");
+    PathBuf::from(root)
+}
+
+struct MemberReader {
+    members: Vec<ArchiveMember>,
+    dirs: HashSet<PathBuf>,
+    max_nesting: usize,
+}
+
+impl MemberReader {
+    fn read<R: Read + Seek>(
+        &mut self,
+        format: ArchiveFormat,
+        reader: R,
+        prefix: &Path,
+        base_depth: usize,
+        nesting: usize,
+    ) -> io::Result<()> {
+        match format {
+            ArchiveFormat::Tar => self.read_tar(reader, prefix, base_depth, nesting),
+            ArchiveFormat::TarGz => {
+                self.read_tar(GzDecoder::new(reader), prefix, base_depth, nesting)
+            }
+            ArchiveFormat::Zip => self.read_zip(reader, prefix, base_depth, nesting),
+        }
+    }
+
+    fn read_tar<R: Read>(
+        &mut self,
+        reader: R,
+        prefix: &Path,
+        base_depth: usize,
+        nesting: usize,
+    ) -> io::Result<()> {
+        let mut archive = tar::Archive::new(reader);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let header = entry.header();
+            let entry_type = header.entry_type();
+            let kind = if entry_type.is_dir() {
+                MemberKind::Dir
+            } else if entry_type.is_symlink() {
+                MemberKind::Symlink
+            } else if entry_type.is_file()
+                || entry_type.is_hard_link()
+                || entry_type.is_gnu_sparse()
+            {
+                MemberKind::File
+            } else {
+                continue;
+            };
+            let member = ArchiveMember {
+                path: PathBuf::new(),
+                kind,
+                size: header.size().unwrap_or(0),
+                modified: header
+                    .mtime()
+                    .ok()
+                    .map(|secs| UNIX_EPOCH + Duration::from_secs(secs)),
+                mode: header.mode().ok(),
+                depth: 0,
+            };
+            let name = entry.path()?.into_owned();
+            if let Some(member) = self.push(member, &name, prefix, base_depth) {
+                self.expand_nested(&mut entry, &member, nesting);
+            }
+        }
+        Ok(())
+    }
+
+    fn read_zip<R: Read + Seek>(
+        &mut self,
+        reader: R,
+        prefix: &Path,
+        base_depth: usize,
+        nesting: usize,
+    ) -> io::Result<()> {
+        let mut archive = ZipArchive::new(reader)?;
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i)?;
+            let kind = if file.is_dir() {
+                MemberKind::Dir
+            } else if file.is_symlink() {
+                MemberKind::Symlink
+            } else {
+                MemberKind::File
+            };
+            let member = ArchiveMember {
+                path: PathBuf::new(),
+                kind,
+                size: file.size(),
+                modified: file.last_modified().and_then(zip_time),
+                mode: file.unix_mode().map(|mode| mode & 0o7777),
+                depth: 0,
+            };
+            let Some(name) = file.enclosed_name() else {
+                continue;
+            };
+            if let Some(member) = self.push(member, &name, prefix, base_depth) {
+                self.expand_nested(&mut file, &member, nesting);
+            }
+        }
+        Ok(())
+    }
+
+    //补全路径并记录条目，同时补上没有单独记录的父目录。返回记录的条目。
+    fn push(
+        &mut self,
+        mut member: ArchiveMember,
+        name: &Path,
+        prefix: &Path,
+        base_depth: usize,
+    ) -> Option<ArchiveMember> {
+        //去掉 ./ 和开头的 /，归档中的路径总是相对的
+        let parts: Vec<_> = name
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(part) => Some(part),
+                _ => None,
+            })
+            .collect();
+        if parts.is_empty() {
+            return None;
+        }
+
+        let mut path = prefix.to_path_buf();
+        for (i, part) in parts.iter().enumerate() {
+            path.push(part);
+            let is_last = i + 1 == parts.len();
+            if is_last && member.kind != MemberKind::Dir {
+                break;
+            }
+            if self.dirs.insert(path.clone()) {
+                self.members.push(ArchiveMember {
+                    path: path.clone(),
+                    kind: MemberKind::Dir,
+                    size: 0,
+                    modified: if is_last { member.modified } else { None },
+                    mode: if is_last { member.mode } else { None },
+                    depth: base_depth + i + 1,
+                });
+            }
+        }
+        if member.kind == MemberKind::Dir {
+            return None;
+        }
+
+        member.path = path;
+        member.depth = base_depth + parts.len();
+        self.members.push(member.clone());
+        Some(member)
+    }
+
+    //成员本身是归档并且还没有达到嵌套层数上限时，读入内存后继续展开。
+    // 嵌套的归档无法读取时不影响外层归档中的其他条目。
+    fn expand_nested<R: Read>(&mut self, reader: &mut R, member: &ArchiveMember, nesting: usize) {
+        if nesting >= self.max_nesting || member.kind != MemberKind::File {
+            return;
+        }
+        let Some(format) = format_of(&member.path) else {
+            return;
+        };
+        if member.size > MAX_NESTED_ARCHIVE_LEN {
+            return;
+        }
+
+        let mut contents = Vec::with_capacity(member.size as usize);
+        if reader.read_to_end(&mut contents).is_err() {
+            return;
+        }
+        let _ = self.read(
+            format,
+            Cursor::new(contents),
+            &virtual_root(&member.path),
+            member.depth,
+            nesting + 1,
+        );
+    }
+}
+
+//zip 中的时间没有时区，按本地时间解释
+fn zip_time(time: zip::DateTime) -> Option<SystemTime> {
+    let naive =
+        NaiveDate::from_ymd_opt(time.year().into(), time.month().into(), time.day().into())?
+            .and_hms_opt(
+                time.hour().into(),
+                time.minute().into(),
+                time.second().into(),
+            )?;
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(SystemTime::from)
+}
@@ -1,22 +1,369 @@
-use clap::Parser; // 引入派生宏
+use clap::{ArgGroup, Parser, Subcommand, ValueEnum}; // 引入派生宏
+
+use crate::action::Collision;
+use crate::count::CountBy;
+use crate::filter::GitStatus;
+use crate::output::ReportFormat;
 
 /// 一个简单的文件搜索工具
 #[derive(Parser, Debug)]
 #[command(name = "fd_search", version = "1.0", about = "A fast file search tool")]
+#[command(group(
+    ArgGroup::new("action")
+        .args(["rename", "delete", "trash", "copy_to", "move_to", "link_to", "archive"])
+))]
+#[command(group(ArgGroup::new("transfer").args(["copy_to", "move_to", "link_to"])))]
+#[command(subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
 pub struct Opts {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// 搜索的模式（正则表达式）
-    #[arg(short, long)]
-    pattern: String,
+    #[arg(short, long, required_unless_present = "expr")]
+    pub pattern: Option<String>,
 
     /// 搜索的路径（默认为当前目录）
     #[arg(short = 'P', long, default_value = ".")]
-    path: String,
+    pub path: String,
 
     /// 是否包括隐藏文件
     #[arg(short = 'H', long)]
-    hidden: bool,
+    pub hidden: bool,
+
+    /// 不读取 .gitignore 和 .fdignore 等忽略文件
+    #[arg(short = 'I', long)]
+    pub no_ignore: bool,
 
     /// 限制搜索结果的数量
     #[arg(short, long)]
-    limit: Option<u64>,
+    pub limit: Option<u64>,
+
+    /// 找到第一个结果后立即退出（等同于 --limit 1），
+    /// 有结果时退出码为 0，否则为 1，可用于 shell 脚本中判断文件是否存在
+    #[arg(short = 'q', long, conflicts_with = "limit")]
+    pub quit: bool,
+
+    /// 限制搜索的最大目录深度
+    #[arg(short = 'd', long, value_name = "depth")]
+    pub max_depth: Option<usize>,
+
+    /// 按文件类型过滤，可多次指定
+    #[arg(short = 't', long = "type", value_name = "filetype", value_enum)]
+    pub filetype: Option<Vec<FileType>>,
+
+    /// 按文件大小过滤，例如 +10k、-1M、500b
+    #[arg(short = 'S', long, value_name = "size", allow_hyphen_values = true)]
+    pub size: Vec<String>,
+
+    /// 只保留在指定时间之后修改过的条目，例如 2weeks、2025-01-21
+    #[arg(long, value_name = "date|dur")]
+    pub changed_within: Option<String>,
+
+    /// 只保留在指定时间之前修改过的条目
+    #[arg(long, value_name = "date|dur")]
+    pub changed_before: Option<String>,
+
+    /// 按所有者过滤，格式为 [user][:group]，可以是名称或数字 id，前面加 ! 表示排除，
+    /// 例如 john、:staff、!root、1000:!100（仅 Unix）
+    #[cfg(unix)]
+    #[arg(short = 'o', long, value_name = "user:group")]
+    pub owner: Option<String>,
+
+    /// 只保留处于指定 git 状态的条目（modified、staged、untracked、ignored、conflicted），
+    /// 多个状态用逗号分隔，满足其一即可；目录中有处于该状态的条目时目录本身也保留。
+    /// 只读取搜索路径所在仓库的索引和工作目录，ignored 需要同时使用 --no-ignore
+    #[arg(
+        long,
+        value_name = "status",
+        value_enum,
+        value_delimiter = ',',
+        conflicts_with = "watch"
+    )]
+    pub git_status: Vec<GitStatus>,
+
+    /// 只保留被 git 跟踪的条目（在索引中的文件以及包含它们的目录）
+    #[arg(long, conflicts_with = "watch")]
+    pub git_tracked: bool,
+
+    /// 只保留从指定版本（例如 origin/main）到 HEAD 的提交中改动过的条目，包括重命名后的文件。
+    /// 只读取本地的对象库，不会获取远程仓库的更新
+    #[arg(long, value_name = "rev", conflicts_with_all = ["watch", "git_changed_in"])]
+    pub git_changed_since: Option<String>,
+
+    /// 只保留指定提交或提交范围（例如 HEAD~3、A..B、A...B）中的提交改动过的条目
+    #[arg(long, value_name = "range", conflicts_with = "watch")]
+    pub git_changed_in: Option<String>,
+
+    /// 用表达式组合过滤条件：-name、-path（正则）、-type、-size、-changed-within、
+    /// -changed-before、-owner、-perm，用 !、-a、-o 和括号组合，例如
+    /// "( -name '\.rs$' -size +10k -o -name '\.toml$' -changed-within 1d ) ! -path /tests/"
+    #[arg(short = 'e', long, value_name = "expr", allow_hyphen_values = true)]
+    pub expr: Option<String>,
+
+    /// 按模板输出结果，支持 {}、{/}、{//}、{.}、{/.}、{hash:sha256}、{hash:blake3}，
+    /// 以及 {size}、{mtime}、{mode}、{owner}、{group}、{ext}、{depth}、{inode}、{nlink}、{root}。
+    /// 占位符可以带格式说明，例如 {size:>8h}、{mtime:%Y-%m-%d}、{mtime:rel}、{/:.20}；
+    /// 搜索模式中的捕获组可以用 {1}、{name} 引用；使用 {{ 和 }} 输出字面的大括号
+    #[arg(long, value_name = "fmt")]
+    pub format: Option<String>,
+
+    /// 只保留内容匹配该正则表达式的文件
+    #[arg(long, value_name = "regex")]
+    pub contains: Option<String>,
+
+    /// 每个文件最多读取的字节数，例如 1M
+    #[arg(long, value_name = "size", requires = "contains")]
+    pub max_scan_bytes: Option<String>,
+
+    /// 同时在二进制文件中搜索内容（默认跳过）
+    #[arg(long, requires = "contains")]
+    pub binary: bool,
+
+    /// 对每个候选条目执行命令，只保留退出码为 0 的条目。参数中可以使用 --format 的占位符，
+    /// 没有占位符时把路径作为最后一个参数；命令以单独的 ; 结束，例如
+    /// --filter-exec rustfmt --check {} \;
+    #[arg(
+        long,
+        value_name = "cmd",
+        num_args = 1..,
+        value_terminator = ";",
+        allow_hyphen_values = true
+    )]
+    pub filter_exec: Option<Vec<String>>,
+
+    /// --filter-exec 中每条命令的超时时间（默认为 30s），超时的条目不保留
+    #[arg(long, value_name = "dur", requires = "filter_exec")]
+    pub filter_timeout: Option<String>,
+
+    /// 缓存 --filter-exec 的结果，文件的大小和修改时间没有变化时不再执行命令
+    #[arg(long, requires = "filter_exec")]
+    pub filter_cache: bool,
+
+    /// 在结果后面输出内容第一处匹配所在的行号（path:line）
+    #[arg(long, requires = "contains")]
+    pub line_number: bool,
+
+    /// 搜索结束后在 stderr 输出统计信息（text 或 json）
+    #[arg(
+        long,
+        value_name = "format",
+        value_enum,
+        num_args = 0..=1,
+        default_missing_value = "text"
+    )]
+    pub stats: Option<ReportFormat>,
+
+    /// 只输出匹配结果的数量
+    #[arg(short = 'c', long)]
+    pub count: bool,
+
+    /// 按目录、搜索路径或扩展名分组计数（隐含 --count）
+    #[arg(long, value_name = "by", value_enum)]
+    pub count_by: Option<CountBy>,
+
+    /// 在结果中查找内容相同的文件，按组输出（text 或 json）
+    #[arg(
+        long,
+        value_name = "format",
+        value_enum,
+        num_args = 0..=1,
+        default_missing_value = "text",
+        conflicts_with_all = ["count", "count_by"]
+    )]
+    pub duplicates: Option<ReportFormat>,
+
+    /// 用替换字符串批量重命名匹配的条目，只替换文件名中的第一处匹配，
+    /// 可以用 $1、${name} 引用捕获组（后面紧跟字母、数字或下划线时写作 ${1}）。默认只预览，需要 --yes 才会执行
+    #[arg(
+        long,
+        value_name = "replacement",
+        conflicts_with_all = ["count", "count_by", "duplicates"]
+    )]
+    pub rename: Option<String>,
+
+    /// 删除匹配的条目，先删除目录中的条目再删除目录本身，目录中还有未匹配的条目时不删除该目录。
+    /// 默认只预览，需要 --yes 或者在终端中确认后才会执行
+    #[arg(long, conflicts_with_all = ["count", "count_by", "duplicates"])]
+    pub delete: bool,
+
+    /// 与 --delete 一起使用，匹配的目录连同其中的全部内容（包括隐藏的和被忽略的条目）一起删除
+    #[arg(short = 'r', long, requires = "delete")]
+    pub recursive: bool,
+
+    /// 把匹配的条目移到回收站（freedesktop.org 回收站规范），可以用 trash-restore 子命令恢复。
+    /// 默认只预览，需要 --yes 或者在终端中确认后才会执行
+    #[arg(long, conflicts_with_all = ["count", "count_by", "duplicates"])]
+    pub trash: bool,
+
+    /// 把匹配的条目复制到目录中，保留相对于搜索路径的目录结构、修改时间和权限。
+    /// 默认只预览，需要 --yes 才会执行
+    #[arg(long, value_name = "dir", conflicts_with_all = ["count", "count_by", "duplicates"])]
+    pub copy_to: Option<String>,
+
+    /// 把匹配的条目移动到目录中，保留相对于搜索路径的目录结构，可以跨文件系统移动。
+    /// 默认只预览，需要 --yes 才会执行
+    #[arg(long, value_name = "dir", conflicts_with_all = ["count", "count_by", "duplicates"])]
+    pub move_to: Option<String>,
+
+    /// 在目录中创建指向匹配条目的符号链接，保留相对于搜索路径的目录结构。
+    /// 默认只预览，需要 --yes 才会执行
+    #[arg(long, value_name = "dir", conflicts_with_all = ["count", "count_by", "duplicates"])]
+    pub link_to: Option<String>,
+
+    /// 不保留目录结构，直接放到 --copy-to、--move-to、--link-to 指定的目录中
+    #[arg(long, requires = "transfer")]
+    pub flatten: bool,
+
+    /// 目标位置已存在同名条目时的处理方式（默认为 error）
+    #[arg(long, value_name = "policy", value_enum, requires = "transfer")]
+    pub on_collision: Option<Collision>,
+
+    /// 把匹配的条目写入归档文件，格式由扩展名决定（.tar、.tar.gz、.tgz、.zip），
+    /// 归档中的路径相对于搜索路径，保留权限和修改时间，符号链接以链接形式保存
+    #[arg(long, value_name = "file", conflicts_with_all = ["count", "count_by", "duplicates"])]
+    pub archive: Option<String>,
+
+    /// 执行 --rename、--delete、--trash、--copy-to 等修改文件系统的操作，而不只是预览
+    #[arg(short = 'y', long, requires = "action")]
+    pub yes: bool,
+
+    /// 把 .tar、.tar.gz、.tgz、.crate 和 .zip 文件当作目录，搜索其中的条目，
+    /// 结果的路径形如 outer.zip!/inner/path
+    #[arg(long, conflicts_with_all = ["action", "contains", "filter_exec"])]
+    pub search_archives: bool,
+
+    /// 最多展开的归档嵌套层数（默认为 3），1 表示不展开归档中的归档
+    #[arg(long, value_name = "depth", requires = "search_archives")]
+    pub max_archive_depth: Option<usize>,
+
+    /// 完成搜索后继续监视搜索路径（仅 Linux），输出新出现的匹配条目，
+    /// 包括新建的、移入的以及修改后满足大小或时间条件的条目
+    #[arg(
+        long,
+        conflicts_with_all = ["count", "count_by", "duplicates", "action", "limit", "quit", "search_archives"]
+    )]
+    pub watch: bool,
+
+    /// 监视时同时输出不再匹配的条目（被删除、移出或修改后不再满足条件），
+    /// 此时监视过程中新匹配的条目以 "+ " 开头，不再匹配的条目以 "- " 开头
+    #[arg(long, requires = "watch")]
+    pub watch_removed: bool,
+
+    /// 在 index build 建立的索引中搜索，而不遍历文件系统，可以指定索引文件（默认为 ~/.cache/file-find/index）。
+    /// 过滤条件与正常搜索相同，索引可能已经过时时给出警告
+    #[arg(
+        long,
+        value_name = "file",
+        num_args = 0..=1,
+        conflicts_with_all = ["watch", "search_archives"]
+    )]
+    pub from_index: Option<Option<String>>,
+
+    /// 通过 serve 启动的守护进程搜索，守护进程没有运行或无法处理这个请求时照常搜索
+    #[arg(long, value_name = "socket")]
+    pub daemon: Option<String>,
+
+    /// 使用的线程数（默认为 CPU 核心数）
+    #[arg(short = 'j', long, value_name = "num")]
+    pub threads: Option<usize>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// 从回收站恢复原路径匹配模式的条目
+    TrashRestore {
+        /// 与条目原来的完整路径匹配的模式（正则表达式）
+        pattern: String,
+
+        /// 执行恢复，而不只是预览
+        #[arg(short = 'y', long)]
+        yes: bool,
+    },
+
+    /// find 兼容模式：按 GNU find 的写法解析起始目录和表达式，
+    /// 支持 -name、-iname、-path、-type、-size、-mtime、-newer、-perm、-user、-group、
+    /// -maxdepth、-prune、-print、-print0、-exec，也可以通过名为 find 的链接调用
+    #[command(disable_help_flag = true)]
+    FindCompat {
+        /// 起始目录和表达式，例如 . -name '*.rs' -size +10k
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+
+    /// 建立或更新供 --from-index 使用的索引
+    Index {
+        #[command(subcommand)]
+        command: IndexCommand,
+    },
+
+    /// 在内存中维护目录的索引（通过 inotify 保持最新，仅 Linux），
+    /// 在 Unix 套接字上响应 JSON 格式的搜索请求，供 --daemon 和编辑器使用
+    Serve {
+        /// 监听的 Unix 套接字
+        #[arg(long, value_name = "path")]
+        socket: String,
+
+        /// 建立索引的目录，可以指定多个（默认为当前目录）
+        #[arg(default_value = ".")]
+        roots: Vec<String>,
+
+        /// 索引中包括隐藏文件
+        #[arg(short = 'H', long)]
+        hidden: bool,
+
+        /// 不读取 .gitignore 和 .fdignore 等忽略文件
+        #[arg(short = 'I', long)]
+        no_ignore: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum IndexCommand {
+    /// 遍历目录，建立新的索引
+    Build {
+        /// 建立索引的目录（默认为当前目录）
+        #[arg(default_value = ".")]
+        path: String,
+
+        /// 索引中包括隐藏文件
+        #[arg(short = 'H', long)]
+        hidden: bool,
+
+        /// 不读取 .gitignore 和 .fdignore 等忽略文件
+        #[arg(short = 'I', long)]
+        no_ignore: bool,
+
+        /// 索引文件（默认为 ~/.cache/file-find/index）
+        #[arg(long, value_name = "file")]
+        database: Option<String>,
+    },
+
+    /// 只重新读取修改时间变化了的目录，增量更新已有的索引
+    Update {
+        /// 索引文件（默认为 ~/.cache/file-find/index）
+        #[arg(long, value_name = "file")]
+        database: Option<String>,
+    },
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, ValueEnum)]
+pub enum FileType {
+    #[value(alias = "f")]
+    File,
+    #[value(alias = "d")]
+    Directory,
+    #[value(alias = "l")]
+    Symlink,
+    #[value(alias = "b")]
+    BlockDevice,
+    #[value(alias = "c")]
+    CharDevice,
+    #[value(alias = "x")]
+    Executable,
+    #[value(alias = "e")]
+    Empty,
+    #[value(alias = "s")]
+    Socket,
+    #[value(alias = "p")]
+    Pipe,
 }
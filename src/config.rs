@@ -1,38 +1,238 @@
+use std::{
+    path::{PathBuf, MAIN_SEPARATOR},
+    sync::Arc,
+};
+
+use anyhow::{bail, Result};
 use lscolors::LsColors;
 
+use crate::action::Action;
+use crate::count::CountBy;
+use crate::filetypes::FileType;
+#[cfg(unix)]
+use crate::filter::OwnerFilter;
+use crate::filter::{CommandFilter, ContentFilter, FilterExpr, GitFilter, SizeFilter, TimeFilter};
 use crate::fmt::FormatTemplate;
+use crate::index::Index;
+use crate::output::ReportFormat;
 
+/*
+一次搜索的全部配置。字段只在 crate 内可见：命令行程序在 app 中根据命令行参数构造，
+库的使用者通过 SearchBuilder 构造，两者都要经过 validate 检查选项之间的约束。
+*/
 pub struct Config {
     //搜索是否注意大小写
-    pub case_sensitive: bool,
+    pub(crate) case_sensitive: bool,
 
     //是否在完整文件路径
     //或仅在基本名称（文件名或目录名称）内搜索。
-    pub search_full_path: bool,
+    pub(crate) search_full_path: bool,
 
     //是否忽略隐藏文件或目录
-    pub ignore_hidden: bool,
+    pub(crate) ignore_hidden: bool,
 
     //是否注意“.fdignore”文件。
-    pub read_fdignore: bool,
+    pub(crate) read_fdignore: bool,
+
+    //是否注意“.gitignore”等版本控制的忽略文件。
+    pub(crate) read_vcsignore: bool,
 
     //是否跟随符号链接。
-    pub follow_links: bool,
+    pub(crate) follow_links: bool,
 
     //是否剥离' ./ '在搜索结果中
-    pub strip_cwd_prefix: bool,
+    pub(crate) strip_cwd_prefix: bool,
+
+    //serve 处理请求时客户端的当前目录：搜索路径是由它拼出的绝对路径，输出时去掉这个前缀
+    pub(crate) base_dir: Option<PathBuf>,
+
+    //搜索路径本身是否也作为结果（find-compat 与 find 相同）
+    pub(crate) include_roots: bool,
 
     /// 是否在路径上使用超链接
-    pub hyperlink: bool,
+    pub(crate) hyperlink: bool,
 
     ///
-    pub format: Option<FormatTemplate>,
+    pub(crate) format: Option<FormatTemplate>,
+
+    pub(crate) path_separator: Option<String>,
+
+    pub(crate) actual_path_separator: String,
+
+    pub(crate) ls_colors: Option<LsColors>,
+
+    pub(crate) null_separator: bool,
+
+    //最大搜索深度，None 表示不限制
+    pub(crate) max_depth: Option<usize>,
+
+    //遍历使用的线程数
+    pub(crate) threads: usize,
+
+    //文件类型过滤，None 表示不按类型过滤
+    pub(crate) file_types: Option<FileType>,
+
+    //文件大小过滤，所有条件都需要满足
+    pub(crate) size_constraints: Vec<SizeFilter>,
+
+    //修改时间过滤，所有条件都需要满足
+    pub(crate) time_constraints: Vec<TimeFilter>,
+
+    //所有者过滤，None 表示不按所有者过滤
+    #[cfg(unix)]
+    pub(crate) owner_constraint: Option<OwnerFilter>,
+
+    //按 git 仓库中的状态过滤（--git-status、--git-tracked），None 表示不按状态过滤
+    pub(crate) git_filter: Option<GitFilter>,
+
+    //过滤表达式（--expr），与其他过滤条件同时满足
+    pub(crate) filter_expr: Option<FilterExpr>,
 
-    pub path_separator: Option<String>,
+    //是否在搜索结束后输出统计信息，以及输出格式
+    pub(crate) stats: Option<ReportFormat>,
 
-    pub actual_path_separator: String,
+    //是否只输出匹配数量而不输出结果
+    pub(crate) count: bool,
 
-    pub ls_colors: Option<LsColors>,
+    //计数时的分组方式，None 表示只输出总数
+    pub(crate) count_by: Option<CountBy>,
+
+    //最多输出的结果数量，达到后立即停止遍历
+    pub(crate) max_results: Option<usize>,
+
+    //是否用退出码表示有没有找到结果（--quit）
+    pub(crate) quit: bool,
+
+    //按文件内容过滤，None 表示不检查内容
+    pub(crate) content_filter: Option<ContentFilter>,
+
+    //用外部命令过滤（--filter-exec），None 表示不执行命令
+    pub(crate) command_filter: Option<CommandFilter>,
+
+    //是否在结果后面输出内容匹配所在的行号
+    pub(crate) show_line_number: bool,
+
+    //是否查找重复文件，以及重复文件分组的输出格式
+    pub(crate) duplicates: Option<ReportFormat>,
+
+    //是否把归档文件当作目录，搜索其中的条目
+    pub(crate) search_archives: bool,
+
+    //最多展开的归档嵌套层数，1 表示不展开归档中的归档
+    pub(crate) max_archive_depth: usize,
+
+    //对全部结果执行的操作（例如 --rename），None 表示只输出结果
+    pub(crate) action: Option<Action>,
+
+    //是否只预览操作而不修改文件系统
+    pub(crate) dry_run: bool,
+
+    //搜索结束后是否继续监视搜索路径，输出新出现的匹配条目
+    pub(crate) watch: bool,
+
+    //监视时是否同时输出不再匹配的条目
+    pub(crate) watch_removed: bool,
+
+    //在索引中搜索时使用的索引：--from-index 加载的索引，或者 serve 在内存中维护的索引
+    pub(crate) index: Option<Arc<Index>>,
+}
+
+impl Default for Config {
+    ///与不带任何选项运行命令行程序时相同的配置，不输出颜色
+    fn default() -> Self {
+        Self {
+            case_sensitive: false,
+            search_full_path: false,
+            ignore_hidden: true,
+            read_fdignore: true,
+            read_vcsignore: true,
+            follow_links: false,
+            strip_cwd_prefix: false,
+            base_dir: None,
+            include_roots: false,
+            hyperlink: false,
+            format: None,
+            path_separator: None,
+            actual_path_separator: MAIN_SEPARATOR.to_string(),
+            ls_colors: None,
+            null_separator: false,
+            max_depth: None,
+            threads: std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+            file_types: None,
+            size_constraints: Vec::new(),
+            time_constraints: Vec::new(),
+            #[cfg(unix)]
+            owner_constraint: None,
+            git_filter: None,
+            filter_expr: None,
+            stats: None,
+            count: false,
+            count_by: None,
+            max_results: None,
+            quit: false,
+            content_filter: None,
+            command_filter: None,
+            show_line_number: false,
+            duplicates: None,
+            search_archives: false,
+            max_archive_depth: 3,
+            action: None,
+            dry_run: true,
+            watch: false,
+            watch_removed: false,
+            index: None,
+        }
+    }
+}
 
-    pub null_separator: bool,
+impl Config {
+    ///检查选项之间的约束。命令行参数的大部分冲突已经由 clap 检查过，
+    /// 这里再检查一遍，保证通过 SearchBuilder 构造的配置同样有效
+    pub fn validate(&self) -> Result<()> {
+        if self.threads == 0 {
+            bail!("线程数必须大于 0");
+        }
+        if self.max_results == Some(0) {
+            bail!("结果数量上限必须大于 0");
+        }
+        if self.max_archive_depth == 0 {
+            bail!("归档嵌套层数必须大于 0");
+        }
+        if self.count_by.is_some() && !self.count {
+            bail!("分组计数需要同时启用计数");
+        }
+        if self.action.is_some() && (self.count || self.duplicates.is_some()) {
+            bail!("对结果执行的操作不能与计数或查找重复文件同时使用");
+        }
+        if self.search_archives
+            && (self.action.is_some()
+                || self.content_filter.is_some()
+                || self.command_filter.is_some())
+        {
+            bail!("搜索归档时不能执行操作、按内容过滤或用命令过滤");
+        }
+        if self.watch_removed && !self.watch {
+            bail!("输出不再匹配的条目需要同时启用监视");
+        }
+        if self.watch {
+            if !cfg!(target_os = "linux") {
+                bail!("--watch 只支持 Linux");
+            }
+            if self.action.is_some()
+                || self.count
+                || self.duplicates.is_some()
+                || self.max_results.is_some()
+                || self.search_archives
+                || self.index.is_some()
+            {
+                bail!("监视时不能执行操作、计数、查找重复文件、限制结果数量、搜索归档或使用索引");
+            }
+        }
+        if self.index.is_some() && self.search_archives {
+            bail!("在索引中搜索时不能搜索归档");
+        }
+        Ok(())
+    }
 }
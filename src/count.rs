@@ -0,0 +1,83 @@
+use std::{
+    collections::HashMap,
+    ffi::{OsStr, OsString},
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::{config::Config, dir_entry::DirEntry};
+
+/*
+--count-by 的分组方式：
+1.Dir：按条目所在的目录分组。
+2.Root：按条目所属的搜索路径分组。
+3.Ext：按文件扩展名分组。
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum CountBy {
+    Dir,
+    Root,
+    Ext,
+}
+
+//没有扩展名的条目在按扩展名分组时使用的键
+const NO_EXTENSION: &str = "(none)";
+
+///只统计匹配数量的计数器（--count）。
+/// 计数时不会生成输出字符串，最后也只对分组的键排序，而不是对全部结果排序。
+pub struct Counter {
+    by: Option<CountBy>,
+    total: u64,
+    groups: HashMap<OsString, u64>,
+}
+
+impl Counter {
+    pub fn new(by: Option<CountBy>) -> Self {
+        Self {
+            by,
+            total: 0,
+            groups: HashMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, entry: &DirEntry, config: &Config) {
+        self.total += 1;
+
+        let key = match self.by {
+            None => return,
+            Some(CountBy::Dir) => {
+                let path = entry.stripped_path(config);
+                match path.parent() {
+                    Some(parent) if parent != Path::new("") => parent.as_os_str(),
+                    _ => OsStr::new("."),
+                }
+            }
+            Some(CountBy::Root) => entry
+                .stripped_root(config)
+                .unwrap_or(entry.path())
+                .as_os_str(),
+            Some(CountBy::Ext) => entry.path().extension().unwrap_or(OsStr::new(NO_EXTENSION)),
+        };
+
+        //大多数条目的键已经存在，先查找可以避免每次都分配新的 OsString
+        if let Some(count) = self.groups.get_mut(key) {
+            *count += 1;
+        } else {
+            self.groups.insert(key.to_owned(), 1);
+        }
+    }
+
+    ///输出计数结果。分组时每行为“数量<TAB>分组”，最后一行为总数。
+    pub fn print<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        if self.by.is_some() {
+            let mut groups: Vec<_> = self.groups.iter().collect();
+            groups.sort_unstable_by(|a, b| a.0.cmp(b.0));
+            for (key, count) in groups {
+                writeln!(w, "{}\t{}", count, key.to_string_lossy())?;
+            }
+            writeln!(w, "{}\ttotal", self.total)
+        } else {
+            writeln!(w, "{}", self.total)
+        }
+    }
+}
@@ -1,11 +1,16 @@
 use std::{
     cell::OnceCell,
+    ffi::{OsStr, OsString},
     fs::{FileType, Metadata},
     path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
 };
 
+use crate::archives::{ArchiveMember, MemberKind};
 use crate::config::Config;
 use crate::filesystem::strip_current_dir;
+use crate::hash::{hash_file, HashAlgorithm};
 use lscolors::{Colorable, LsColors, Style};
 /*
 DirEntryInner 枚举：
@@ -14,35 +19,91 @@ DirEntryInner 枚举：
     这个变体用于正常的文件或目录条目。
 2.BrokenSymlink(PathBuf)：这个变体表示一个损坏的符号链接，存储了链接的路径。
     它用于处理那些指向不存在位置的符号链接。
+3.Archived(ArchiveMember)：归档中的条目（--search-archives），路径是虚拟的，
+    没有对应的 std::fs::FileType 和 Metadata，类型、大小和修改时间从归档中读取。
+4.Indexed(ArchiveMember)：索引中的条目（--from-index），类型、大小、修改时间和权限使用索引中记录的值，
+    只有在需要其他元数据（例如颜色、所有者）时才读取文件本身的元数据。
 */
 #[derive(Debug)]
 enum DirEntryInner {
     Normal(ignore::DirEntry),
     BrokenSymlink(PathBuf),
+    Archived(ArchiveMember),
+    Indexed(ArchiveMember),
 }
 
 #[derive(Debug)]
 pub struct DirEntry {
     inner: DirEntryInner,
-    metedata: OnceCell<Option<Metadata>>,
+    metadata: OnceCell<Option<Metadata>>,
     style: OnceCell<Option<Style>>,
+    //--contains 匹配到的第一行的行号
+    matched_line: Option<u64>,
+    sha256: OnceCell<Option<String>>,
+    blake3: OnceCell<Option<String>>,
+    //条目所属的搜索路径
+    root: Option<Arc<Path>>,
+    //搜索模式中各个捕获组匹配到的内容，只在格式模板用到捕获组时保存
+    captures: Vec<Option<OsString>>,
+    //监视模式下从子目录开始重新遍历时，子目录相对于搜索路径的层数
+    base_depth: usize,
 }
 
 impl DirEntry {
     #[inline]
-    pub fn normol(e: ignore::DirEntry) -> Self {
+    pub(crate) fn normol(e: ignore::DirEntry) -> Self {
         Self {
             inner: DirEntryInner::Normal(e),
-            metedata: OnceCell::new(),
+            metadata: OnceCell::new(),
             style: OnceCell::new(),
+            matched_line: None,
+            sha256: OnceCell::new(),
+            blake3: OnceCell::new(),
+            root: None,
+            captures: Vec::new(),
+            base_depth: 0,
         }
     }
 
-    pub fn borken_symlink(path: PathBuf) -> Self {
+    pub(crate) fn archived(member: ArchiveMember) -> Self {
+        Self {
+            inner: DirEntryInner::Archived(member),
+            metadata: OnceCell::new(),
+            style: OnceCell::new(),
+            matched_line: None,
+            sha256: OnceCell::new(),
+            blake3: OnceCell::new(),
+            root: None,
+            captures: Vec::new(),
+            base_depth: 0,
+        }
+    }
+
+    pub(crate) fn indexed(record: ArchiveMember) -> Self {
+        Self {
+            inner: DirEntryInner::Indexed(record),
+            metadata: OnceCell::new(),
+            style: OnceCell::new(),
+            matched_line: None,
+            sha256: OnceCell::new(),
+            blake3: OnceCell::new(),
+            root: None,
+            captures: Vec::new(),
+            base_depth: 0,
+        }
+    }
+
+    pub(crate) fn borken_symlink(path: PathBuf) -> Self {
         Self {
             inner: DirEntryInner::BrokenSymlink(path),
-            metedata: OnceCell::new(),
+            metadata: OnceCell::new(),
             style: OnceCell::new(),
+            matched_line: None,
+            sha256: OnceCell::new(),
+            blake3: OnceCell::new(),
+            root: None,
+            captures: Vec::new(),
+            base_depth: 0,
         }
     }
 
@@ -50,6 +111,9 @@ impl DirEntry {
         match &self.inner {
             DirEntryInner::Normal(e) => e.path(),
             DirEntryInner::BrokenSymlink(pathbuf) => pathbuf.as_path(),
+            DirEntryInner::Archived(member) | DirEntryInner::Indexed(member) => {
+                member.path.as_path()
+            }
         }
     }
 
@@ -57,19 +121,25 @@ impl DirEntry {
         match self.inner {
             DirEntryInner::Normal(e) => e.into_path(),
             DirEntryInner::BrokenSymlink(pathbuf) => pathbuf,
+            DirEntryInner::Archived(member) | DirEntryInner::Indexed(member) => member.path,
         }
     }
 
-    pub fn stripped_path(&self, config: &Config) -> &Path {
+    pub(crate) fn stripped_path(&self, config: &Config) -> &Path {
+        //serve 中的搜索路径是拼上客户端当前目录的绝对路径，先去掉这部分
+        let path = match config.base_dir {
+            Some(ref base) => self.path().strip_prefix(base).unwrap_or(self.path()),
+            None => self.path(),
+        };
         if config.strip_cwd_prefix {
-            strip_current_dir(self.path())
+            strip_current_dir(path)
         } else {
-            self.path()
+            path
         }
     }
 
-    pub fn into_stripped_path(self, config: &Config) -> PathBuf {
-        if config.strip_cwd_prefix {
+    pub(crate) fn into_stripped_path(self, config: &Config) -> PathBuf {
+        if config.strip_cwd_prefix || config.base_dir.is_some() {
             self.stripped_path(config).to_path_buf()
         } else {
             self.into_path()
@@ -79,26 +149,144 @@ impl DirEntry {
     pub fn file_type(&self) -> Option<FileType> {
         match &self.inner {
             DirEntryInner::Normal(e) => e.file_type(),
-            DirEntryInner::BrokenSymlink(_) => self.metedata().map(|m| m.file_type()),
+            DirEntryInner::BrokenSymlink(_) | DirEntryInner::Indexed(_) => {
+                self.metadata().map(|m| m.file_type())
+            }
+            DirEntryInner::Archived(_) => None,
         }
     }
 
-    pub fn metedata(&self) -> Option<&Metadata> {
-        self.metedata
+    pub fn metadata(&self) -> Option<&Metadata> {
+        self.metadata
             .get_or_init(|| match &self.inner {
                 DirEntryInner::Normal(e) => e.metadata().ok(),
                 DirEntryInner::BrokenSymlink(path) => path.symlink_metadata().ok(),
+                DirEntryInner::Archived(_) => None,
+                DirEntryInner::Indexed(record) => record.path.symlink_metadata().ok(),
             })
             .as_ref()
     }
 
     pub fn depth(&self) -> Option<usize> {
         match &self.inner {
-            DirEntryInner::Normal(e) => Some(e.depth()),
+            DirEntryInner::Normal(e) => Some(self.base_depth + e.depth()),
             DirEntryInner::BrokenSymlink(_) => None,
+            DirEntryInner::Archived(member) | DirEntryInner::Indexed(member) => Some(member.depth),
         }
     }
 
+    ///归档中的条目返回归档中记录的信息
+    pub fn archive_member(&self) -> Option<&ArchiveMember> {
+        match &self.inner {
+            DirEntryInner::Archived(member) => Some(member),
+            _ => None,
+        }
+    }
+
+    ///归档或索引中的条目返回记录下来的类型、大小、修改时间和权限
+    pub fn recorded(&self) -> Option<&ArchiveMember> {
+        match &self.inner {
+            DirEntryInner::Archived(member) | DirEntryInner::Indexed(member) => Some(member),
+            _ => None,
+        }
+    }
+
+    //以下方法对普通条目、归档和索引中的条目都有效
+
+    pub fn is_file(&self) -> bool {
+        match self.recorded() {
+            Some(member) => member.kind == MemberKind::File,
+            None => self.file_type().is_some_and(|ft| ft.is_file()),
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        match self.recorded() {
+            Some(member) => member.kind == MemberKind::Dir,
+            None => self.file_type().is_some_and(|ft| ft.is_dir()),
+        }
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        match self.recorded() {
+            Some(member) => member.kind == MemberKind::Symlink,
+            None => self.file_type().is_some_and(|ft| ft.is_symlink()),
+        }
+    }
+
+    pub fn size(&self) -> Option<u64> {
+        match self.recorded() {
+            Some(member) => Some(member.size),
+            None => self.metadata().map(|m| m.len()),
+        }
+    }
+
+    pub fn modified(&self) -> Option<SystemTime> {
+        match self.recorded() {
+            Some(member) => member.modified,
+            None => self.metadata()?.modified().ok(),
+        }
+    }
+
+    pub fn root(&self) -> Option<&Path> {
+        self.root.as_deref()
+    }
+
+    ///输出用的搜索路径，与 stripped_path 一样去掉 serve 中客户端当前目录的前缀
+    pub(crate) fn stripped_root(&self, config: &Config) -> Option<&Path> {
+        let root = self.root()?;
+        let Some(ref base) = config.base_dir else {
+            return Some(root);
+        };
+        match root.strip_prefix(base) {
+            Ok(relative) if relative.as_os_str().is_empty() => Some(Path::new(".")),
+            Ok(relative) => Some(relative),
+            Err(_) => Some(root),
+        }
+    }
+
+    pub(crate) fn set_root(&mut self, root: Arc<Path>) {
+        self.root = Some(root);
+    }
+
+    //监视模式下从子目录开始遍历时，子目录本身相对于搜索路径的深度，加到遍历器报告的深度上
+    pub(crate) fn set_base_depth(&mut self, base_depth: usize) {
+        self.base_depth = base_depth;
+    }
+
+    ///第 index 个捕获组匹配到的内容，捕获组没有参与匹配时返回 None
+    pub fn capture(&self, index: usize) -> Option<&OsStr> {
+        self.captures.get(index)?.as_deref()
+    }
+
+    pub(crate) fn set_captures(&mut self, captures: Vec<Option<OsString>>) {
+        self.captures = captures;
+    }
+
+    pub fn matched_line(&self) -> Option<u64> {
+        self.matched_line
+    }
+
+    pub(crate) fn set_matched_line(&mut self, line: u64) {
+        self.matched_line = Some(line);
+    }
+
+    ///文件内容的哈希值，只在第一次需要时计算并缓存。
+    /// 不是普通文件或者文件无法读取时返回 None。
+    pub fn content_hash(&self, algorithm: HashAlgorithm) -> Option<&str> {
+        let cell = match algorithm {
+            HashAlgorithm::Sha256 => &self.sha256,
+            HashAlgorithm::Blake3 => &self.blake3,
+        };
+        cell.get_or_init(|| {
+            if !self.file_type().is_some_and(|ft| ft.is_file()) {
+                return None;
+            }
+            hash_file(self.path(), algorithm).ok()
+        })
+        .as_deref()
+    }
+
     pub fn style(&self, ls_colors: &LsColors) -> Option<&Style> {
         self.style
             .get_or_init(|| ls_colors.style_for(self).cloned())
@@ -142,6 +330,10 @@ impl Colorable for DirEntry {
                 .last()
                 .map(|c| c.as_os_str())
                 .unwrap_or_else(|| path.as_os_str()),
+            DirEntryInner::Archived(member) | DirEntryInner::Indexed(member) => member
+                .path
+                .file_name()
+                .unwrap_or_else(|| member.path.as_os_str()),
         };
         name.to_owned()
     }
@@ -151,6 +343,6 @@ impl Colorable for DirEntry {
     }
 
     fn metadata(&self) -> Option<Metadata> {
-        self.metedata().cloned()
+        self.metadata().cloned()
     }
 }
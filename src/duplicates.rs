@@ -0,0 +1,355 @@
+use std::{
+    collections::HashMap,
+    fs::{File, Metadata},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use serde_json::json;
+
+use crate::{
+    config::Config, dir_entry::DirEntry, error::print_error, filter::format_bytes,
+    output::ReportFormat,
+};
+
+//第二轮比较时只对文件开头的这么多字节计算哈希
+const PARTIAL_HASH_LEN: u64 = 4 * 1024;
+
+///一组内容完全相同的文件
+pub struct DuplicateGroup {
+    pub len: u64,
+    pub hash: blake3::Hash,
+    pub paths: Vec<PathBuf>,
+    ///links[i] 是与 paths[i] 指向同一个 inode 的其他路径（硬链接），不占用额外的空间
+    pub links: Vec<Vec<PathBuf>>,
+}
+
+impl DuplicateGroup {
+    //删除多余副本后可以节省的字节数
+    pub fn wasted_bytes(&self) -> u64 {
+        self.len * (self.paths.len() as u64 - 1)
+    }
+}
+
+///查找重复文件的结果
+pub struct Duplicates {
+    pub groups: Vec<DuplicateGroup>,
+    ///没有其他副本、只是互为硬链接的文件，每组的第一个路径是比较时使用的那个
+    pub hardlinks: Vec<Vec<PathBuf>>,
+}
+
+/*
+在搜索结果中查找重复文件（--duplicates），逐步缩小候选范围以减少读取量：
+1.按文件大小分组，大小不同的文件不可能重复。
+2.对大小相同的文件，只计算开头 PARTIAL_HASH_LEN 字节的哈希再分组。
+3.对开头相同的文件，计算整个文件的哈希，最终确定重复的文件。
+哈希由 config.threads 个线程并行计算。指向同一个 inode 的硬链接只参与一次比较，
+其余路径记录在所属的组中，或者单独列出。空文件会被忽略。
+*/
+pub fn find_duplicates(mut entries: Vec<DirEntry>, config: &Config) -> Duplicates {
+    entries.sort();
+
+    let mut by_len: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+    //inode -> (参与比较的路径, 其他硬链接)
+    let mut inodes: HashMap<(u64, u64), (PathBuf, Vec<PathBuf>)> = HashMap::new();
+    for entry in entries {
+        if !entry.file_type().is_some_and(|ft| ft.is_file()) {
+            continue;
+        }
+        let Some(metadata) = entry.metadata() else {
+            continue;
+        };
+        let len = metadata.len();
+        if len == 0 {
+            continue;
+        }
+        let id = file_id(metadata);
+        let path = entry.into_stripped_path(config);
+        if let Some(id) = id {
+            if let Some((_, links)) = inodes.get_mut(&id) {
+                links.push(path);
+                continue;
+            }
+            inodes.insert(id, (path.clone(), Vec::new()));
+        }
+        by_len.entry(len).or_default().push(path);
+    }
+    let mut links: HashMap<PathBuf, Vec<PathBuf>> = inodes
+        .into_values()
+        .filter(|(_, links)| !links.is_empty())
+        .collect();
+
+    let candidates = by_len
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .flat_map(|(len, paths)| paths.into_iter().map(move |path| (len, path)))
+        .collect();
+    let mut groups = Vec::new();
+    let mut full = Vec::new();
+    for ((len, partial_hash), paths) in group_by_hash(candidates, Some(PARTIAL_HASH_LEN), config) {
+        //文件不超过 PARTIAL_HASH_LEN 时，部分哈希就是完整哈希
+        if len <= PARTIAL_HASH_LEN {
+            groups.push((len, partial_hash, paths));
+        } else {
+            full.extend(paths.into_iter().map(|path| (len, path)));
+        }
+    }
+    for ((len, hash), paths) in group_by_hash(full, None, config) {
+        groups.push((len, hash, paths));
+    }
+
+    let mut groups: Vec<_> = groups
+        .into_iter()
+        .map(|(len, hash, mut paths)| {
+            paths.sort();
+            let links = paths
+                .iter()
+                .map(|path| links.remove(path).unwrap_or_default())
+                .collect();
+            DuplicateGroup {
+                len,
+                hash,
+                paths,
+                links,
+            }
+        })
+        .collect();
+    groups.sort_by(|a, b| {
+        b.wasted_bytes()
+            .cmp(&a.wasted_bytes())
+            .then_with(|| a.paths.cmp(&b.paths))
+    });
+
+    let mut hardlinks: Vec<_> = links
+        .into_iter()
+        .map(|(path, links)| [vec![path], links].concat())
+        .collect();
+    hardlinks.sort();
+    Duplicates { groups, hardlinks }
+}
+
+//按（大小, 哈希值）分组，只保留至少包含两个文件的组。无法读取的文件会被跳过。
+fn group_by_hash(
+    files: Vec<(u64, PathBuf)>,
+    limit: Option<u64>,
+    config: &Config,
+) -> Vec<((u64, blake3::Hash), Vec<PathBuf>)> {
+    let hashes = hash_files(&files, limit, config.threads);
+    let mut by_hash: HashMap<(u64, blake3::Hash), Vec<PathBuf>> = HashMap::new();
+    for ((len, path), hash) in files.into_iter().zip(hashes) {
+        match hash {
+            Ok(hash) => by_hash.entry((len, hash)).or_default().push(path),
+            Err(err) => print_error(format!("无法读取'{}': {}", path.display(), err)),
+        }
+    }
+    by_hash
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .collect()
+}
+
+//用最多 threads 个线程计算哈希，结果与 files 的顺序一致
+fn hash_files(
+    files: &[(u64, PathBuf)],
+    limit: Option<u64>,
+    threads: usize,
+) -> Vec<io::Result<blake3::Hash>> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new((0..files.len()).map(|_| None).collect::<Vec<_>>());
+    thread::scope(|scope| {
+        for _ in 0..threads.clamp(1, files.len().max(1)) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some((_, path)) = files.get(i) else {
+                    break;
+                };
+                let hash = hash_file(path, limit);
+                results.lock().unwrap()[i] = Some(hash);
+            });
+        }
+    });
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|hash| hash.expect("每个文件都计算过哈希"))
+        .collect()
+}
+
+//计算文件内容的 BLAKE3 哈希，limit 为 Some 时只读取开头的部分
+fn hash_file(path: &Path, limit: Option<u64>) -> io::Result<blake3::Hash> {
+    let mut file = File::open(path)?;
+    let mut hasher = blake3::Hasher::new();
+    match limit {
+        Some(limit) => io::copy(&mut (&file).take(limit), &mut hasher)?,
+        None => io::copy(&mut file, &mut hasher)?,
+    };
+    Ok(hasher.finalize())
+}
+
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+pub fn print_duplicates<W: Write>(
+    w: &mut W,
+    entries: Vec<DirEntry>,
+    config: &Config,
+    format: ReportFormat,
+) -> io::Result<()> {
+    let duplicates = find_duplicates(entries, config);
+    match format {
+        ReportFormat::Text => print_text(w, &duplicates),
+        ReportFormat::Json => print_json(w, &duplicates),
+    }
+}
+
+fn print_text<W: Write>(w: &mut W, duplicates: &Duplicates) -> io::Result<()> {
+    let groups = &duplicates.groups;
+    let mut total_wasted = 0;
+    for group in groups {
+        total_wasted += group.wasted_bytes();
+        writeln!(
+            w,
+            "# {} files, {} each, {} wasted",
+            group.paths.len(),
+            format_bytes(group.len),
+            format_bytes(group.wasted_bytes()),
+        )?;
+        for (path, links) in group.paths.iter().zip(&group.links) {
+            writeln!(w, "{}", path.to_string_lossy())?;
+            for link in links {
+                writeln!(w, "  = {} (hardlink)", link.to_string_lossy())?;
+            }
+        }
+        writeln!(w)?;
+    }
+    for paths in &duplicates.hardlinks {
+        writeln!(w, "# {} hardlinks to the same file", paths.len())?;
+        for path in paths {
+            writeln!(w, "{}", path.to_string_lossy())?;
+        }
+        writeln!(w)?;
+    }
+    writeln!(
+        w,
+        "# {} groups, {} wasted",
+        groups.len(),
+        format_bytes(total_wasted)
+    )
+}
+
+fn print_json<W: Write>(w: &mut W, duplicates: &Duplicates) -> io::Result<()> {
+    let lossy = |paths: &[PathBuf]| {
+        paths
+            .iter()
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+    };
+    let groups = &duplicates.groups;
+    let report = json!({
+        "groups": groups
+            .iter()
+            .map(|group| json!({
+                "size": group.len,
+                "blake3": group.hash.to_hex().as_str(),
+                "wasted_bytes": group.wasted_bytes(),
+                "paths": lossy(&group.paths),
+                "hardlinks": group
+                    .paths
+                    .iter()
+                    .zip(&group.links)
+                    .filter(|(_, links)| !links.is_empty())
+                    .map(|(path, links)| (path.to_string_lossy().into_owned(), json!(lossy(links))))
+                    .collect::<serde_json::Map<_, _>>(),
+            }))
+            .collect::<Vec<_>>(),
+        "hardlinks": duplicates.hardlinks.iter().map(|paths| lossy(paths)).collect::<Vec<_>>(),
+        "wasted_bytes": groups.iter().map(DuplicateGroup::wasted_bytes).sum::<u64>(),
+    });
+    writeln!(w, "{report}")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::test_util::{create_tree, search};
+
+    //把 paths 转换为相对于 root 的字符串
+    fn relative(root: &Path, paths: &[PathBuf]) -> Vec<String> {
+        paths
+            .iter()
+            .map(|path| path.strip_prefix(root).unwrap().display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn groups_files_with_same_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        create_tree(root, &["a", "b", "c", "empty1", "empty2"]);
+        fs::write(root.join("a"), "same").unwrap();
+        fs::write(root.join("b"), "same").unwrap();
+        fs::write(root.join("c"), "diff").unwrap();
+        fs::write(root.join("empty1"), "").unwrap();
+        fs::write(root.join("empty2"), "").unwrap();
+        //超过 PARTIAL_HASH_LEN 且只有结尾不同的文件需要比较完整的哈希
+        let long = vec![b'x'; PARTIAL_HASH_LEN as usize + 1];
+        fs::write(root.join("long1"), &long).unwrap();
+        fs::write(root.join("long2"), &long).unwrap();
+        fs::write(root.join("long3"), [&long[1..], b"y"].concat()).unwrap();
+
+        let config = Config {
+            threads: 3,
+            ..Config::default()
+        };
+        let duplicates = find_duplicates(search(root, "", Config::default()), &config);
+        let groups: Vec<_> = duplicates
+            .groups
+            .iter()
+            .map(|group| relative(root, &group.paths))
+            .collect();
+        assert_eq!(groups, [vec!["long1", "long2"], vec!["a", "b"]]);
+        assert!(duplicates.hardlinks.is_empty());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn lists_hardlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        create_tree(root, &["a", "b", "c", "d"]);
+        fs::write(root.join("a"), "same").unwrap();
+        fs::write(root.join("b"), "same").unwrap();
+        fs::hard_link(root.join("a"), root.join("a2")).unwrap();
+        fs::hard_link(root.join("c"), root.join("c2")).unwrap();
+
+        let duplicates = find_duplicates(search(root, "", Config::default()), &Config::default());
+        assert_eq!(duplicates.groups.len(), 1);
+        let group = &duplicates.groups[0];
+        assert_eq!(relative(root, &group.paths), ["a", "b"]);
+        assert_eq!(relative(root, &group.links[0]), ["a2"]);
+        assert!(group.links[1].is_empty());
+        assert_eq!(group.wasted_bytes(), 4);
+        let hardlinks: Vec<_> = duplicates
+            .hardlinks
+            .iter()
+            .map(|paths| relative(root, paths))
+            .collect();
+        assert_eq!(hardlinks, [vec!["c", "c2"]]);
+    }
+}
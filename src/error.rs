@@ -1,3 +1,7 @@
 pub fn print_error(msg: impl Into<String>) {
     eprintln!("[file-find error]: {}", msg.into())
 }
+
+pub fn print_warning(msg: impl Into<String>) {
+    eprintln!("[file-find warning]: {}", msg.into())
+}
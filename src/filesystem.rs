@@ -76,6 +76,10 @@ pub fn is_existing_directory(path: &Path) -> bool {
 }
 
 pub fn is_empty(entry: &dir_entry::DirEntry) -> bool {
+    //归档中的目录是否为空需要扫描整个归档，这里只判断文件
+    if entry.archive_member().is_some() {
+        return entry.is_file() && entry.size() == Some(0);
+    }
     if let Some(file_type) = entry.file_type() {
         if file_type.is_dir() {
             if let Ok(mut entries) = fs::read_dir(entry.path()) {
@@ -84,7 +88,7 @@ pub fn is_empty(entry: &dir_entry::DirEntry) -> bool {
                 false
             }
         } else if file_type.is_file() {
-            entry.metedata().map(|m| m.len() == 0).unwrap_or(false)
+            entry.metadata().map(|m| m.len() == 0).unwrap_or(false)
         } else {
             false
         }
@@ -154,3 +158,54 @@ pub fn defaault_path_separator() -> Option<String> {
     }
     None
 }
+
+///存放索引等缓存文件的目录：$XDG_CACHE_HOME/file-find，默认为 ~/.cache/file-find
+pub fn cache_dir() -> Option<PathBuf> {
+    let cache_home = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))?;
+    Some(cache_home.join("file-find"))
+}
+
+/*
+根据 uid/gid 查找用户名和组名。查找需要读取 /etc/passwd 等数据库，
+同一个 id 往往会被查询很多次，所以把结果缓存起来。找不到名称时返回数字形式的 id。
+*/
+#[cfg(unix)]
+pub fn user_name(uid: u32) -> String {
+    use nix::unistd::{Uid, User};
+    use std::{collections::HashMap, sync::Mutex, sync::OnceLock};
+
+    static USERS: OnceLock<Mutex<HashMap<u32, String>>> = OnceLock::new();
+
+    let mut users = USERS.get_or_init(Default::default).lock().unwrap();
+    users
+        .entry(uid)
+        .or_insert_with(|| {
+            User::from_uid(Uid::from_raw(uid))
+                .ok()
+                .flatten()
+                .map_or_else(|| uid.to_string(), |user| user.name)
+        })
+        .clone()
+}
+
+#[cfg(unix)]
+pub fn group_name(gid: u32) -> String {
+    use nix::unistd::{Gid, Group};
+    use std::{collections::HashMap, sync::Mutex, sync::OnceLock};
+
+    static GROUPS: OnceLock<Mutex<HashMap<u32, String>>> = OnceLock::new();
+
+    let mut groups = GROUPS.get_or_init(Default::default).lock().unwrap();
+    groups
+        .entry(gid)
+        .or_insert_with(|| {
+            Group::from_gid(Gid::from_raw(gid))
+                .ok()
+                .flatten()
+                .map_or_else(|| gid.to_string(), |group| group.name)
+        })
+        .clone()
+}
@@ -1,5 +1,7 @@
 use faccess::PathExt;
 
+use crate::archives::MemberKind;
+use crate::cli;
 use crate::dir_entry;
 use crate::filesystem;
 
@@ -17,7 +19,50 @@ pub struct FileType {
 }
 
 impl FileType {
+    ///根据 --type 的取值构造
+    pub fn from_values(values: &[cli::FileType]) -> Self {
+        use crate::cli::FileType::*;
+        let mut file_types = FileType::default();
+        for value in values {
+            match value {
+                File => file_types.files = true,
+                Directory => file_types.directories = true,
+                Symlink => file_types.symlibks = true,
+                BlockDevice => file_types.block_devices = true,
+                CharDevice => file_types.chat_devices = true,
+                Socket => file_types.sockets = true,
+                Pipe => file_types.pipes = true,
+                Executable => {
+                    file_types.executables_only = true;
+                    file_types.files = true;
+                }
+                Empty => file_types.empty_only = true,
+            }
+        }
+
+        //只指定了 --type empty 时，同时匹配空文件和空目录
+        if file_types.empty_only && !(file_types.files || file_types.directories) {
+            file_types.files = true;
+            file_types.directories = true;
+        }
+
+        file_types
+    }
+
     pub fn should_ignore(&self, entry: &dir_entry::DirEntry) -> bool {
+        //归档和索引中的条目使用记录下来的类型和权限位，不读取文件本身的元数据。
+        // 索引中记录为其他类型的条目仍然读取元数据来区分设备、套接字和管道
+        if let Some(member) = entry
+            .recorded()
+            .filter(|member| member.kind != MemberKind::Other)
+        {
+            return (!self.files && entry.is_file())
+                || (!self.directories && entry.is_dir())
+                || (!self.symlibks && entry.is_symlink())
+                || (self.executables_only
+                    && !(entry.is_file() && member.mode.is_some_and(|mode| mode & 0o111 != 0)))
+                || (self.empty_only && !filesystem::is_empty(entry));
+        }
         if let Some(ref entry_type) = entry.file_type() {
             (!self.files && entry_type.is_file())
                 || (!self.directories && entry_type.is_dir())
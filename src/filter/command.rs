@@ -0,0 +1,440 @@
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use tempfile::NamedTempFile;
+
+use crate::dir_entry::DirEntry;
+use crate::error::{print_error, print_warning};
+use crate::filesystem;
+use crate::fmt::FormatTemplate;
+
+//没有指定 --filter-timeout 时每条命令的超时时间
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+//等待命令结束时检查的间隔，从短到长逐渐增加
+const MIN_POLL_INTERVAL: Duration = Duration::from_millis(2);
+const MAX_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+//缓存的结果超过这么多条时，保存时只保留本次用到的
+const MAX_CACHE_ENTRIES: usize = 0x40000;
+
+/*
+用外部命令过滤（--filter-exec）：对每个候选条目执行一次命令，退出码为 0 时保留该条目。
+1.命令的参数是格式模板，可以使用 --format 的占位符；没有任何占位符时把路径作为最后一个参数。
+2.命令在工作线程中执行，不同条目的命令并行运行；命令的标准输入、输出和错误都被丢弃。
+3.超过 timeout 仍未结束的命令被终止，条目不保留。
+4.启用缓存时，以命令行、文件的绝对路径、大小和修改时间作为键记录结果，
+  下次搜索时文件没有变化就直接使用记录的结果。超时和无法执行的结果不记录。
+*/
+pub struct CommandFilter {
+    command: Vec<FormatTemplate>,
+    timeout: Duration,
+    cache: Option<ResultCache>,
+    //命令无法启动时只报告一次
+    spawn_failed: AtomicBool,
+}
+
+struct ResultCache {
+    path: PathBuf,
+    results: Mutex<HashMap<[u8; 16], CachedResult>>,
+}
+
+#[derive(Clone, Copy)]
+struct CachedResult {
+    passed: bool,
+    //本次搜索是否用到，缓存太大时只保留用到的
+    used: bool,
+}
+
+impl CommandFilter {
+    ///cache 为缓存文件的路径，None 表示不使用缓存
+    pub fn new(
+        command: &[String],
+        timeout: Option<Duration>,
+        cache: Option<PathBuf>,
+    ) -> Result<Self> {
+        let mut command = command
+            .iter()
+            .map(|arg| FormatTemplate::parse(arg))
+            .collect::<Result<Vec<_>>>()?;
+        if !command.iter().any(FormatTemplate::has_tokens) {
+            command.push(FormatTemplate::parse("{}")?);
+        }
+        Ok(Self {
+            command,
+            timeout: timeout.unwrap_or(DEFAULT_TIMEOUT),
+            cache: cache.map(ResultCache::load),
+            spawn_failed: AtomicBool::new(false),
+        })
+    }
+
+    pub fn matches(&self, entry: &DirEntry) -> bool {
+        let path = entry.path();
+        let args: Vec<OsString> = self
+            .command
+            .iter()
+            .map(|arg| arg.generate(path, entry, None))
+            .collect();
+
+        let key = self.cache.as_ref().and_then(|_| cache_key(entry, &args));
+        if let (Some(cache), Some(key)) = (&self.cache, key) {
+            if let Some(passed) = cache.get(&key) {
+                return passed;
+            }
+        }
+
+        let Some(passed) = self.run(&args) else {
+            return false;
+        };
+        if let (Some(cache), Some(key)) = (&self.cache, key) {
+            cache.insert(key, passed);
+        }
+        passed
+    }
+
+    ///把缓存写回缓存文件，没有使用缓存时什么也不做
+    pub fn save_cache(&self) -> Result<()> {
+        match self.cache {
+            Some(ref cache) => cache.save(),
+            None => Ok(()),
+        }
+    }
+
+    //执行命令并等待结束，返回是否成功。超时或者无法执行时返回 None
+    fn run(&self, args: &[OsString]) -> Option<bool> {
+        let (program, args) = args.split_first()?;
+        let mut child = match Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(err) => {
+                if !self.spawn_failed.swap(true, Ordering::Relaxed) {
+                    print_error(format!("无法执行'{}': {}", program.to_string_lossy(), err));
+                }
+                return None;
+            }
+        };
+
+        let deadline = Instant::now() + self.timeout;
+        let mut interval = MIN_POLL_INTERVAL;
+        loop {
+            match child.try_wait() {
+                Ok(Some(status)) => return Some(status.success()),
+                Ok(None) => {}
+                Err(_) => {
+                    kill(&mut child);
+                    return None;
+                }
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                kill(&mut child);
+                print_warning(format!(
+                    "命令'{} {}'超过 {} 仍未结束，已终止",
+                    program.to_string_lossy(),
+                    args.iter()
+                        .map(|arg| arg.to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    humantime::format_duration(self.timeout)
+                ));
+                return None;
+            }
+            thread::sleep(interval.min(deadline - now));
+            interval = (interval * 2).min(MAX_POLL_INTERVAL);
+        }
+    }
+}
+
+//终止并回收子进程，不留下僵尸进程
+fn kill(child: &mut Child) {
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+//命令行、绝对路径、大小和修改时间的哈希。读不到元数据时不使用缓存
+fn cache_key(entry: &DirEntry, args: &[OsString]) -> Option<[u8; 16]> {
+    let metadata = entry.metadata()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    let path = filesystem::path_absolute_form(entry.path()).ok()?;
+
+    let mut hasher = blake3::Hasher::new();
+    for arg in args {
+        hasher.update(arg.as_encoded_bytes());
+        hasher.update(b"\0");
+    }
+    hasher.update(path.as_os_str().as_encoded_bytes());
+    hasher.update(b"\0");
+    hasher.update(&metadata.len().to_le_bytes());
+    hasher.update(&modified.as_nanos().to_le_bytes());
+
+    let mut key = [0; 16];
+    key.copy_from_slice(&hasher.finalize().as_bytes()[..16]);
+    Some(key)
+}
+
+/*
+缓存文件是文本格式，每行一条记录：32 个十六进制字符的键，空格，1 表示通过、0 表示不通过。
+无法解析的行被忽略，缓存文件不存在或者无法读取时从空的缓存开始。
+*/
+impl ResultCache {
+    fn load(path: PathBuf) -> Self {
+        let mut results = HashMap::new();
+        match File::open(&path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines().map_while(Result::ok) {
+                    if let Some((key, passed)) = parse_line(&line) {
+                        results.insert(
+                            key,
+                            CachedResult {
+                                passed,
+                                used: false,
+                            },
+                        );
+                    }
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => print_warning(format!("无法读取缓存文件'{}': {}", path.display(), err)),
+        }
+        Self {
+            path,
+            results: Mutex::new(results),
+        }
+    }
+
+    fn get(&self, key: &[u8; 16]) -> Option<bool> {
+        let mut results = self.results.lock().unwrap();
+        let cached = results.get_mut(key)?;
+        cached.used = true;
+        Some(cached.passed)
+    }
+
+    fn insert(&self, key: [u8; 16], passed: bool) {
+        self.results
+            .lock()
+            .unwrap()
+            .insert(key, CachedResult { passed, used: true });
+    }
+
+    //先写入同一目录中名称唯一的临时文件再改名，中途失败不会破坏原来的缓存，
+    // 同时运行的多个搜索也不会互相覆盖临时文件
+    fn save(&self) -> Result<()> {
+        let results = self.results.lock().unwrap();
+        let keep_unused = results.len() <= MAX_CACHE_ENTRIES;
+
+        let parent = match self.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        fs::create_dir_all(parent)
+            .with_context(|| format!("无法创建目录'{}'", parent.display()))?;
+
+        let write = || -> io::Result<()> {
+            let mut temp = NamedTempFile::new_in(parent)?;
+            let mut w = BufWriter::new(temp.as_file_mut());
+            for (key, cached) in results.iter() {
+                if keep_unused || cached.used {
+                    for byte in key {
+                        write!(w, "{byte:02x}")?;
+                    }
+                    writeln!(w, " {}", u8::from(cached.passed))?;
+                }
+            }
+            w.flush()?;
+            drop(w);
+            temp.persist(&self.path)?;
+            Ok(())
+        };
+        write().with_context(|| format!("无法写入缓存文件'{}'", self.path.display()))
+    }
+}
+
+fn parse_line(line: &str) -> Option<([u8; 16], bool)> {
+    let (hex, passed) = line.split_once(' ')?;
+    if hex.len() != 32 {
+        return None;
+    }
+    let mut key = [0; 16];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    let passed = match passed {
+        "1" => true,
+        "0" => false,
+        _ => return None,
+    };
+    Some((key, passed))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::test_util::{create_tree, search};
+
+    //root 中通过 filter 的文件名，按字典序排列
+    fn passed(root: &Path, filter: &CommandFilter) -> Vec<String> {
+        search(root, "", Config::default())
+            .into_iter()
+            .filter(|entry| entry.is_file() && filter.matches(entry))
+            .map(|entry| {
+                entry
+                    .path()
+                    .strip_prefix(root)
+                    .unwrap()
+                    .display()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    fn filter(
+        command: &[&str],
+        timeout: Option<Duration>,
+        cache: Option<PathBuf>,
+    ) -> CommandFilter {
+        let command: Vec<String> = command.iter().map(|arg| arg.to_string()).collect();
+        CommandFilter::new(&command, timeout, cache).unwrap()
+    }
+
+    #[test]
+    fn keeps_entries_whose_command_succeeds() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        //文件内容就是它的路径
+        create_tree(root, &["a.keep", "b.drop", "sub/c.keep"]);
+
+        assert_eq!(
+            passed(root, &filter(&["grep", "-q", "keep", "{}"], None, None)),
+            ["a.keep", "sub/c.keep"]
+        );
+        //没有占位符时把路径作为最后一个参数
+        assert_eq!(
+            passed(root, &filter(&["grep", "-q", "drop"], None, None)),
+            ["b.drop"]
+        );
+        assert!(passed(root, &filter(&["no-such-command-for-test"], None, None)).is_empty());
+    }
+
+    #[test]
+    fn kills_commands_that_time_out() {
+        let dir = tempfile::tempdir().unwrap();
+        create_tree(dir.path(), &["a"]);
+        let started = Instant::now();
+        let filter = filter(
+            &["sh", "-c", "sleep 10"],
+            Some(Duration::from_millis(50)),
+            None,
+        );
+        assert!(passed(dir.path(), &filter).is_empty());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn parses_cache_lines() {
+        let key: [u8; 16] = std::array::from_fn(|i| i as u8 * 17);
+        let hex: String = key.iter().map(|byte| format!("{byte:02x}")).collect();
+        assert_eq!(parse_line(&format!("{hex} 1")), Some((key, true)));
+        assert_eq!(parse_line(&format!("{hex} 0")), Some((key, false)));
+        assert_eq!(parse_line(&format!("{hex} 2")), None);
+        assert_eq!(parse_line(&format!("{hex}1")), None);
+        assert_eq!(parse_line(&format!("{} 1", &hex[2..])), None);
+        assert_eq!(parse_line(&format!("{}zz 1", &hex[2..])), None);
+        assert_eq!(parse_line(&format!("{}é 1", &hex[1..])), None);
+    }
+
+    #[test]
+    fn cached_results_skip_the_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        create_tree(&root, &["a"]);
+        let runs = dir.path().join("runs");
+        let cache = dir.path().join("cache/results");
+        //每执行一次命令就在 runs 中追加一行
+        let script = format!("echo run >> '{}'", runs.display());
+        let command = ["sh", "-c", &script];
+        let run_count = || fs::read_to_string(&runs).map_or(0, |runs| runs.lines().count());
+
+        let first = filter(&command, None, Some(cache.clone()));
+        assert_eq!(passed(&root, &first), ["a"]);
+        first.save_cache().unwrap();
+        assert_eq!(run_count(), 1);
+        //缓存目录中只有缓存文件，没有留下临时文件
+        assert_eq!(fs::read_dir(cache.parent().unwrap()).unwrap().count(), 1);
+
+        let second = filter(&command, None, Some(cache.clone()));
+        assert_eq!(passed(&root, &second), ["a"]);
+        assert_eq!(run_count(), 1);
+
+        //文件变化后重新执行
+        fs::write(root.join("a"), "changed").unwrap();
+        assert_eq!(passed(&root, &second), ["a"]);
+        assert_eq!(run_count(), 2);
+
+        //超时的结果不记录
+        let timeout = filter(
+            &["sh", "-c", "sleep 10"],
+            Some(Duration::from_millis(50)),
+            Some(cache.clone()),
+        );
+        let before = timeout
+            .cache
+            .as_ref()
+            .unwrap()
+            .results
+            .lock()
+            .unwrap()
+            .len();
+        assert!(passed(&root, &timeout).is_empty());
+        assert_eq!(
+            timeout
+                .cache
+                .as_ref()
+                .unwrap()
+                .results
+                .lock()
+                .unwrap()
+                .len(),
+            before
+        );
+    }
+
+    #[test]
+    fn concurrent_saves_do_not_clobber_each_other() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = dir.path().join("results");
+        let caches: Vec<ResultCache> = (0..8u8)
+            .map(|i| {
+                let cache = ResultCache::load(cache.clone());
+                cache.insert([i; 16], i % 2 == 0);
+                cache
+            })
+            .collect();
+        thread::scope(|scope| {
+            for cache in &caches {
+                scope.spawn(|| cache.save().unwrap());
+            }
+        });
+        let saved = ResultCache::load(cache);
+        assert_eq!(saved.results.lock().unwrap().len(), 1);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,75 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::Path,
+};
+
+use anyhow::anyhow;
+use regex::bytes::{Regex, RegexBuilder};
+
+//只检查文件开头这么多字节来判断是否为二进制文件
+const BINARY_DETECTION_LEN: usize = 8 * 1024;
+
+/*
+按文件内容过滤（--contains）。
+文件以流的方式逐行读取，找到第一处匹配就停止，因此正则表达式不能跨行匹配。
+默认跳过二进制文件（开头包含 NUL 字节的文件）。
+*/
+#[derive(Clone, Debug)]
+pub struct ContentFilter {
+    regex: Regex,
+    max_scan_bytes: Option<u64>,
+    search_binary: bool,
+}
+
+impl ContentFilter {
+    pub fn new(
+        pattern: &str,
+        case_sensitive: bool,
+        max_scan_bytes: Option<u64>,
+        search_binary: bool,
+    ) -> anyhow::Result<Self> {
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(!case_sensitive)
+            .multi_line(true)
+            .build()
+            .map_err(|e| anyhow!("'{}'不是有效的正则表达式: {}", pattern, e))?;
+
+        Ok(Self {
+            regex,
+            max_scan_bytes,
+            search_binary,
+        })
+    }
+
+    ///返回文件中第一处匹配所在的行号（从 1 开始）。
+    /// 没有匹配、文件无法读取，或者是被跳过的二进制文件时返回 None。
+    pub fn matching_line(&self, path: &Path) -> Option<u64> {
+        let file = File::open(path).ok()?;
+        let limit = self.max_scan_bytes.unwrap_or(u64::MAX);
+        let mut reader = BufReader::new(file.take(limit));
+
+        if !self.search_binary {
+            let head = reader.fill_buf().ok()?;
+            let head = &head[..head.len().min(BINARY_DETECTION_LEN)];
+            if head.contains(&0) {
+                return None;
+            }
+        }
+
+        let mut line = Vec::new();
+        let mut line_number = 0;
+        loop {
+            line.clear();
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) | Err(_) => return None,
+                Ok(_) => {
+                    line_number += 1;
+                    if self.regex.is_match(&line) {
+                        return Some(line_number);
+                    }
+                }
+            }
+        }
+    }
+}
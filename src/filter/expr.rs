@@ -0,0 +1,522 @@
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+use std::time::SystemTime;
+
+use anyhow::{anyhow, bail, Result};
+use clap::ValueEnum;
+use regex::bytes::Regex;
+
+use crate::cli;
+use crate::dir_entry::DirEntry;
+use crate::filetypes::FileType;
+#[cfg(unix)]
+use crate::filter::{OwnerFilter, PermFilter};
+use crate::filter::{SizeFilter, TimeFilter};
+use crate::regex_helper;
+
+/*
+过滤表达式，写法与 find 的表达式类似：
+1.条件：-name 正则、-path 正则、-type 类型、-size 大小、-changed-within 时间、
+  -changed-before 时间、-owner 所有者、-perm 权限（最后两个仅 Unix）。
+2.运算：! 或 -not 表示取反，-a 或 -and 表示并且（可以省略），-o 或 -or 表示或者，
+  优先级依次降低，可以用括号分组。括号和运算符需要用空格与其他部分隔开。
+例如 ( -name '\.rs$' -size +10k -o -name '\.toml$' -changed-within 1d ) ! -path /tests/
+*/
+pub struct FilterExpr {
+    root: ExprNode,
+    //解析时确定表达式中是否有 -print 等输出动作和 -prune，求值时不需要再遍历整个表达式
+    has_output: bool,
+    has_prune: bool,
+}
+
+//表达式中的一个节点
+pub(crate) enum ExprNode {
+    Test(Predicate),
+    Not(Box<ExprNode>),
+    And(Vec<ExprNode>),
+    Or(Vec<ExprNode>),
+    //find 的 -print、-print0 和 -exec：表达式中有这类节点时，
+    // 只有求值经过它们的条目才作为结果，而不是整个表达式为真的条目
+    Output,
+    //find 的 -prune：总是为真，求值经过它的目录不再深入
+    Prune,
+}
+
+///表达式中的单个条件
+pub enum Predicate {
+    //文件名匹配正则表达式
+    Name(Regex),
+    //遍历得到的路径匹配正则表达式
+    Path(Regex),
+    Type(FileType),
+    Size(SizeFilter),
+    Time(TimeFilter),
+    #[cfg(unix)]
+    Owner(OwnerFilter),
+    #[cfg(unix)]
+    Perm(PermFilter),
+}
+
+///对一个条目求值的结果
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Verdict {
+    //条目是否作为结果
+    pub matched: bool,
+    //是否不再深入该目录
+    pub prune: bool,
+}
+
+impl FilterExpr {
+    ///解析一个字符串形式的表达式，参数可以用单引号或双引号括起来
+    pub fn from_string(input: &str) -> Result<Self> {
+        let tokens = split_words(input)?;
+        Self::parse(&tokens)
+    }
+
+    ///解析已经拆分好的表达式
+    pub fn parse<S: AsRef<str>>(tokens: &[S]) -> Result<Self> {
+        let tokens: Vec<&str> = tokens.iter().map(AsRef::as_ref).collect();
+        let root = parse_with(
+            &tokens,
+            &mut ExprDialect {
+                now: SystemTime::now(),
+            },
+        )?;
+        Ok(Self::new(root))
+    }
+
+    pub(crate) fn new(root: ExprNode) -> Self {
+        Self {
+            has_output: root.contains(&|node| matches!(node, ExprNode::Output)),
+            has_prune: root.contains(&|node| matches!(node, ExprNode::Prune)),
+            root,
+        }
+    }
+
+    pub fn matches(&self, entry: &DirEntry) -> bool {
+        self.evaluate(entry).matched
+    }
+
+    pub fn evaluate(&self, entry: &DirEntry) -> Verdict {
+        let mut verdict = Verdict::default();
+        let result = self.root.eval(entry, &mut verdict);
+        if !self.has_output {
+            verdict.matched = result;
+        }
+        verdict
+    }
+
+    ///表达式中是否有 -prune，没有时不需要为了它提前求值
+    pub fn has_prune(&self) -> bool {
+        self.has_prune
+    }
+}
+
+impl ExprNode {
+    fn eval(&self, entry: &DirEntry, verdict: &mut Verdict) -> bool {
+        match self {
+            ExprNode::Test(predicate) => predicate.matches(entry),
+            ExprNode::Not(expr) => !expr.eval(entry, verdict),
+            ExprNode::And(exprs) => exprs.iter().all(|expr| expr.eval(entry, verdict)),
+            ExprNode::Or(exprs) => exprs.iter().any(|expr| expr.eval(entry, verdict)),
+            ExprNode::Output => {
+                verdict.matched = true;
+                true
+            }
+            ExprNode::Prune => {
+                verdict.prune = true;
+                true
+            }
+        }
+    }
+
+    fn contains(&self, pred: &impl Fn(&ExprNode) -> bool) -> bool {
+        pred(self)
+            || match self {
+                ExprNode::Not(expr) => expr.contains(pred),
+                ExprNode::And(exprs) | ExprNode::Or(exprs) => {
+                    exprs.iter().any(|expr| expr.contains(pred))
+                }
+                _ => false,
+            }
+    }
+
+    //求值的代价：0 只需要路径，1 需要文件类型，2 需要读取元数据。
+    // 元数据在 DirEntry 中只读取一次，所以组合的代价取最大值而不是总和
+    fn cost(&self) -> u8 {
+        match self {
+            ExprNode::Test(predicate) => predicate.cost(),
+            ExprNode::Not(expr) => expr.cost(),
+            ExprNode::And(exprs) | ExprNode::Or(exprs) => {
+                exprs.iter().map(ExprNode::cost).max().unwrap_or(0)
+            }
+            ExprNode::Output | ExprNode::Prune => 0,
+        }
+    }
+
+    /*
+    整理表达式以减少求值的开销：
+    1.把嵌套的同类运算展开，例如 a -a ( b -a c ) 变成 a -a b -a c。
+    2.同一层的子表达式按代价从低到高排序，名称不匹配时就不需要再读取元数据。
+    条件没有副作用，所以调整顺序不影响结果；含有 -print、-prune 等动作的层保持原来的顺序。
+    */
+    fn optimize(self) -> Self {
+        match self {
+            ExprNode::Not(expr) => ExprNode::Not(Box::new(expr.optimize())),
+            ExprNode::And(exprs) => {
+                let mut flat = Vec::with_capacity(exprs.len());
+                for expr in exprs {
+                    match expr.optimize() {
+                        ExprNode::And(inner) => flat.extend(inner),
+                        expr => flat.push(expr),
+                    }
+                }
+                sort_by_cost(&mut flat);
+                ExprNode::And(flat)
+            }
+            ExprNode::Or(exprs) => {
+                let mut flat = Vec::with_capacity(exprs.len());
+                for expr in exprs {
+                    match expr.optimize() {
+                        ExprNode::Or(inner) => flat.extend(inner),
+                        expr => flat.push(expr),
+                    }
+                }
+                sort_by_cost(&mut flat);
+                ExprNode::Or(flat)
+            }
+            _ => self,
+        }
+    }
+}
+
+fn sort_by_cost(exprs: &mut [ExprNode]) {
+    let has_actions = exprs
+        .iter()
+        .any(|expr| expr.contains(&|expr| matches!(expr, ExprNode::Output | ExprNode::Prune)));
+    if !has_actions {
+        exprs.sort_by_key(ExprNode::cost);
+    }
+}
+
+impl Predicate {
+    pub fn matches(&self, entry: &DirEntry) -> bool {
+        match self {
+            Predicate::Name(regex) => entry
+                .path()
+                .file_name()
+                .is_some_and(|name| regex.is_match(name.as_encoded_bytes())),
+            Predicate::Path(regex) => regex.is_match(entry.path().as_os_str().as_encoded_bytes()),
+            Predicate::Type(file_types) => !file_types.should_ignore(entry),
+            Predicate::Size(filter) => {
+                entry.is_file() && entry.size().is_some_and(|size| filter.is_within(size))
+            }
+            Predicate::Time(filter) => entry
+                .modified()
+                .is_some_and(|modified| filter.applies_to(&modified)),
+            #[cfg(unix)]
+            Predicate::Owner(filter) => entry.metadata().is_some_and(|m| filter.matches(m)),
+            #[cfg(unix)]
+            Predicate::Perm(filter) => entry
+                .recorded()
+                .and_then(|member| member.mode)
+                .or_else(|| entry.metadata().map(|m| m.mode()))
+                .is_some_and(|mode| filter.matches(mode)),
+        }
+    }
+
+    fn cost(&self) -> u8 {
+        match self {
+            Predicate::Name(_) | Predicate::Path(_) => 0,
+            Predicate::Type(file_types)
+                if !(file_types.executables_only || file_types.empty_only) =>
+            {
+                1
+            }
+            _ => 2,
+        }
+    }
+}
+
+///表达式中条件部分的写法。--expr 和 find-compat 的条件不同，共用同一套运算符和括号
+pub(crate) trait Dialect {
+    ///解析以 test 开头的条件，参数从 tokens 中读取
+    fn primary(&mut self, test: &str, tokens: &mut Tokens<'_>) -> Result<ExprNode>;
+}
+
+pub(crate) struct Tokens<'a> {
+    tokens: &'a [&'a str],
+    pos: usize,
+}
+
+impl<'a> Tokens<'a> {
+    pub(crate) fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).copied()
+    }
+
+    pub(crate) fn next(&mut self) -> Option<&'a str> {
+        let token = self.peek()?;
+        self.pos += 1;
+        Some(token)
+    }
+
+    ///读取条件的参数
+    pub(crate) fn argument(&mut self, test: &str) -> Result<&'a str> {
+        self.next().ok_or_else(|| anyhow!("条件'{test}'缺少参数"))
+    }
+}
+
+///按 dialect 中的条件解析表达式，并整理成便于求值的形式
+pub(crate) fn parse_with<D: Dialect>(tokens: &[&str], dialect: &mut D) -> Result<ExprNode> {
+    if tokens.is_empty() {
+        bail!("过滤表达式不能为空");
+    }
+    let mut parser = Parser {
+        tokens: Tokens { tokens, pos: 0 },
+        dialect,
+    };
+    let expr = parser.or()?;
+    if let Some(token) = parser.tokens.peek() {
+        bail!("过滤表达式在'{token}'处有多余的内容");
+    }
+    Ok(expr.optimize())
+}
+
+/*
+递归下降解析，优先级从低到高：
+or   := and ( (-o | -or) and )*
+and  := not ( [-a | -and] not )*
+not  := (! | -not) not | primary
+primary := ( or ) | 条件
+*/
+struct Parser<'a, 'd, D> {
+    tokens: Tokens<'a>,
+    dialect: &'d mut D,
+}
+
+impl<D: Dialect> Parser<'_, '_, D> {
+    fn or(&mut self) -> Result<ExprNode> {
+        let mut exprs = vec![self.and()?];
+        while matches!(self.tokens.peek(), Some("-o" | "-or")) {
+            self.tokens.pos += 1;
+            exprs.push(self.and()?);
+        }
+        Ok(if exprs.len() == 1 {
+            exprs.pop().unwrap()
+        } else {
+            ExprNode::Or(exprs)
+        })
+    }
+
+    fn and(&mut self) -> Result<ExprNode> {
+        let mut exprs = vec![self.not()?];
+        loop {
+            match self.tokens.peek() {
+                None | Some("-o" | "-or" | ")") => break,
+                Some("-a" | "-and") => self.tokens.pos += 1,
+                //两个条件之间省略了 -a
+                Some(_) => {}
+            }
+            exprs.push(self.not()?);
+        }
+        Ok(if exprs.len() == 1 {
+            exprs.pop().unwrap()
+        } else {
+            ExprNode::And(exprs)
+        })
+    }
+
+    fn not(&mut self) -> Result<ExprNode> {
+        if matches!(self.tokens.peek(), Some("!" | "-not")) {
+            self.tokens.pos += 1;
+            return Ok(ExprNode::Not(Box::new(self.not()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<ExprNode> {
+        let token = self
+            .tokens
+            .next()
+            .ok_or_else(|| anyhow!("过滤表达式不完整，末尾缺少条件"))?;
+        match token {
+            "(" => {
+                let expr = self.or()?;
+                if self.tokens.next() != Some(")") {
+                    bail!("过滤表达式中的括号不匹配");
+                }
+                Ok(expr)
+            }
+            ")" => bail!("过滤表达式中的括号不匹配"),
+            "-o" | "-or" | "-a" | "-and" => bail!("'{token}'前面缺少条件"),
+            _ => self.dialect.primary(token, &mut self.tokens),
+        }
+    }
+}
+
+//--expr 的条件
+struct ExprDialect {
+    //-changed-within 等相对时间的参照时间
+    now: SystemTime,
+}
+
+impl Dialect for ExprDialect {
+    fn primary(&mut self, test: &str, tokens: &mut Tokens<'_>) -> Result<ExprNode> {
+        let predicate = match test {
+            "-name" => Predicate::Name(regex_helper::build_regex(tokens.argument(test)?)?),
+            "-path" => Predicate::Path(regex_helper::build_regex(tokens.argument(test)?)?),
+            "-type" => Predicate::Type(file_types_from(tokens.argument(test)?)?),
+            "-size" => Predicate::Size(SizeFilter::from_string(tokens.argument(test)?)?),
+            "-changed-within" => {
+                let arg = tokens.argument(test)?;
+                Predicate::Time(TimeFilter::after(&self.now, arg).ok_or_else(|| invalid_time(arg))?)
+            }
+            "-changed-before" => {
+                let arg = tokens.argument(test)?;
+                Predicate::Time(
+                    TimeFilter::before(&self.now, arg).ok_or_else(|| invalid_time(arg))?,
+                )
+            }
+            #[cfg(unix)]
+            "-owner" => Predicate::Owner(OwnerFilter::from_string(tokens.argument(test)?)?),
+            #[cfg(unix)]
+            "-perm" => Predicate::Perm(PermFilter::from_string(tokens.argument(test)?)?),
+            _ => bail!("'{test}'不是过滤表达式中已知的条件"),
+        };
+        Ok(ExprNode::Test(predicate))
+    }
+}
+
+fn invalid_time(arg: &str) -> anyhow::Error {
+    anyhow!(
+        "'{}'不是有效的日期或时间长度。输入file-find -h获取帮助",
+        arg
+    )
+}
+
+//-type 的参数与 --type 相同，多个类型用逗号分隔，例如 f,l
+pub(crate) fn file_types_from(arg: &str) -> Result<FileType> {
+    let values = arg
+        .split(',')
+        .map(|name| {
+            cli::FileType::from_str(name, true).map_err(|_| anyhow!("'{name}'不是有效的文件类型"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(FileType::from_values(&values))
+}
+
+//按空白拆分表达式。引号内的空白不拆分；正则表达式中经常出现 \，
+// 所以 \ 只在引号和空白前面表示转义，其他情况下原样保留
+fn split_words(input: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\\', _)
+                if chars
+                    .peek()
+                    .is_some_and(|&c| c == '\'' || c == '"' || c.is_whitespace()) =>
+            {
+                word.get_or_insert_with(String::new).extend(chars.next());
+            }
+            (c, None) if c.is_whitespace() => words.extend(word.take()),
+            ('\'' | '"', None) => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (c, Some(q)) if c == q => quote = None,
+            (c, _) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        bail!("过滤表达式中的引号不匹配");
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::Config,
+        test_util::{create_tree, search},
+    };
+
+    //在 a.rs、b.rs、b.txt、c.txt 中按表达式过滤，返回通过的文件名
+    fn filter(expr: &str) -> Vec<String> {
+        let dir = tempfile::tempdir().unwrap();
+        create_tree(dir.path(), &["a.rs", "b.rs", "b.txt", "c.txt"]);
+        let config = Config {
+            filter_expr: Some(FilterExpr::from_string(expr).unwrap()),
+            ..Default::default()
+        };
+        search(dir.path(), "", config)
+            .iter()
+            .map(|entry| {
+                entry
+                    .path()
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect()
+    }
+
+    fn costs(expr: &str) -> Vec<u8> {
+        match FilterExpr::from_string(expr).unwrap().root {
+            ExprNode::And(nodes) | ExprNode::Or(nodes) => {
+                nodes.iter().map(ExprNode::cost).collect()
+            }
+            node => vec![node.cost()],
+        }
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            filter("-name ^a -o -name ^b -a -name txt$"),
+            ["a.rs", "b.txt"]
+        );
+        assert_eq!(filter("-name ^a -o -name ^b -name txt$"), ["a.rs", "b.txt"]);
+        assert_eq!(filter("! -name ^a -name rs$"), ["b.rs"]);
+        assert_eq!(filter("-not -name ^a -and -not -name txt$"), ["b.rs"]);
+    }
+
+    #[test]
+    fn parentheses_group_operands() {
+        assert_eq!(filter("( -name ^a -o -name ^b ) -name txt$"), ["b.txt"]);
+        assert_eq!(filter("! ( -name ^a -o -name txt$ )"), ["b.rs"]);
+        assert!(FilterExpr::from_string("( -name a").is_err());
+        assert!(FilterExpr::from_string("-name a )").is_err());
+        assert!(FilterExpr::from_string("-name a -o").is_err());
+        assert!(FilterExpr::from_string("-o -name a").is_err());
+    }
+
+    #[test]
+    fn split_words_handles_quotes_and_escapes() {
+        assert_eq!(
+            split_words(r#"-name 'a b' -path "x y" -name a\ b -name \d+\.rs"#).unwrap(),
+            ["-name", "a b", "-path", "x y", "-name", "a b", "-name", r"\d+\.rs"]
+        );
+        assert_eq!(
+            split_words(r#"-name '' -name "it's""#).unwrap(),
+            ["-name", "", "-name", "it's"]
+        );
+        assert_eq!(split_words(r"-name \'x").unwrap(), ["-name", "'x"]);
+        assert!(split_words("-name 'a").is_err());
+    }
+
+    #[test]
+    fn cheaper_conditions_are_evaluated_first() {
+        assert_eq!(costs("-size +1k -type f -name x"), [0, 1, 2]);
+        assert_eq!(costs("-size +1k -o -name x"), [0, 2]);
+        //嵌套的同类运算展开到同一层后一起排序
+        assert_eq!(costs("-size +1k ( -name x -type d )"), [0, 1, 2]);
+        //调整顺序不改变结果
+        assert_eq!(filter("-size -1k -name ^b"), ["b.rs", "b.txt"]);
+    }
+}
@@ -0,0 +1,490 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{anyhow, Context, Result};
+use git2::{DiffFindOptions, Oid, Repository, RevparseMode, Status, StatusOptions};
+
+use crate::dir_entry::DirEntry;
+
+/*
+按 git 状态过滤时可以选择的状态（--git-status）：
+1.Modified：工作目录中有未暂存的修改（包括删除和类型变化）。
+2.Staged：暂存区中有尚未提交的修改。
+3.Untracked：未被跟踪的新文件。
+4.Ignored：被 .gitignore 等规则忽略的文件，需要同时使用 --no-ignore 才会遍历到。
+5.Conflicted：合并时有冲突、尚未解决的文件。
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum GitStatus {
+    Modified,
+    Staged,
+    Untracked,
+    Ignored,
+    Conflicted,
+}
+
+impl GitStatus {
+    fn flags(self) -> Status {
+        match self {
+            GitStatus::Modified => {
+                Status::WT_MODIFIED
+                    | Status::WT_DELETED
+                    | Status::WT_TYPECHANGE
+                    | Status::WT_RENAMED
+            }
+            GitStatus::Staged => {
+                Status::INDEX_NEW
+                    | Status::INDEX_MODIFIED
+                    | Status::INDEX_DELETED
+                    | Status::INDEX_RENAMED
+                    | Status::INDEX_TYPECHANGE
+            }
+            GitStatus::Untracked => Status::WT_NEW,
+            GitStatus::Ignored => Status::IGNORED,
+            GitStatus::Conflicted => Status::CONFLICTED,
+        }
+    }
+}
+
+/*
+按 git 仓库中的状态和历史过滤（--git-status、--git-tracked、--git-changed-since、--git-changed-in）：
+1.每个搜索路径所在的仓库在搜索开始前读取一次索引、工作目录的状态和需要的提交，
+  只访问本地文件和对象库，之后每个条目只需要查表。
+  搜索路径可以是仓库中的任意子目录，不同的搜索路径可以属于不同的仓库。
+2.目录中有处于所选状态或者被改动过的条目时，目录本身也算处于该状态；
+  整个未跟踪或被忽略的目录中的条目与目录的状态相同。
+3.指定了多个状态时满足其一即可，同时指定的其他 git 条件都需要满足。
+*/
+pub struct GitFilter {
+    statuses: Status,
+    tracked: bool,
+    //是否只保留指定的提交中改动过的条目
+    changed: bool,
+    roots: Vec<RepoRoot>,
+}
+
+struct RepoRoot {
+    root: PathBuf,
+    //搜索路径相对于工作目录的位置，条目的路径去掉搜索路径后接在它后面
+    prefix: PathBuf,
+    //同一个仓库中的多个搜索路径共用读取的状态
+    repo: Arc<RepoState>,
+}
+
+//一个仓库中与过滤有关的状态，路径都相对于仓库的工作目录
+struct RepoState {
+    //有状态的文件以及包含它们的目录
+    statuses: HashMap<PathBuf, Status>,
+    //git 只报告目录本身的未跟踪或被忽略的目录，其中的条目与目录的状态相同
+    whole_dirs: HashMap<PathBuf, Status>,
+    //索引中的文件以及包含它们的目录，只在 --git-tracked 时读取
+    tracked: HashSet<PathBuf>,
+    //指定的提交中改动过的文件以及包含它们的目录，只在指定了提交范围时读取
+    changed: HashSet<PathBuf>,
+}
+
+impl GitFilter {
+    ///revisions 为提交（例如 HEAD~2）或者提交范围（例如 A..B、A...B），
+    /// 只保留其中的提交改动过的条目，None 表示不按历史过滤
+    pub fn new(
+        roots: &[PathBuf],
+        statuses: &[GitStatus],
+        tracked: bool,
+        revisions: Option<&str>,
+    ) -> Result<Self> {
+        let statuses = statuses
+            .iter()
+            .fold(Status::empty(), |flags, status| flags | status.flags());
+        let mut loaded: HashMap<PathBuf, Arc<RepoState>> = HashMap::new();
+        let mut repo_roots = Vec::with_capacity(roots.len());
+        for root in roots {
+            let absolute = root
+                .canonicalize()
+                .with_context(|| format!("无法访问搜索路径'{}'", root.display()))?;
+            let repo = Repository::discover(&absolute)
+                .map_err(|_| anyhow!("搜索路径'{}'不在 git 仓库中", root.display()))?;
+            let workdir = repo
+                .workdir()
+                .ok_or_else(|| anyhow!("git 仓库'{}'没有工作目录", repo.path().display()))?
+                .canonicalize()?;
+            let prefix = absolute
+                .strip_prefix(&workdir)
+                .unwrap_or(Path::new(""))
+                .to_path_buf();
+
+            let repo = match loaded.get(&workdir) {
+                Some(state) => Arc::clone(state),
+                None => {
+                    let state = Arc::new(RepoState::load(&repo, statuses, tracked, revisions)?);
+                    loaded.insert(workdir, Arc::clone(&state));
+                    state
+                }
+            };
+            repo_roots.push(RepoRoot {
+                root: root.clone(),
+                prefix,
+                repo,
+            });
+        }
+        Ok(Self {
+            statuses,
+            tracked,
+            changed: revisions.is_some(),
+            roots: repo_roots,
+        })
+    }
+
+    pub fn matches(&self, entry: &DirEntry) -> bool {
+        //搜索路径互相嵌套时取最长的那个
+        let Some(root) = self
+            .roots
+            .iter()
+            .filter(|root| entry.path().starts_with(&root.root))
+            .max_by_key(|root| root.root.as_os_str().len())
+        else {
+            return false;
+        };
+        let Ok(relative) = entry.path().strip_prefix(&root.root) else {
+            return false;
+        };
+        let path = root.prefix.join(relative);
+        let repo = &root.repo;
+
+        if !self.statuses.is_empty() && !repo.status(&path).intersects(self.statuses) {
+            return false;
+        }
+        if self.changed && !repo.changed.contains(&path) {
+            return false;
+        }
+        !self.tracked || repo.tracked.contains(&path)
+    }
+}
+
+impl RepoState {
+    fn load(
+        repo: &Repository,
+        wanted: Status,
+        tracked: bool,
+        revisions: Option<&str>,
+    ) -> Result<Self> {
+        let context = || format!("无法读取 git 仓库'{}'的状态", repo.path().display());
+        let mut state = Self {
+            statuses: HashMap::new(),
+            whole_dirs: HashMap::new(),
+            tracked: HashSet::new(),
+            changed: HashSet::new(),
+        };
+
+        if !wanted.is_empty() {
+            //不展开未跟踪和被忽略的目录，避免遍历 target 之类的大目录。
+            // 工作目录中的重命名要和未跟踪的文件配对才能检测出来，所以同样需要未跟踪的文件
+            let renamed = wanted.intersects(Status::INDEX_RENAMED | Status::WT_RENAMED);
+            let mut options = StatusOptions::new();
+            options
+                .include_untracked(wanted.intersects(Status::WT_NEW | Status::WT_RENAMED))
+                .recurse_untracked_dirs(false)
+                .include_ignored(wanted.intersects(Status::IGNORED))
+                .recurse_ignored_dirs(false)
+                .renames_head_to_index(renamed)
+                .renames_index_to_workdir(renamed);
+            for entry in repo
+                .statuses(Some(&mut options))
+                .with_context(context)?
+                .iter()
+            {
+                let bytes = entry.path_bytes();
+                if bytes.ends_with(b"/") {
+                    state
+                        .whole_dirs
+                        .insert(path_from_bytes(bytes), entry.status());
+                }
+                //重命名的条目的路径是原来的路径，现在的路径要从比较结果中取得
+                let new_paths = [entry.head_to_index(), entry.index_to_workdir()]
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|delta| delta.new_file().path().map(Path::to_path_buf));
+                for path in std::iter::once(path_from_bytes(bytes)).chain(new_paths) {
+                    for dir in path.ancestors() {
+                        *state
+                            .statuses
+                            .entry(dir.to_path_buf())
+                            .or_insert(Status::empty()) |= entry.status();
+                    }
+                }
+            }
+        }
+
+        if tracked {
+            for entry in repo.index().with_context(context)?.iter() {
+                insert_with_parents(&mut state.tracked, &path_from_bytes(&entry.path));
+            }
+        }
+        if let Some(revisions) = revisions {
+            for path in changed_paths(repo, revisions).with_context(|| {
+                format!(
+                    "无法读取 git 仓库'{}'中'{}'的改动",
+                    repo.path().display(),
+                    revisions
+                )
+            })? {
+                insert_with_parents(&mut state.changed, &path);
+            }
+        }
+        Ok(state)
+    }
+
+    fn status(&self, path: &Path) -> Status {
+        let own = self.statuses.get(path).copied().unwrap_or(Status::empty());
+        let inherited = path
+            .ancestors()
+            .skip(1)
+            .find_map(|dir| self.whole_dirs.get(dir))
+            .copied()
+            .unwrap_or(Status::empty());
+        own | inherited
+    }
+}
+
+/*
+提交或提交范围中的提交改动过的文件路径：
+1.单个提交：只有这个提交；A..B：从 B 可以到达但从 A 不能到达的提交；
+  A...B：从 A 或 B 可以到达但从它们的共同祖先不能到达的提交。
+2.每个提交与它的父提交比较，重命名的文件同时记录原来和现在的路径。
+3.提交范围中合并提交的改动来自被合并的提交，这些提交已经分别比较过，所以跳过合并提交本身；
+  单个的合并提交与它的第一个父提交比较，得到合并进来的全部改动。
+*/
+fn changed_paths(repo: &Repository, revisions: &str) -> Result<Vec<PathBuf>> {
+    let spec = repo.revparse(revisions)?;
+    let commit_id = |object: Option<&git2::Object<'_>>| -> Result<Oid> {
+        let object = object.ok_or_else(|| anyhow!("'{revisions}'不是有效的提交范围"))?;
+        Ok(object.peel_to_commit()?.id())
+    };
+
+    let single = spec.mode().contains(RevparseMode::SINGLE);
+    let commits = if single {
+        vec![commit_id(spec.from())?]
+    } else {
+        let (from, to) = (commit_id(spec.from())?, commit_id(spec.to())?);
+        let mut walk = repo.revwalk()?;
+        walk.push(to)?;
+        if spec.mode().contains(RevparseMode::MERGE_BASE) {
+            walk.push(from)?;
+            walk.hide(repo.merge_base(from, to)?)?;
+        } else {
+            walk.hide(from)?;
+        }
+        walk.collect::<Result<Vec<_>, _>>()?
+    };
+
+    let mut paths = Vec::new();
+    for id in commits {
+        let commit = repo.find_commit(id)?;
+        let parent = match commit.parent_count() {
+            0 => None,
+            1 => Some(commit.parent(0)?.tree()?),
+            _ if single => Some(commit.parent(0)?.tree()?),
+            _ => continue,
+        };
+        let mut diff = repo.diff_tree_to_tree(parent.as_ref(), Some(&commit.tree()?), None)?;
+        diff.find_similar(Some(DiffFindOptions::new().renames(true)))?;
+        for delta in diff.deltas() {
+            paths.extend(delta.old_file().path().map(Path::to_path_buf));
+            paths.extend(delta.new_file().path().map(Path::to_path_buf));
+        }
+    }
+    Ok(paths)
+}
+
+//记录路径以及包含它的各级目录
+fn insert_with_parents(paths: &mut HashSet<PathBuf>, path: &Path) {
+    for dir in path.ancestors() {
+        if !paths.insert(dir.to_path_buf()) {
+            break;
+        }
+    }
+}
+
+//git 中的路径总是以 / 分隔
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+    PathBuf::from(OsStr::from_bytes(bytes.strip_suffix(b"/").unwrap_or(bytes)))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    let bytes = bytes.strip_suffix(b"/").unwrap_or(bytes);
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use git2::{Commit, Signature};
+
+    use super::*;
+    use crate::{config::Config, test_util::search};
+
+    //把 files 写入工作目录并提交，parents 为空时使用 HEAD，提交后 HEAD 指向新的提交
+    fn commit(repo: &Repository, files: &[(&str, &str)], parents: &[&Commit<'_>]) -> Oid {
+        let workdir = repo.workdir().unwrap();
+        let mut index = repo.index().unwrap();
+        for (name, content) in files {
+            let path = workdir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, content).unwrap();
+            index.add_path(Path::new(name)).unwrap();
+        }
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("test", "test@example.com").unwrap();
+        let head = repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+        let parents = match parents {
+            [] => head.iter().collect(),
+            parents => parents.to_vec(),
+        };
+        let id = repo
+            .commit(None, &signature, &signature, "test", &tree, &parents)
+            .unwrap();
+        repo.set_head_detached(id).unwrap();
+        id
+    }
+
+    fn changed(repo: &Repository, revisions: &str) -> Vec<String> {
+        let mut paths: Vec<String> = changed_paths(repo, revisions)
+            .unwrap()
+            .iter()
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect();
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+
+    #[test]
+    fn changed_paths_of_commits_and_ranges() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let first = commit(&repo, &[("a.txt", "a"), ("src/b.rs", "b")], &[]);
+        commit(&repo, &[("src/b.rs", "b2")], &[]);
+        commit(&repo, &[("c.txt", "c")], &[]);
+
+        assert_eq!(changed(&repo, "HEAD~1"), ["src/b.rs"]);
+        assert_eq!(
+            changed(&repo, &format!("{first}..HEAD")),
+            ["c.txt", "src/b.rs"]
+        );
+        assert_eq!(changed(&repo, &first.to_string()), ["a.txt", "src/b.rs"]);
+    }
+
+    #[test]
+    fn merge_commit_is_compared_with_its_first_parent() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let base = repo
+            .find_commit(commit(&repo, &[("a.txt", "a")], &[]))
+            .unwrap();
+        let side = repo
+            .find_commit(commit(&repo, &[("side.txt", "side")], &[&base]))
+            .unwrap();
+        //主线上的提交不包含 side.txt，合并时再加入
+        let mut index = repo.index().unwrap();
+        index.remove_path(Path::new("side.txt")).unwrap();
+        index.write().unwrap();
+        fs::remove_file(dir.path().join("side.txt")).unwrap();
+        let main = repo
+            .find_commit(commit(&repo, &[("main.txt", "main")], &[&base]))
+            .unwrap();
+        commit(&repo, &[("side.txt", "side")], &[&main, &side]);
+
+        assert_eq!(changed(&repo, "HEAD"), ["side.txt"]);
+        assert_eq!(
+            changed(&repo, &format!("{}..HEAD", base.id())),
+            ["main.txt", "side.txt"]
+        );
+    }
+
+    //root 中通过过滤的条目相对于 root 的路径，不包括 .git 中的条目
+    fn matched(filter: &GitFilter, root: &Path) -> Vec<String> {
+        search(root, "", Config::default())
+            .iter()
+            .filter(|entry| !entry.path().components().any(|c| c.as_os_str() == ".git"))
+            .filter(|entry| filter.matches(entry))
+            .map(|entry| {
+                let relative = entry.path().strip_prefix(root).unwrap();
+                relative.to_string_lossy().into_owned()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn renames_in_index_and_workdir() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let repo = Repository::init(root).unwrap();
+        commit(
+            &repo,
+            &[("staged.txt", "staged"), ("moved.txt", "moved")],
+            &[],
+        );
+
+        fs::rename(root.join("staged.txt"), root.join("renamed.txt")).unwrap();
+        let mut index = repo.index().unwrap();
+        index.remove_path(Path::new("staged.txt")).unwrap();
+        index.add_path(Path::new("renamed.txt")).unwrap();
+        index.write().unwrap();
+        fs::rename(root.join("moved.txt"), root.join("new.txt")).unwrap();
+
+        let roots = [root.to_path_buf()];
+        let staged = GitFilter::new(&roots, &[GitStatus::Staged], false, None).unwrap();
+        assert_eq!(matched(&staged, root), ["renamed.txt"]);
+        let modified = GitFilter::new(&roots, &[GitStatus::Modified], false, None).unwrap();
+        assert_eq!(matched(&modified, root), ["new.txt"]);
+    }
+
+    #[test]
+    fn search_root_in_subdirectory() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let repo = Repository::init(root).unwrap();
+        commit(
+            &repo,
+            &[("top.txt", "top"), ("sub/a.txt", "a"), ("sub/b.txt", "b")],
+            &[],
+        );
+        fs::write(root.join("top.txt"), "top changed").unwrap();
+        fs::write(root.join("sub/a.txt"), "a changed").unwrap();
+
+        let sub = root.join("sub");
+        let filter = GitFilter::new(
+            std::slice::from_ref(&sub),
+            &[GitStatus::Modified],
+            false,
+            None,
+        )
+        .unwrap();
+        assert_eq!(matched(&filter, &sub), ["a.txt"]);
+    }
+
+    #[test]
+    fn search_roots_in_different_repositories() {
+        let dir = tempfile::tempdir().unwrap();
+        let (first, second) = (dir.path().join("first"), dir.path().join("second"));
+        let first_repo = Repository::init(&first).unwrap();
+        let second_repo = Repository::init(&second).unwrap();
+        //两个仓库中有相同的相对路径，状态却不同
+        commit(&first_repo, &[("a.txt", "a"), ("b.txt", "b")], &[]);
+        commit(&second_repo, &[("a.txt", "a"), ("b.txt", "b")], &[]);
+        fs::write(first.join("a.txt"), "a changed").unwrap();
+        fs::write(second.join("b.txt"), "b changed").unwrap();
+
+        let roots = [first.clone(), second.clone()];
+        let filter = GitFilter::new(&roots, &[GitStatus::Modified], false, None).unwrap();
+        assert_eq!(matched(&filter, &first), ["a.txt"]);
+        assert_eq!(matched(&filter, &second), ["b.txt"]);
+    }
+}
@@ -1,10 +1,23 @@
-pub use self::size::SizeFilter;
+pub use self::command::CommandFilter;
+pub use self::contents::ContentFilter;
+pub(crate) use self::expr::{file_types_from, parse_with, Dialect, ExprNode, Tokens};
+pub use self::expr::{FilterExpr, Predicate, Verdict};
+pub use self::git::{GitFilter, GitStatus};
+pub use self::size::{format_bytes, parse_size, SizeFilter};
 pub use self::time::TimeFilter;
 
 #[cfg(unix)]
 pub use self::owner::OwnerFilter;
+#[cfg(unix)]
+pub use self::perm::PermFilter;
 
+mod command;
+mod contents;
+mod expr;
+mod git;
 #[cfg(unix)]
 mod owner;
+#[cfg(unix)]
+mod perm;
 mod size;
 mod time;
@@ -1,6 +1,91 @@
+use std::fs::Metadata;
+use std::os::unix::fs::MetadataExt;
+
+use anyhow::{anyhow, Result};
+use nix::unistd::{Group, User};
+
 //只针对Unix系统
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct OwnerFilter {
     uid: Check<u32>,
     gid: Check<u32>,
 }
+
+/*
+对 uid 或 gid 的检查：
+1.Equal：必须等于给定的值。
+2.NotEq：必须不等于给定的值（写作 !user）。
+3.Ignore：不检查。
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Check<T> {
+    Equal(T),
+    NotEq(T),
+    Ignore,
+}
+
+impl OwnerFilter {
+    ///解析 [user][:group] 形式的所有者条件，user 和 group 可以是名称或数字 id，
+    /// 前面加 ! 表示排除，例如 john、:staff、!root、1000:!100
+    pub fn from_string(input: &str) -> Result<Self> {
+        let (user, group) = match input.split_once(':') {
+            Some((user, group)) => (user, group),
+            None => (input, ""),
+        };
+        let uid = Check::parse(user, |name| {
+            User::from_name(name)
+                .ok()
+                .flatten()
+                .map(|user| user.uid.as_raw())
+        })
+        .map_err(|name| anyhow!("'{name}'不是已知的用户"))?;
+        let gid = Check::parse(group, |name| {
+            Group::from_name(name)
+                .ok()
+                .flatten()
+                .map(|group| group.gid.as_raw())
+        })
+        .map_err(|name| anyhow!("'{name}'不是已知的用户组"))?;
+
+        if uid == Check::Ignore && gid == Check::Ignore {
+            return Err(anyhow!(
+                "'{input}'不是有效的所有者条件，至少需要指定用户或用户组"
+            ));
+        }
+        Ok(Self { uid, gid })
+    }
+
+    pub fn matches(&self, metadata: &Metadata) -> bool {
+        self.uid.check(metadata.uid()) && self.gid.check(metadata.gid())
+    }
+}
+
+impl Check<u32> {
+    //空字符串表示不检查。解析失败时返回无法识别的名称
+    fn parse(s: &str, lookup: impl FnOnce(&str) -> Option<u32>) -> Result<Self, String> {
+        let (negated, name) = match s.strip_prefix('!') {
+            Some(name) => (true, name),
+            None => (false, s),
+        };
+        if name.is_empty() {
+            return Ok(Check::Ignore);
+        }
+        let id = match name.parse() {
+            Ok(id) => id,
+            Err(_) => lookup(name).ok_or_else(|| name.to_owned())?,
+        };
+        Ok(if negated {
+            Check::NotEq(id)
+        } else {
+            Check::Equal(id)
+        })
+    }
+
+    fn check(&self, id: u32) -> bool {
+        match *self {
+            Check::Equal(expected) => id == expected,
+            Check::NotEq(unexpected) => id != unexpected,
+            Check::Ignore => true,
+        }
+    }
+}
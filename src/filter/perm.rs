@@ -0,0 +1,86 @@
+use anyhow::{anyhow, Result};
+
+//只针对Unix系统
+/*
+权限位条件，写法与 find 的 -perm 相同：
+1.Exact：权限位必须与给定的完全相同，例如 644、u=rw,go=r。
+2.All：必须包含给定的所有权限位（写作 -mode），例如 -u+x。
+3.Any：至少包含给定的一个权限位（写作 /mode），例如 /111。
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PermFilter {
+    Exact(u32),
+    All(u32),
+    Any(u32),
+}
+
+impl PermFilter {
+    ///解析八进制（644）或符号形式（u=rw,g+x）的权限条件，前面可以加 - 或 /
+    pub fn from_string(input: &str) -> Result<Self> {
+        let (make, mode): (fn(u32) -> Self, &str) = if let Some(mode) = input.strip_prefix('-') {
+            (PermFilter::All, mode)
+        } else if let Some(mode) = input.strip_prefix('/') {
+            (PermFilter::Any, mode)
+        } else {
+            (PermFilter::Exact, input)
+        };
+        let bits = parse_octal(mode)
+            .or_else(|| parse_symbolic(mode))
+            .ok_or_else(|| anyhow!("'{input}'不是有效的权限条件，例如 644、-u+x、/111"))?;
+        Ok(make(bits))
+    }
+
+    pub fn matches(&self, mode: u32) -> bool {
+        let mode = mode & 0o7777;
+        match *self {
+            PermFilter::Exact(bits) => mode == bits,
+            PermFilter::All(bits) => mode & bits == bits,
+            //与 find 相同，/000 匹配所有条目
+            PermFilter::Any(bits) => bits == 0 || mode & bits != 0,
+        }
+    }
+}
+
+fn parse_octal(s: &str) -> Option<u32> {
+    if s.is_empty() || s.len() > 4 || !s.bytes().all(|b| (b'0'..=b'7').contains(&b)) {
+        return None;
+    }
+    u32::from_str_radix(s, 8).ok()
+}
+
+//逗号分隔的若干个 [ugoa]*[=+][rwxst]*，省略 ugoa 时等同于 a
+fn parse_symbolic(s: &str) -> Option<u32> {
+    let mut bits = 0;
+    for clause in s.split(',') {
+        let op = clause.find(['=', '+'])?;
+        let (who, perms) = (&clause[..op], &clause[op + 1..]);
+
+        let mut who_mask = 0;
+        for c in who.chars() {
+            who_mask |= match c {
+                'u' => 0o4700,
+                'g' => 0o2070,
+                'o' => 0o1007,
+                'a' => 0o7777,
+                _ => return None,
+            };
+        }
+        if who_mask == 0 {
+            who_mask = 0o7777;
+        }
+
+        let mut perm_mask = 0;
+        for c in perms.chars() {
+            perm_mask |= match c {
+                'r' => 0o444,
+                'w' => 0o222,
+                'x' => 0o111,
+                's' => 0o6000,
+                't' => 0o1000,
+                _ => return None,
+            };
+        }
+        bits |= who_mask & perm_mask;
+    }
+    Some(bits)
+}
@@ -75,3 +75,23 @@ impl SizeFilter {
         }
     }
 }
+
+//解析一个不带 +/- 前缀的大小，例如 "4k"、"1MiB"，返回字节数
+pub fn parse_size(s: &str) -> anyhow::Result<u64> {
+    match SizeFilter::parse_opt(s) {
+        Some(SizeFilter::Equals(size)) => Ok(size),
+        _ => Err(anyhow!("'{}'不是有效的大小。输入file-find -h获取帮助", s)),
+    }
+}
+
+//将字节数格式化为便于阅读的二进制单位，例如 1536 -> "1.5 KiB"
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [(u64, &str); 4] = [(TEBI, "TiB"), (GIBI, "GiB"), (MEBI, "MiB"), (KIBI, "KiB")];
+
+    for (size, unit) in UNITS {
+        if bytes >= size {
+            return format!("{:.1} {}", bytes as f64 / size as f64, unit);
+        }
+    }
+    format!("{bytes} B")
+}
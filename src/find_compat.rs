@@ -0,0 +1,440 @@
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, bail, Result};
+use regex::bytes::RegexBuilder;
+
+use crate::action::{Action, Exec};
+use crate::config::Config;
+use crate::error_codes::ExitCode;
+use crate::filter::{
+    file_types_from, parse_with, Dialect, ExprNode, FilterExpr, Predicate, SizeFilter, TimeFilter,
+    Tokens,
+};
+#[cfg(unix)]
+use crate::filter::{OwnerFilter, PermFilter};
+use crate::fmt::FormatTemplate;
+use crate::{filesystem, walk};
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+//不支持的条件给出的提示
+const SUPPORTED: &str =
+    "-name、-iname、-path、-type、-size、-mtime、-newer、-perm、-user、-group、\
+    -maxdepth、-prune、-print、-print0、-exec";
+
+/*
+find 兼容模式（file-find find-compat，或者以 find 的名字调用），方便迁移使用 GNU find 的脚本：
+1.参数的写法与 find 相同：开头是起始目录（默认为 .），然后是表达式。
+2.与 find 一样包括隐藏文件，不读取忽略文件，起始目录本身也作为结果，目录后面不加 /。
+3.-name、-iname、-path 使用通配符，-size、-mtime 的单位和取整方式与 find 相同。
+4.-exec 总是为真，命令在遍历结束后对全部结果执行，同一个表达式中只能有一个 -exec，
+  并且不能与 -print、-print0 同时使用。
+5.不支持的条件和选项直接报错，而不是被忽略。
+*/
+pub fn run(args: &[String]) -> Result<ExitCode> {
+    let split = args
+        .iter()
+        .position(|arg| arg.starts_with('-') || arg == "(" || arg == "!")
+        .unwrap_or(args.len());
+    let (paths, expression) = args.split_at(split);
+
+    let paths: Vec<PathBuf> = if paths.is_empty() {
+        vec![PathBuf::from(".")]
+    } else {
+        paths.iter().map(PathBuf::from).collect()
+    };
+    if let Some(path) = paths
+        .iter()
+        .find(|path| !filesystem::is_existing_directory(path))
+    {
+        bail!("起始路径'{}'不是一个目录", path.display());
+    }
+
+    let mut dialect = FindDialect {
+        now: SystemTime::now(),
+        max_depth: None,
+        print: false,
+        print0: false,
+        exec: None,
+    };
+    let expression: Vec<&str> = expression.iter().map(String::as_str).collect();
+    let expr = if expression.is_empty() {
+        ExprNode::Output
+    } else {
+        parse_with(&expression, &mut dialect)?
+    };
+    let expr = dialect.finish(expr)?;
+
+    let config = Config {
+        include_roots: true,
+        //只输出路径本身
+        format: Some(FormatTemplate::parse("{}")?),
+        ignore_hidden: false,
+        read_fdignore: false,
+        read_vcsignore: false,
+        max_depth: dialect.max_depth,
+        null_separator: dialect.print0,
+        filter_expr: Some(FilterExpr::new(expr)),
+        action: dialect.exec.map(Action::Exec),
+        dry_run: false,
+        ..Default::default()
+    };
+    config.validate()?;
+    walk::scan(&paths, Vec::new(), config)
+}
+
+struct FindDialect {
+    //-mtime 的参照时间
+    now: SystemTime,
+    max_depth: Option<usize>,
+    print: bool,
+    print0: bool,
+    exec: Option<Exec>,
+}
+
+impl Dialect for FindDialect {
+    fn primary(&mut self, test: &str, tokens: &mut Tokens<'_>) -> Result<ExprNode> {
+        let predicate = match test {
+            "-name" => Predicate::Name(glob_regex(tokens.argument(test)?, false)?),
+            "-iname" => Predicate::Name(glob_regex(tokens.argument(test)?, true)?),
+            "-path" => Predicate::Path(glob_regex(tokens.argument(test)?, false)?),
+            "-type" => Predicate::Type(file_types_from(tokens.argument(test)?)?),
+            "-size" => return find_size(tokens.argument(test)?),
+            "-mtime" => return self.find_mtime(tokens.argument(test)?),
+            "-newer" => {
+                let file = tokens.argument(test)?;
+                let modified = fs::metadata(file)
+                    .and_then(|metadata| metadata.modified())
+                    .map_err(|err| anyhow!("无法读取'{file}'的修改时间: {err}"))?;
+                Predicate::Time(TimeFilter::After(modified))
+            }
+            #[cfg(unix)]
+            "-perm" => Predicate::Perm(PermFilter::from_string(tokens.argument(test)?)?),
+            #[cfg(unix)]
+            "-user" => Predicate::Owner(OwnerFilter::from_string(tokens.argument(test)?)?),
+            #[cfg(unix)]
+            "-group" => Predicate::Owner(OwnerFilter::from_string(&format!(
+                ":{}",
+                tokens.argument(test)?
+            ))?),
+            "-maxdepth" => {
+                let depth = tokens.argument(test)?;
+                self.max_depth = Some(
+                    depth
+                        .parse()
+                        .map_err(|_| anyhow!("-maxdepth 的参数'{depth}'不是非负整数"))?,
+                );
+                //与 find 相同，选项在表达式中总是为真
+                return Ok(ExprNode::And(Vec::new()));
+            }
+            "-prune" => return Ok(ExprNode::Prune),
+            "-print" => {
+                self.print = true;
+                return Ok(ExprNode::Output);
+            }
+            "-print0" => {
+                self.print0 = true;
+                return Ok(ExprNode::Output);
+            }
+            "-exec" => {
+                if self.exec.is_some() {
+                    bail!("find-compat 的表达式中只能有一个 -exec");
+                }
+                let mut command = Vec::new();
+                let batch = loop {
+                    match tokens.next() {
+                        Some(";") => break false,
+                        Some("+") if command.last() == Some(&"{}") => break true,
+                        Some(arg) => command.push(arg),
+                        None => bail!("-exec 缺少结尾的 ; 或 +"),
+                    }
+                };
+                self.exec = Some(Exec::new(&command, batch)?);
+                return Ok(ExprNode::Output);
+            }
+            _ => bail!("find-compat 不支持'{test}'，支持的条件有 {SUPPORTED}"),
+        };
+        Ok(ExprNode::Test(predicate))
+    }
+}
+
+impl FindDialect {
+    //检查动作之间的冲突。表达式中没有动作时与 find 相同，相当于在末尾加上 -print
+    fn finish(&self, expr: ExprNode) -> Result<ExprNode> {
+        if self.print && self.print0 {
+            bail!("find-compat 不支持同时使用 -print 和 -print0");
+        }
+        if self.exec.is_some() && (self.print || self.print0) {
+            bail!("find-compat 不支持同时使用 -exec 和 -print、-print0");
+        }
+        if self.print || self.print0 || self.exec.is_some() {
+            return Ok(expr);
+        }
+        Ok(match expr {
+            ExprNode::Output => expr,
+            expr => ExprNode::And(vec![expr, ExprNode::Output]),
+        })
+    }
+
+    //-mtime n：修改时间距今的天数（舍去小数）等于 n，+n 表示大于 n，-n 表示小于 n
+    fn find_mtime(&self, arg: &str) -> Result<ExprNode> {
+        let (cmp, days) =
+            parse_number(arg).ok_or_else(|| anyhow!("'{arg}'不是有效的 -mtime 参数"))?;
+        let ago = |days: u64| {
+            self.now
+                .checked_sub(DAY.saturating_mul(days.try_into().unwrap_or(u32::MAX)))
+                .unwrap_or(SystemTime::UNIX_EPOCH)
+        };
+        let time = |filter| ExprNode::Test(Predicate::Time(filter));
+        Ok(match cmp {
+            Compare::Greater => time(TimeFilter::Before(ago(days + 1))),
+            Compare::Less => time(TimeFilter::After(ago(days))),
+            Compare::Equal => ExprNode::And(vec![
+                time(TimeFilter::Before(ago(days))),
+                time(TimeFilter::After(ago(days + 1))),
+            ]),
+        })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Compare {
+    Greater,
+    Less,
+    Equal,
+}
+
+//解析 find 的数字参数：+n、-n 或 n
+fn parse_number(arg: &str) -> Option<(Compare, u64)> {
+    let (cmp, number) = match arg.as_bytes().first()? {
+        b'+' => (Compare::Greater, &arg[1..]),
+        b'-' => (Compare::Less, &arg[1..]),
+        _ => (Compare::Equal, arg),
+    };
+    Some((cmp, number.parse().ok()?))
+}
+
+/*
+-size n[cwbkMG]：按单位向上取整后的大小与 n 比较，默认单位是 512 字节的块。
+转换成以字节为单位的 SizeFilter：
+1.+n：取整后大于 n，即大于 n 个单位。
+2.-n：取整后小于 n，即不超过 n-1 个单位。
+3.n：取整后等于 n，即大于 n-1 个单位并且不超过 n 个单位。
+与本工具的 --size 相同，只有文件参与大小比较。
+*/
+fn find_size(arg: &str) -> Result<ExprNode> {
+    let invalid = || anyhow!("'{arg}'不是有效的 -size 参数，例如 +10k、-1M、100c");
+    let (number, unit) = match arg.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => (&arg[..i], c),
+        _ => (arg, 'b'),
+    };
+    let unit: u64 = match unit {
+        'c' => 1,
+        'w' => 2,
+        'b' => 512,
+        'k' => 1024,
+        'M' => 1024 * 1024,
+        'G' => 1024 * 1024 * 1024,
+        _ => return Err(invalid()),
+    };
+    let (cmp, n) = parse_number(number).ok_or_else(invalid)?;
+    let size = |filter| ExprNode::Test(Predicate::Size(filter));
+    Ok(match (cmp, n) {
+        (Compare::Greater, n) => size(SizeFilter::Min(n.saturating_mul(unit) + 1)),
+        //取整后不可能小于 0
+        (Compare::Less, 0) => ExprNode::Or(Vec::new()),
+        (Compare::Less, n) => size(SizeFilter::MAX((n - 1).saturating_mul(unit))),
+        (Compare::Equal, 0) => size(SizeFilter::Equals(0)),
+        (Compare::Equal, n) => ExprNode::And(vec![
+            size(SizeFilter::Min((n - 1).saturating_mul(unit) + 1)),
+            size(SizeFilter::MAX(n.saturating_mul(unit))),
+        ]),
+    })
+}
+
+//把 find 的通配符转换为匹配整个名称或路径的正则表达式。与 find 相同，* 和 ? 也匹配 / 和开头的 .
+fn glob_regex(glob: &str, case_insensitive: bool) -> Result<regex::bytes::Regex> {
+    let mut regex = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '\\' => {
+                if let Some(c) = chars.next() {
+                    regex.push_str(&regex::escape(&c.to_string()));
+                }
+            }
+            '[' => {
+                //没有对应的 ] 时 [ 按普通字符处理
+                let class: String = chars.clone().take_while(|&c| c != ']').collect();
+                let closed = chars.clone().nth(class.chars().count()) == Some(']');
+                if !closed || class.is_empty() {
+                    regex.push_str(r"\[");
+                    continue;
+                }
+                for _ in 0..=class.chars().count() {
+                    chars.next();
+                }
+                let (negated, class) = match class.strip_prefix(['!', '^']) {
+                    Some(class) => (true, class),
+                    None => (false, class.as_str()),
+                };
+                regex.push('[');
+                if negated {
+                    regex.push('^');
+                }
+                for c in class.chars() {
+                    //- 保留为范围，其他在字符类中有特殊含义的字符需要转义
+                    if matches!(c, '\\' | '[' | ']' | '^' | '&' | '~') {
+                        regex.push('\\');
+                    }
+                    regex.push(c);
+                }
+                regex.push(']');
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    RegexBuilder::new(&regex)
+        .case_insensitive(case_insensitive)
+        .dot_matches_new_line(true)
+        .build()
+        .map_err(|e| anyhow!("'{}'不是有效的通配符: {}", glob, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, path::Path};
+
+    use super::*;
+    use crate::test_util::{create_tree, search};
+
+    fn dialect() -> FindDialect {
+        FindDialect {
+            now: SystemTime::now(),
+            max_depth: None,
+            print: false,
+            print0: false,
+            exec: None,
+        }
+    }
+
+    //按 find 的表达式在 root 中搜索，返回作为结果的条目相对于 root 的路径
+    fn find(root: &Path, expression: &str) -> Vec<String> {
+        let tokens: Vec<&str> = expression.split_whitespace().collect();
+        let mut dialect = dialect();
+        let expr = parse_with(&tokens, &mut dialect).unwrap();
+        let config = Config {
+            filter_expr: Some(FilterExpr::new(dialect.finish(expr).unwrap())),
+            ..Default::default()
+        };
+        search(root, "", config)
+            .iter()
+            .map(|entry| {
+                let relative = entry.path().strip_prefix(root).unwrap();
+                relative.to_string_lossy().into_owned()
+            })
+            .collect()
+    }
+
+    fn matches(glob: &str, name: &str) -> bool {
+        glob_regex(glob, false).unwrap().is_match(name.as_bytes())
+    }
+
+    #[test]
+    fn size_rounds_up_to_units() {
+        let dir = tempfile::tempdir().unwrap();
+        for size in [0, 1, 512, 513, 1024, 1025] {
+            File::create(dir.path().join(size.to_string()))
+                .unwrap()
+                .set_len(size)
+                .unwrap();
+        }
+        let sizes = |arg: &str| {
+            let mut sizes: Vec<u64> = find(dir.path(), &format!("-size {arg}"))
+                .iter()
+                .map(|name| name.parse().unwrap())
+                .collect();
+            sizes.sort();
+            sizes
+        };
+
+        //默认单位是 512 字节的块，1 到 512 字节都算 1 块
+        assert_eq!(sizes("1"), [1, 512]);
+        assert_eq!(sizes("+1"), [513, 1024, 1025]);
+        assert_eq!(sizes("-2"), [0, 1, 512]);
+        assert_eq!(sizes("0"), [0]);
+        assert_eq!(sizes("1k"), [1, 512, 513, 1024]);
+        assert_eq!(sizes("-1k"), [0]);
+        assert_eq!(sizes("-0"), Vec::<u64>::new());
+        assert_eq!(sizes("1025c"), [1025]);
+        assert!(find_size("10x").is_err());
+    }
+
+    #[test]
+    fn mtime_counts_whole_days() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = SystemTime::now();
+        for (name, hours) in [("today", 12), ("yesterday", 36), ("older", 60)] {
+            File::create(dir.path().join(name))
+                .unwrap()
+                .set_modified(now - Duration::from_secs(hours * 60 * 60))
+                .unwrap();
+        }
+        let names = |arg: &str| {
+            let mut names = find(dir.path(), &format!("-type f -mtime {arg}"));
+            names.sort();
+            names
+        };
+
+        //距今的天数舍去小数：36 小时算 1 天
+        assert_eq!(names("0"), ["today"]);
+        assert_eq!(names("1"), ["yesterday"]);
+        assert_eq!(names("+1"), ["older"]);
+        assert_eq!(names("-1"), ["today"]);
+        assert_eq!(names("-2"), ["today", "yesterday"]);
+    }
+
+    #[test]
+    fn glob_character_classes() {
+        assert!(matches("[abc].rs", "b.rs"));
+        assert!(!matches("[abc].rs", "d.rs"));
+        assert!(matches("file[0-9]", "file7"));
+        assert!(!matches("file[0-9]", "filex"));
+        assert!(matches("[!a]*", "b"));
+        assert!(!matches("[!a]*", "a"));
+        assert!(matches("[^a]*", "b"));
+        //] 紧跟在 [ 后面时没有内容，按普通字符处理
+        assert!(matches("[]", "[]"));
+        //没有对应的 ]
+        assert!(matches("a[b", "a[b"));
+        //字符类中的特殊字符按字面匹配
+        assert!(matches("[&~]", "~"));
+        assert!(matches(r"\*", "*"));
+        assert!(!matches(r"\*", "x"));
+        assert!(matches("*.RS", "a.RS"));
+        assert!(glob_regex("*.RS", true).unwrap().is_match(b"a.rs"));
+    }
+
+    #[test]
+    fn prune_skips_directory_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        create_tree(
+            root,
+            &["src/a.rs", "target/x.rs", "target/deep/y.rs", "b.rs"],
+        );
+
+        let mut found = find(root, "-name target -prune -o -name *.rs -print");
+        found.sort();
+        assert_eq!(found, ["b.rs", "src/a.rs"]);
+        //没有 -print 时整个表达式为真的条目作为结果，被跳过的目录本身也在其中
+        assert_eq!(find(root, "-name target -prune"), ["target"]);
+    }
+}
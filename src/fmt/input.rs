@@ -10,6 +10,11 @@ pub fn basename(path: &Path) -> &OsStr {
     path.file_name().unwrap_or(path.as_os_str())
 }
 
+//提取文件的拓展名（不含 .），没有拓展名时返回空字符串
+pub fn extension(path: &Path) -> &OsStr {
+    path.extension().unwrap_or_default()
+}
+
 //去掉文件后拓展名 如.txt
 pub fn remove_extension(path: &Path) -> OsString {
     let dirname = dirname(path);
@@ -0,0 +1,85 @@
+use std::time::SystemTime;
+
+use crate::dir_entry::DirEntry;
+
+//以下函数从条目的元数据中取出格式模板需要的值，元数据不可用时返回 None。
+
+pub fn size(entry: &DirEntry) -> Option<u64> {
+    entry.size()
+}
+
+pub fn mtime(entry: &DirEntry) -> Option<SystemTime> {
+    entry.modified()
+}
+
+pub fn depth(entry: &DirEntry) -> Option<String> {
+    entry.depth().map(|depth| depth.to_string())
+}
+
+//八进制的权限位，与 find -printf '%m' 相同，例如 644
+#[cfg(unix)]
+pub fn mode(entry: &DirEntry) -> Option<String> {
+    use std::os::unix::fs::PermissionsExt;
+    if let Some(member) = entry.archive_member() {
+        return member.mode.map(|mode| format!("{:o}", mode & 0o7777));
+    }
+    entry
+        .metadata()
+        .map(|m| format!("{:o}", m.permissions().mode() & 0o7777))
+}
+
+#[cfg(unix)]
+pub fn owner(entry: &DirEntry) -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+    entry
+        .metadata()
+        .map(|m| crate::filesystem::user_name(m.uid()))
+}
+
+#[cfg(unix)]
+pub fn group(entry: &DirEntry) -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+    entry
+        .metadata()
+        .map(|m| crate::filesystem::group_name(m.gid()))
+}
+
+#[cfg(unix)]
+pub fn inode(entry: &DirEntry) -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+    entry.metadata().map(|m| m.ino().to_string())
+}
+
+#[cfg(unix)]
+pub fn nlink(entry: &DirEntry) -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+    entry.metadata().map(|m| m.nlink().to_string())
+}
+
+/*
+以下函数在 Windows 系统下没有对应的概念，因此始终返回 None。
+*/
+#[cfg(not(unix))]
+pub fn mode(_: &DirEntry) -> Option<String> {
+    None
+}
+
+#[cfg(not(unix))]
+pub fn owner(_: &DirEntry) -> Option<String> {
+    None
+}
+
+#[cfg(not(unix))]
+pub fn group(_: &DirEntry) -> Option<String> {
+    None
+}
+
+#[cfg(not(unix))]
+pub fn inode(_: &DirEntry) -> Option<String> {
+    None
+}
+
+#[cfg(not(unix))]
+pub fn nlink(_: &DirEntry) -> Option<String> {
+    None
+}
@@ -3,13 +3,19 @@ use std::{
     ffi::{OsStr, OsString},
     fmt::{Display, Formatter},
     path::{Component, Path, Prefix},
-    sync::OnceLock,
 };
 
 mod input;
+mod metadata;
+mod spec;
 
-use aho_corasick::AhoCorasick;
-use input::{basename, dirname, remove_extension};
+use anyhow::{anyhow, Result};
+use input::{basename, dirname, extension, remove_extension};
+use regex::bytes::Regex;
+use spec::{format_local_time, FormatSpec, DEFAULT_TIME_FORMAT};
+
+use crate::dir_entry::DirEntry;
+use crate::hash::HashAlgorithm;
 //指定应写入缓冲区的内容每个“Token”包含文本或占位符变体，
 //在收集了给定命令模板的所有令牌后，将用于生成命令。
 /*
@@ -18,7 +24,14 @@ use input::{basename, dirname, remove_extension};
 3.Parent：路径的父目录。
 4.NoExt：去掉扩展名的路径。
 5.BasenameNoExt：路径的基本名称（不含扩展名）。
-6.Text(String)：存储任意文本内容。
+6.Hash(HashAlgorithm)：文件内容的哈希值，例如 {hash:sha256}。
+7.Size、Mtime、Mode、Owner、Group、Inode、Nlink：从条目元数据中取得的值。
+8.Ext：文件的扩展名。
+9.Depth：条目相对于搜索路径的深度。
+10.Root：条目所属的搜索路径。
+11.Capture(usize)：搜索模式中的捕获组，例如 {1}，命名捕获组在解析时转换为编号。
+12.Formatted：带格式说明的占位符，例如 {size:>8h}。
+13.Text(String)：存储任意文本内容。
 */
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Token {
@@ -27,18 +40,75 @@ pub enum Token {
     Parent,
     NoExt,
     BasenameNoExt,
+    Hash(HashAlgorithm),
+    Size,
+    Mtime,
+    Mode,
+    Owner,
+    Group,
+    Ext,
+    Depth,
+    Inode,
+    Nlink,
+    Root,
+    Capture(usize),
+    Formatted(Box<Token>, FormatSpec),
     Text(String),
 }
 
+//可以通过名称引用的占位符，{hash:...} 需要单独解析算法，所以不在其中
+const NAMED_TOKENS: [Token; 15] = [
+    Token::Placeholder,
+    Token::Basename,
+    Token::Parent,
+    Token::NoExt,
+    Token::BasenameNoExt,
+    Token::Size,
+    Token::Mtime,
+    Token::Mode,
+    Token::Owner,
+    Token::Group,
+    Token::Ext,
+    Token::Depth,
+    Token::Inode,
+    Token::Nlink,
+    Token::Root,
+];
+
+impl Token {
+    //占位符在大括号中的名称
+    fn name(&self) -> Cow<'static, str> {
+        let name = match *self {
+            Token::Placeholder => "",
+            Token::Basename => "/",
+            Token::Parent => "//",
+            Token::NoExt => ".",
+            Token::BasenameNoExt => "/.",
+            Token::Hash(algorithm) => return Cow::Owned(format!("hash:{}", algorithm.name())),
+            Token::Size => "size",
+            Token::Mtime => "mtime",
+            Token::Mode => "mode",
+            Token::Owner => "owner",
+            Token::Group => "group",
+            Token::Ext => "ext",
+            Token::Depth => "depth",
+            Token::Inode => "inode",
+            Token::Nlink => "nlink",
+            Token::Root => "root",
+            Token::Capture(index) => return Cow::Owned(index.to_string()),
+            Token::Formatted(ref token, _) => return token.name(),
+            Token::Text(_) => "",
+        };
+        Cow::Borrowed(name)
+    }
+}
+
 impl Display for Token {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match *self {
-            Token::Placeholder => f.write_str("{}")?,
-            Token::Basename => f.write_str("{/}")?,
-            Token::Parent => f.write_str("{//}")?,
-            Token::NoExt => f.write_str("{.}")?,
-            Token::BasenameNoExt => f.write_str("{/.}")?,
             Token::Text(ref string) => f.write_str(string)?,
+            Token::Formatted(ref token, ref spec) => write!(f, "{{{}:{}}}", token.name(), spec)?,
+            _ => write!(f, "{{{}}}", self.name())?,
         }
         Ok(())
     }
@@ -50,62 +120,169 @@ pub enum FormatTemplate {
     Text(String),
 }
 
-static PLACEHOLDERS: OnceLock<AhoCorasick> = OnceLock::new();
-
 impl FormatTemplate {
     pub fn has_tokens(&self) -> bool {
         matches!(self, FormatTemplate::Toekns(_))
     }
 
-    pub fn parse(fmt: &str) -> Self {
-        const BRACE_LEN: usize = '{'.len_utf8();
+    ///模板中用到的所有哈希算法。工作线程据此提前计算哈希，
+    /// 这样哈希计算可以和遍历并行进行，而不是在输出时才逐个计算。
+    pub fn hash_algorithms(&self) -> impl Iterator<Item = HashAlgorithm> + '_ {
+        let tokens = match self {
+            FormatTemplate::Toekns(tokens) => tokens.as_slice(),
+            FormatTemplate::Text(_) => &[],
+        };
+        tokens.iter().filter_map(|token| match token {
+            Token::Hash(algorithm) => Some(*algorithm),
+            _ => None,
+        })
+    }
+
+    ///模板中是否用到了捕获组，用到时工作线程才需要保存匹配结果
+    pub fn has_captures(&self) -> bool {
+        let tokens = match self {
+            FormatTemplate::Toekns(tokens) => tokens.as_slice(),
+            FormatTemplate::Text(_) => &[],
+        };
+        tokens.iter().any(|token| match token {
+            Token::Capture(_) => true,
+            Token::Formatted(token, _) => matches!(**token, Token::Capture(_)),
+            _ => false,
+        })
+    }
+
+    ///解析格式模板。{{ 和 }} 分别表示字面的 { 和 }。
+    /// 大括号中不是已知占位符名称的内容（例如 awk 脚本中的 {print $1}）按原样保留，
+    /// 而已知占位符的格式说明无效时返回错误。
+    pub fn parse(fmt: &str) -> Result<Self> {
+        Self::parse_with_regex(fmt, None)
+    }
+
+    ///与 parse 相同，但还可以通过 {1}、{name} 引用 regex 中的捕获组。
+    /// 捕获组的名称与内置占位符相同时，优先使用内置占位符。
+    /// 引用的编号超出了捕获组的数量，或者模式中没有引用的名称（由字母、数字和下划线组成）的捕获组时返回错误。
+    pub fn parse_with_regex(fmt: &str, regex: Option<&Regex>) -> Result<Self> {
         let mut tokens = Vec::new();
         let mut remaining = fmt;
         let mut buf = String::new();
-        let placeholders = PLACEHOLDERS.get_or_init(|| {
-            AhoCorasick::new(["{{", "}}", "{}", "{/}", "{//}", "{.}", "{/.}"]).unwrap()
-        });
-        while let Some(m) = placeholders.find(remaining) {
-            match m.pattern().as_u32() {
-                0 | 1 => {
-                    // 我们发现了转义的 {{ 或 }}，因此将
-                    // 直到第一个字符的所有内容添加到缓冲区
-                    // 然后跳过第二个。
-                    buf += &remaining[..m.start() + BRACE_LEN];
-                    remaining = &remaining[m.end()..];
-                }
-                id if !remaining[m.end()..].starts_with('}') => {
-                    buf += &remaining[..m.start()];
-                    if !buf.is_empty() {
-                        tokens.push(Token::Text(std::mem::take(&mut buf)));
+
+        while let Some(pos) = remaining.find(['{', '}']) {
+            buf += &remaining[..pos];
+            let rest = &remaining[pos..];
+
+            if rest.starts_with("{{") || rest.starts_with("}}") {
+                // 我们发现了转义的 {{ 或 }}，只将第一个字符添加到缓冲区
+                buf += &rest[..1];
+                remaining = &rest[2..];
+                continue;
+            }
+
+            if rest.starts_with('{') {
+                if let Some(end) = rest.find('}') {
+                    if let Some(token) = Self::parse_placeholder(&rest[1..end], regex)? {
+                        if !buf.is_empty() {
+                            tokens.push(Token::Text(std::mem::take(&mut buf)));
+                        }
+                        tokens.push(token);
+                        remaining = &rest[end + 1..];
+                        continue;
                     }
-                    tokens.push(token_from_pattern_id(id));
-                    remaining = &remaining[m.end()..];
-                }
-                _ => {
-                    buf += &remaining[..m.end()];
-                    remaining = &remaining[m.end() + BRACE_LEN..]
                 }
             }
+
+            // 不属于占位符的单个 { 或 } 原样保留
+            buf += &rest[..1];
+            remaining = &rest[1..];
         }
-        if !remaining.is_empty() {
-            buf += remaining;
-        }
+        buf += remaining;
+
         if tokens.is_empty() {
-            return FormatTemplate::Text(buf);
+            return Ok(FormatTemplate::Text(buf));
         }
         if !buf.is_empty() {
             tokens.push(Token::Text(buf));
         }
-        debug_assert!(!tokens.is_empty());
-        FormatTemplate::Toekns(tokens)
+        Ok(FormatTemplate::Toekns(tokens))
+    }
+
+    //解析大括号中的内容，不是已知的占位符时返回 None
+    fn parse_placeholder(inner: &str, regex: Option<&Regex>) -> Result<Option<Token>> {
+        let (name, spec) = match inner.split_once(':') {
+            Some((name, spec)) => (name, Some(spec)),
+            None => (inner, None),
+        };
+
+        if name == "hash" {
+            let algorithm = match spec {
+                Some("sha256") => HashAlgorithm::Sha256,
+                Some("blake3") => HashAlgorithm::Blake3,
+                _ => {
+                    return Err(anyhow!(
+                        "{{hash}} 需要指定算法，例如 {{hash:sha256}} 或 {{hash:blake3}}"
+                    ))
+                }
+            };
+            return Ok(Some(Token::Hash(algorithm)));
+        }
+
+        let token = match NAMED_TOKENS.iter().find(|token| token.name() == name) {
+            Some(token) => token.clone(),
+            None => match regex
+                .map(|regex| Self::parse_capture(name, regex))
+                .transpose()?
+            {
+                Some(Some(token)) => token,
+                _ => return Ok(None),
+            },
+        };
+
+        match spec {
+            None => Ok(Some(token)),
+            Some(spec) => {
+                let spec = FormatSpec::parse(spec, token == Token::Mtime, token == Token::Size)
+                    .map_err(|e| anyhow!("{{{}:{}}}: {}", name, spec, e))?;
+                Ok(Some(Token::Formatted(Box::new(token), spec)))
+            }
+        }
+    }
+
+    //把捕获组的编号或名称转换为 Token::Capture，不像捕获组名称的内容返回 None
+    fn parse_capture(name: &str, regex: &Regex) -> Result<Option<Token>> {
+        if !name.is_empty() && name.bytes().all(|b| b.is_ascii_digit()) {
+            let index = name
+                .parse::<usize>()
+                .ok()
+                .filter(|&index| index < regex.captures_len())
+                .ok_or_else(|| {
+                    anyhow!(
+                        "{{{}}}: 搜索模式'{}'中只有 {} 个捕获组",
+                        name,
+                        regex.as_str(),
+                        regex.captures_len() - 1
+                    )
+                })?;
+            return Ok(Some(Token::Capture(index)));
+        }
+
+        if !is_group_name(name) {
+            return Ok(None);
+        }
+        regex
+            .capture_names()
+            .position(|capture_name| capture_name == Some(name))
+            .map(|index| Some(Token::Capture(index)))
+            .ok_or_else(|| anyhow!("{{{}}}: 搜索模式'{}'中没有这个捕获组", name, regex.as_str()))
     }
 
     ///从此模板生成结果字符串。如果 path_separator 为 Some，则它将替换
     /// 所有占位符标记中的路径分隔符。固定文本和标记不受
-    /// 路径分隔符替换的影响。
-    pub fn generate(&self, path: impl AsRef<Path>, path_separator: Option<&str>) -> OsString {
-        use Token::*;
+    /// 路径分隔符替换的影响。哈希和元数据等与路径无关的标记从 entry 中获取。
+    pub fn generate(
+        &self,
+        path: impl AsRef<Path>,
+        entry: &DirEntry,
+        path_separator: Option<&str>,
+    ) -> OsString {
         let path = path.as_ref();
 
         match *self {
@@ -113,20 +290,23 @@ impl FormatTemplate {
                 let mut s = OsString::new();
                 for token in tokens {
                     match token {
-                        Basename => s.push(Self::replace_separator(basename(path), path_separator)),
-                        BasenameNoExt => s.push(Self::replace_separator(
-                            &remove_extension(basename(path).as_ref()),
-                            path_separator,
-                        )),
-                        NoExt => s.push(Self::replace_separator(
-                            &remove_extension(path),
+                        Token::Formatted(token, spec) => {
+                            let value = Self::generate_token(
+                                token,
+                                Some(spec),
+                                path,
+                                entry,
+                                path_separator,
+                            );
+                            s.push(spec.pad(&value.to_string_lossy()));
+                        }
+                        _ => s.push(Self::generate_token(
+                            token,
+                            None,
+                            path,
+                            entry,
                             path_separator,
                         )),
-                        Parent => s.push(Self::replace_separator(&dirname(path), path_separator)),
-                        Placeholder => {
-                            s.push(Self::replace_separator(path.as_ref(), path_separator));
-                        }
-                        Text(ref string) => s.push(string),
                     }
                 }
                 s
@@ -135,6 +315,62 @@ impl FormatTemplate {
         }
     }
 
+    fn generate_token<'a>(
+        token: &'a Token,
+        spec: Option<&FormatSpec>,
+        path: &'a Path,
+        entry: &'a DirEntry,
+        path_separator: Option<&str>,
+    ) -> Cow<'a, OsStr> {
+        use Token::*;
+
+        let value = match token {
+            Basename => return Self::replace_separator(basename(path), path_separator),
+            BasenameNoExt => {
+                let stem = remove_extension(basename(path).as_ref());
+                return Cow::Owned(Self::replace_separator(&stem, path_separator).into_owned());
+            }
+            NoExt => {
+                let stem = remove_extension(path);
+                return Cow::Owned(Self::replace_separator(&stem, path_separator).into_owned());
+            }
+            Parent => {
+                let parent = dirname(path);
+                return Cow::Owned(Self::replace_separator(&parent, path_separator).into_owned());
+            }
+            Placeholder => return Self::replace_separator(path.as_ref(), path_separator),
+            Ext => return Cow::Borrowed(extension(path)),
+            Capture(index) => return Cow::Borrowed(entry.capture(*index).unwrap_or_default()),
+            Root => match entry.root() {
+                Some(root) => {
+                    let root = displayed_root(path, entry.path(), root);
+                    return Self::replace_separator(root.as_os_str(), path_separator);
+                }
+                None => None,
+            },
+            Hash(algorithm) => entry.content_hash(*algorithm).map(str::to_owned),
+            Size => metadata::size(entry).map(|size| match spec {
+                Some(spec) => spec.format_size(size),
+                None => size.to_string(),
+            }),
+            Mtime => metadata::mtime(entry).map(|time| match spec {
+                Some(spec) => spec.format_time(time),
+                None => format_local_time(time, DEFAULT_TIME_FORMAT),
+            }),
+            Mode => metadata::mode(entry),
+            Owner => metadata::owner(entry),
+            Group => metadata::group(entry),
+            Depth => metadata::depth(entry),
+            Inode => metadata::inode(entry),
+            Nlink => metadata::nlink(entry),
+            Formatted(token, spec) => {
+                return Self::generate_token(token, Some(spec), path, entry, path_separator)
+            }
+            Text(ref string) => return Cow::Borrowed(OsStr::new(string)),
+        };
+        Cow::Owned(value.map(OsString::from).unwrap_or_default())
+    }
+
     ///将输入中的路径分隔符替换为自定义分隔符字符串。如果 path_separator
     /// 为 None，则只需返回从输入借用的 Cow<OsStr>。否则，输入将被
     /// 解释为路径，其组件将被迭代并重新连接到新的
@@ -176,14 +412,103 @@ impl FormatTemplate {
     }
 }
 
-fn token_from_pattern_id(id: u32) -> Token {
-    use Token::*;
-    match id {
-        2 => Placeholder,
-        3 => Basename,
-        4 => Parent,
-        5 => NoExt,
-        6 => BasenameNoExt,
-        _ => unreachable!(),
+//能否作为正则表达式中捕获组的名称：由字母、数字和下划线组成，不以数字开头
+fn is_group_name(name: &str) -> bool {
+    name.chars()
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/*
+输出中的搜索路径：
+1.path 是输出中的路径，可能去掉了开头的 ./ 或者 serve 中客户端当前目录的前缀，
+  从它的末尾去掉条目相对于搜索路径的部分，得到的搜索路径与输出中的路径一致。
+2.条目不在搜索路径下，或者搜索路径在输出中被整个去掉时，使用原来的搜索路径。
+*/
+fn displayed_root<'a>(path: &'a Path, full_path: &Path, root: &'a Path) -> &'a Path {
+    let Ok(relative) = full_path.strip_prefix(root) else {
+        return root;
+    };
+    let mut displayed = path;
+    for _ in relative.components() {
+        match displayed.parent() {
+            Some(parent) => displayed = parent,
+            None => return root,
+        }
+    }
+    if displayed.as_os_str().is_empty() {
+        root
+    } else {
+        displayed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::test_util::{create_tree, search};
+
+    //用 pattern 搜索 file，按 fmt 输出找到的条目
+    fn expand(file: &str, pattern: &str, fmt: &str) -> String {
+        let dir = tempfile::tempdir().unwrap();
+        create_tree(dir.path(), &[file]);
+        let regex = Regex::new(pattern).unwrap();
+        let template = FormatTemplate::parse_with_regex(fmt, Some(&regex)).unwrap();
+        let config = Config {
+            format: Some(template.clone()),
+            ..Config::default()
+        };
+        let entries = search(dir.path(), pattern, config);
+        assert_eq!(entries.len(), 1);
+        let path = entries[0].path().strip_prefix(dir.path()).unwrap();
+        template
+            .generate(path, &entries[0], None)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn parse_error(fmt: &str, pattern: &str) -> String {
+        let regex = Regex::new(pattern).unwrap();
+        FormatTemplate::parse_with_regex(fmt, Some(&regex))
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn captures_expand_by_index_and_name() {
+        let pattern = r"^(?P<name>\w+)-(\d+)(?P<suffix>_x)?\.txt$";
+        assert_eq!(
+            expand("report-2024.txt", pattern, "{2}/{name}{suffix}.{0}"),
+            "2024/report.report-2024.txt"
+        );
+        assert_eq!(
+            expand("report-2024_x.txt", pattern, "{suffix}|{1:>8}"),
+            "_x|  report"
+        );
+        //与内置占位符同名的捕获组不会覆盖内置占位符
+        assert_eq!(expand("a.txt", r"^(?P<ext>\w+)\.txt$", "{ext}"), "txt");
+    }
+
+    #[test]
+    fn rejects_references_missing_from_the_pattern() {
+        let err = parse_error("{3}", r"(\w+)-(\d+)");
+        assert!(err.contains("只有 2 个捕获组"), "{err}");
+        let err = parse_error("{missing}", r"(?P<name>\w+)");
+        assert!(err.contains("没有这个捕获组"), "{err}");
+        let err = parse_error("{1:x}", r"(\w+)");
+        assert!(err.contains("{1:x}"), "{err}");
+
+        //不像捕获组名称的内容和没有搜索模式时的 {1} 按原样保留
+        let regex = Regex::new(r"(\w+)").unwrap();
+        assert_eq!(
+            FormatTemplate::parse_with_regex("{print $1}", Some(&regex)).unwrap(),
+            FormatTemplate::Text("{print $1}".to_owned())
+        );
+        assert_eq!(
+            FormatTemplate::parse("{1}{name}").unwrap(),
+            FormatTemplate::Text("{1}{name}".to_owned())
+        );
     }
 }
@@ -0,0 +1,323 @@
+use std::{
+    fmt::{Display, Formatter},
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, Local,
+};
+
+use crate::filter::format_bytes;
+
+//没有格式说明时时间的输出格式
+pub const DEFAULT_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+//对齐方式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+//时间类占位符的输出方式：strftime 格式字符串，或者相对时间（例如 "3 days ago"）
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TimeFormat {
+    Strftime(String),
+    Relative,
+}
+
+/*
+占位符冒号后面的格式说明，例如 {size:>8h}、{mtime:%Y-%m-%d}、{/:.20}。
+语法与 Rust 的格式说明类似：[[填充字符]对齐][宽度][.最大字符数][类型]
+1.对齐：< 左对齐，> 右对齐，^ 居中。
+2.宽度：输出不足该宽度时用填充字符补齐。
+3.最大字符数：超过时截断。
+4.类型：h 表示以便于阅读的单位输出大小，只能用于 {size}。
+时间类占位符的格式说明是整个 strftime 字符串或者 rel，不支持对齐和宽度。
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FormatSpec {
+    raw: String,
+    fill: char,
+    align: Option<Align>,
+    width: Option<usize>,
+    precision: Option<usize>,
+    human: bool,
+    time: Option<TimeFormat>,
+}
+
+impl FormatSpec {
+    ///解析格式说明。is_time 表示占位符的值是时间，allow_human 表示可以使用 h 类型。
+    pub fn parse(spec: &str, is_time: bool, allow_human: bool) -> Result<Self> {
+        let mut result = FormatSpec {
+            raw: spec.to_owned(),
+            fill: ' ',
+            align: None,
+            width: None,
+            precision: None,
+            human: false,
+            time: None,
+        };
+
+        if is_time {
+            result.time = Some(parse_time_format(spec)?);
+            return Ok(result);
+        }
+
+        let invalid = || anyhow!("'{}'不是有效的格式说明", spec);
+        let mut rest = spec;
+
+        let mut chars = rest.chars();
+        let first = chars.next();
+        let second = chars.next();
+        if let Some(align) = second.and_then(align_from_char) {
+            result.fill = first.ok_or_else(invalid)?;
+            result.align = Some(align);
+            rest = &rest[first.map_or(0, char::len_utf8) + 1..];
+        } else if let Some(align) = first.and_then(align_from_char) {
+            result.align = Some(align);
+            rest = &rest[1..];
+        }
+
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if digits > 0 {
+            result.width = Some(rest[..digits].parse().map_err(|_| invalid())?);
+            rest = &rest[digits..];
+        }
+
+        if let Some(after_dot) = rest.strip_prefix('.') {
+            let digits = after_dot.len()
+                - after_dot
+                    .trim_start_matches(|c: char| c.is_ascii_digit())
+                    .len();
+            if digits == 0 {
+                return Err(invalid());
+            }
+            result.precision = Some(after_dot[..digits].parse().map_err(|_| invalid())?);
+            rest = &after_dot[digits..];
+        }
+
+        match rest {
+            "" => {}
+            "h" if allow_human => result.human = true,
+            "h" => return Err(anyhow!("格式说明'{}'中的 h 只能用于 {{size}}", spec)),
+            _ => return Err(invalid()),
+        }
+
+        Ok(result)
+    }
+
+    ///按格式说明输出时间
+    pub fn format_time(&self, time: SystemTime) -> String {
+        match self.time {
+            Some(TimeFormat::Strftime(ref fmt)) => format_local_time(time, fmt),
+            Some(TimeFormat::Relative) => relative_time(time, SystemTime::now()),
+            None => format_local_time(time, DEFAULT_TIME_FORMAT),
+        }
+    }
+
+    ///按最大字符数截断，再按宽度和对齐方式补齐。
+    pub fn pad(&self, value: &str) -> String {
+        let mut value: String = match self.precision {
+            Some(precision) => value.chars().take(precision).collect(),
+            None => value.to_owned(),
+        };
+
+        let len = value.chars().count();
+        let width = self.width.unwrap_or(0);
+        if len >= width {
+            return value;
+        }
+
+        let padding = width - len;
+        let (before, after) = match self.align.unwrap_or(Align::Left) {
+            Align::Left => (0, padding),
+            Align::Right => (padding, 0),
+            Align::Center => (padding / 2, padding - padding / 2),
+        };
+        let fill = |n: usize| std::iter::repeat_n(self.fill, n).collect::<String>();
+        value.insert_str(0, &fill(before));
+        value.push_str(&fill(after));
+        value
+    }
+
+    pub fn format_size(&self, size: u64) -> String {
+        if self.human {
+            format_bytes(size)
+        } else {
+            size.to_string()
+        }
+    }
+}
+
+impl Display for FormatSpec {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
+
+//使用本地时区按 strftime 格式输出时间
+pub fn format_local_time(time: SystemTime, fmt: &str) -> String {
+    DateTime::<Local>::from(time).format(fmt).to_string()
+}
+
+fn align_from_char(c: char) -> Option<Align> {
+    match c {
+        '<' => Some(Align::Left),
+        '>' => Some(Align::Right),
+        '^' => Some(Align::Center),
+        _ => None,
+    }
+}
+
+//检查 strftime 格式字符串是否有效，避免在输出时才出错
+fn parse_time_format(spec: &str) -> Result<TimeFormat> {
+    if spec == "rel" {
+        return Ok(TimeFormat::Relative);
+    }
+    if StrftimeItems::new(spec).any(|item| matches!(item, Item::Error)) {
+        return Err(anyhow!("'{}'不是有效的 strftime 格式", spec));
+    }
+    Ok(TimeFormat::Strftime(spec.to_owned()))
+}
+
+//将时间格式化为相对于现在的描述，例如 "3 days ago"、"in 2 hours"
+fn relative_time(time: SystemTime, now: SystemTime) -> String {
+    const UNITS: [(u64, &str); 6] = [
+        (365 * 24 * 60 * 60, "year"),
+        (30 * 24 * 60 * 60, "month"),
+        (24 * 60 * 60, "day"),
+        (60 * 60, "hour"),
+        (60, "minute"),
+        (1, "second"),
+    ];
+
+    let (secs, future) = match now.duration_since(time) {
+        Ok(elapsed) => (elapsed.as_secs(), false),
+        Err(err) => (err.duration().as_secs(), true),
+    };
+    if secs == 0 {
+        return "just now".to_owned();
+    }
+
+    let (unit_secs, unit) = UNITS
+        .iter()
+        .copied()
+        .find(|&(unit_secs, _)| secs >= unit_secs)
+        .unwrap_or((1, "second"));
+    let n = secs / unit_secs;
+    let plural = if n == 1 { "" } else { "s" };
+
+    if future {
+        format!("in {n} {unit}{plural}")
+    } else {
+        format!("{n} {unit}{plural} ago")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn spec(spec: &str) -> FormatSpec {
+        FormatSpec::parse(spec, false, true).unwrap()
+    }
+
+    #[test]
+    fn parses_fill_align_width_and_precision() {
+        let parsed = spec("*^10.3h");
+        assert_eq!(parsed.fill, '*');
+        assert_eq!(parsed.align, Some(Align::Center));
+        assert_eq!(parsed.width, Some(10));
+        assert_eq!(parsed.precision, Some(3));
+        assert!(parsed.human);
+        assert_eq!(parsed.to_string(), "*^10.3h");
+
+        let parsed = spec(">8");
+        assert_eq!(
+            (parsed.fill, parsed.align, parsed.width),
+            (' ', Some(Align::Right), Some(8))
+        );
+        assert_eq!(spec(".5").precision, Some(5));
+        //填充字符本身可以是对齐字符
+        assert_eq!(
+            (spec("<<3").fill, spec("<<3").align),
+            ('<', Some(Align::Left))
+        );
+
+        for invalid in ["x", "5x", ".", ".x", "<5.", "*", "99999999999999999999999"] {
+            assert!(
+                FormatSpec::parse(invalid, false, true).is_err(),
+                "{invalid}"
+            );
+        }
+    }
+
+    #[test]
+    fn human_sizes_only_for_size() {
+        assert!(FormatSpec::parse(">8h", false, true).is_ok());
+        let err = FormatSpec::parse(">8h", false, false).unwrap_err();
+        assert!(err.to_string().contains("{size}"), "{err}");
+        assert_eq!(spec("h").format_size(2048), format_bytes(2048));
+        assert_eq!(spec("").format_size(2048), "2048");
+    }
+
+    #[test]
+    fn pads_and_truncates_by_characters() {
+        assert_eq!(spec("5").pad("ab"), "ab   ");
+        assert_eq!(spec(">5").pad("ab"), "   ab");
+        assert_eq!(spec("^5").pad("ab"), " ab  ");
+        assert_eq!(spec("-^6").pad("ab"), "--ab--");
+        assert_eq!(spec(".3").pad("abcdef"), "abc");
+        assert_eq!(spec("2").pad("abcdef"), "abcdef");
+        //填充字符和值都可以是多字节字符，宽度按字符计算
+        assert_eq!(spec("中>4").pad("é"), "中中中é");
+        assert_eq!(spec("·<4.2").pad("文件名"), "文件··");
+    }
+
+    #[test]
+    fn time_formats() {
+        assert_eq!(
+            FormatSpec::parse("%Y-%m", true, false).unwrap().time,
+            Some(TimeFormat::Strftime("%Y-%m".to_owned()))
+        );
+        assert_eq!(
+            FormatSpec::parse("rel", true, false).unwrap().time,
+            Some(TimeFormat::Relative)
+        );
+        //时间的格式说明不按对齐和宽度解析
+        assert!(FormatSpec::parse(">8", true, false)
+            .unwrap()
+            .width
+            .is_none());
+        for invalid in ["%Q", "%", "%Y-%"] {
+            assert!(
+                FormatSpec::parse(invalid, true, false).is_err(),
+                "{invalid}"
+            );
+        }
+    }
+
+    #[test]
+    fn relative_times() {
+        let now = SystemTime::now();
+        let ago = |secs: u64| relative_time(now - Duration::from_secs(secs), now);
+        let later = |secs: u64| relative_time(now + Duration::from_secs(secs), now);
+
+        assert_eq!(ago(0), "just now");
+        assert_eq!(ago(1), "1 second ago");
+        assert_eq!(ago(59), "59 seconds ago");
+        assert_eq!(ago(60), "1 minute ago");
+        assert_eq!(ago(2 * 60 * 60 + 59), "2 hours ago");
+        assert_eq!(ago(24 * 60 * 60), "1 day ago");
+        assert_eq!(ago(45 * 24 * 60 * 60), "1 month ago");
+        assert_eq!(ago(3 * 365 * 24 * 60 * 60), "3 years ago");
+        assert_eq!(later(1), "in 1 second");
+        assert_eq!(later(3 * 24 * 60 * 60), "in 3 days");
+    }
+}
@@ -0,0 +1,831 @@
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context, Result};
+use ignore::WalkBuilder;
+
+use crate::archives::{ArchiveMember, MemberKind};
+use crate::filesystem;
+
+//索引文件开头的标识，格式改变时修改其中的版本号
+const MAGIC: &[u8; 8] = b"FFINDEX1";
+
+//超过这个时间没有更新的索引被认为可能已经过时
+pub const STALE_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+//索引中记录的一个条目，名称相对于所在的目录
+#[derive(Clone, Debug)]
+struct Record {
+    name: OsString,
+    kind: MemberKind,
+    size: u64,
+    modified: Option<SystemTime>,
+    mode: u32,
+}
+
+//索引中的一个目录：建立索引时目录的修改时间和其中的条目
+#[derive(Clone, Debug, Default)]
+struct IndexedDir {
+    modified: Option<SystemTime>,
+    children: Vec<Record>,
+}
+
+/*
+路径索引（类似 locate 的数据库）：
+记录索引根目录下所有条目的路径、类型、大小、修改时间和权限，按所在的目录分组，
+目录的路径相对于根目录，根目录本身为空路径。
+建立索引时使用的 --hidden、--no-ignore 也记录在索引中，增量更新时使用相同的规则。
+serve 在搜索的同时根据 inotify 事件更新索引，所以每个目录单独放在 Arc 中：
+更新时只替换发生变化的目录，搜索开始时取得的目录不受影响，也不需要复制整个索引。
+*/
+#[derive(Debug)]
+pub struct Index {
+    root: PathBuf,
+    built: SystemTime,
+    hidden: bool,
+    no_ignore: bool,
+    dirs: RwLock<Dirs>,
+}
+
+type Dirs = BTreeMap<PathBuf, Arc<IndexedDir>>;
+
+//建立或更新索引的统计信息
+#[derive(Debug, Default)]
+pub struct IndexStats {
+    pub entries: usize,
+    pub dirs_read: usize,
+    pub dirs_reused: usize,
+}
+
+///默认的索引文件：$XDG_CACHE_HOME/file-find/index，默认为 ~/.cache/file-find/index
+pub fn default_database() -> Result<PathBuf> {
+    filesystem::cache_dir()
+        .map(|dir| dir.join("index"))
+        .ok_or_else(|| anyhow!("无法确定索引文件的位置，请用 --database 指定"))
+}
+
+impl Index {
+    ///遍历 root，建立新的索引
+    pub fn build(root: &Path, hidden: bool, no_ignore: bool) -> Result<(Self, IndexStats)> {
+        let root = root
+            .canonicalize()
+            .with_context(|| format!("无法访问'{}'", root.display()))?;
+        let index = Self {
+            root,
+            built: SystemTime::now(),
+            hidden,
+            no_ignore,
+            dirs: RwLock::default(),
+        };
+        let mut stats = IndexStats::default();
+        let dirs = index.index_tree(&index.root, &mut stats);
+        *index.dirs.write().unwrap() = dirs;
+        Ok((index, stats))
+    }
+
+    //遍历 path 并返回其中的所有目录。path 本身只记录目录的修改时间，它在父目录中的条目由调用者记录
+    fn index_tree(&self, path: &Path, stats: &mut IndexStats) -> Dirs {
+        let mut dirs: BTreeMap<PathBuf, IndexedDir> = BTreeMap::new();
+        for result in self.walk_builder(path).build() {
+            let Ok(entry) = result else {
+                continue;
+            };
+            let Ok(relative) = entry.path().strip_prefix(&self.root) else {
+                continue;
+            };
+            let relative = relative.to_path_buf();
+            let metadata = entry.metadata().ok();
+            if entry.file_type().is_some_and(|ft| ft.is_dir()) {
+                dirs.entry(relative.clone()).or_default().modified =
+                    metadata.as_ref().and_then(|m| m.modified().ok());
+                stats.dirs_read += 1;
+            }
+            if entry.depth() == 0 {
+                continue;
+            }
+            let parent = relative.parent().unwrap_or(Path::new("")).to_path_buf();
+            let record = Record::new(entry.file_name().to_owned(), metadata.as_ref());
+            dirs.entry(parent).or_default().children.push(record);
+            stats.entries += 1;
+        }
+        dirs.into_iter()
+            .map(|(dir, indexed)| (dir, Arc::new(indexed)))
+            .collect()
+    }
+
+    /*
+    重新读取一个目录（相对于根目录）中的条目，用于 serve 根据 inotify 事件更新内存中的索引：
+    1.不再存在的子目录连同其中的内容一起从索引中移除。
+    2.新出现的子目录连同其中的内容一起加入索引。
+    读取目录时不持有锁，最后只替换这些目录，正在进行的搜索继续使用原来的目录。
+    返回新加入索引的目录（包括其中的子目录），调用者需要监视这些目录。
+    */
+    pub fn refresh(&self, relative: &Path) -> Vec<PathBuf> {
+        let path = self.root.join(relative);
+        let Ok(metadata) = fs::metadata(&path) else {
+            remove_tree(&mut self.dirs.write().unwrap(), relative);
+            return Vec::new();
+        };
+        let children = self.read_dir(&path);
+        let (old_dirs, new_dirs): (Vec<PathBuf>, Vec<PathBuf>) = {
+            let dirs = self.dirs.read().unwrap();
+            let subdirs = |children: &[Record]| -> Vec<PathBuf> {
+                children
+                    .iter()
+                    .filter(|child| child.kind == MemberKind::Dir)
+                    .map(|child| relative.join(&child.name))
+                    .collect()
+            };
+            let old_dirs = dirs
+                .get(relative)
+                .map(|dir| subdirs(&dir.children))
+                .unwrap_or_default();
+            let new_dirs = subdirs(&children)
+                .into_iter()
+                .filter(|dir| !dirs.contains_key(dir))
+                .collect();
+            (old_dirs, new_dirs)
+        };
+        let mut stats = IndexStats::default();
+        let added: Dirs = new_dirs
+            .iter()
+            .flat_map(|dir| self.index_tree(&self.root.join(dir), &mut stats))
+            .collect();
+
+        let mut dirs = self.dirs.write().unwrap();
+        for dir in old_dirs {
+            if !children
+                .iter()
+                .any(|child| child.kind == MemberKind::Dir && relative.join(&child.name) == dir)
+            {
+                remove_tree(&mut dirs, &dir);
+            }
+        }
+        dirs.insert(
+            relative.to_path_buf(),
+            Arc::new(IndexedDir {
+                modified: metadata.modified().ok(),
+                children,
+            }),
+        );
+        let mut watch = Vec::new();
+        for (dir, indexed) in added {
+            watch.push(dir.clone());
+            dirs.insert(dir, indexed);
+        }
+        watch
+    }
+
+    ///索引中所有目录的绝对路径
+    pub fn dirs(&self) -> Vec<PathBuf> {
+        let dirs = self.dirs.read().unwrap();
+        dirs.keys().map(|dir| self.root.join(dir)).collect()
+    }
+
+    /*
+    增量更新：从根目录开始，修改时间没有变化的目录直接沿用索引中记录的条目列表，
+    只重新读取修改时间变化了的目录（其中有条目被新建、删除或重命名）。
+    1.没有变化的目录中的子目录仍然需要检查，因为子目录的变化不会改变父目录的修改时间。
+    2.修改文件内容也不会改变目录的修改时间，所以沿用的条目仍然要重新读取元数据，
+      这样大小、修改时间和权限才是最新的。只需要 stat，不需要读取目录和匹配忽略规则。
+    */
+    pub fn update(&self) -> Result<(Self, IndexStats)> {
+        let old_dirs = self.dirs.read().unwrap();
+        let mut dirs = Dirs::new();
+        let mut stats = IndexStats::default();
+
+        let mut pending = vec![PathBuf::new()];
+        while let Some(relative) = pending.pop() {
+            let path = self.root.join(&relative);
+            let Ok(metadata) = fs::metadata(&path) else {
+                continue;
+            };
+            let modified = metadata.modified().ok();
+            let children = match old_dirs.get(&relative) {
+                Some(dir) if modified.is_some() && dir.modified == modified => {
+                    stats.dirs_reused += 1;
+                    dir.children
+                        .iter()
+                        .map(|child| child.restat(&path))
+                        .collect()
+                }
+                _ => {
+                    stats.dirs_read += 1;
+                    self.read_dir(&path)
+                }
+            };
+
+            for child in &children {
+                if child.kind == MemberKind::Dir {
+                    pending.push(relative.join(&child.name));
+                }
+            }
+            stats.entries += children.len();
+            dirs.insert(relative, Arc::new(IndexedDir { modified, children }));
+        }
+        let index = Self {
+            root: self.root.clone(),
+            built: SystemTime::now(),
+            hidden: self.hidden,
+            no_ignore: self.no_ignore,
+            dirs: RwLock::new(dirs),
+        };
+        Ok((index, stats))
+    }
+
+    //按建立索引时的规则读取目录中的条目，忽略文件的规则与完整遍历时相同
+    fn read_dir(&self, path: &Path) -> Vec<Record> {
+        let mut builder = self.walk_builder(path);
+        builder.max_depth(Some(1));
+        builder
+            .build()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.depth() == 1)
+            .map(|entry| Record::new(entry.file_name().to_owned(), entry.metadata().ok().as_ref()))
+            .collect()
+    }
+
+    fn walk_builder(&self, path: &Path) -> WalkBuilder {
+        let read_ignore = !self.no_ignore;
+        let mut builder = WalkBuilder::new(path);
+        builder
+            .hidden(!self.hidden)
+            .ignore(read_ignore)
+            .parents(read_ignore)
+            .git_ignore(read_ignore)
+            .git_global(read_ignore)
+            .git_exclude(read_ignore)
+            .require_git(false)
+            .follow_links(false);
+        if read_ignore {
+            builder.add_custom_ignore_filename(".fdignore");
+        }
+        builder
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    ///索引能否代替按这些选项进行的遍历：没有隐藏文件的索引不能用于 --hidden，
+    /// 是否读取忽略文件必须与建立索引时相同
+    pub fn covers(&self, hidden: bool, no_ignore: bool) -> bool {
+        (self.hidden || !hidden) && self.no_ignore == no_ignore
+    }
+
+    /*
+    判断索引是否可能已经过时：
+    1.距离上次建立或更新索引已经超过 STALE_AGE。
+    2.搜索路径或其中第一层目录的修改时间与索引中记录的不同。
+    只检查这些目录，检查所有目录的开销与重新遍历差不多。
+    */
+    pub fn is_stale(&self, path: &Path) -> bool {
+        if self
+            .built
+            .elapsed()
+            .map_or(true, |elapsed| elapsed > STALE_AGE)
+        {
+            return true;
+        }
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return true;
+        };
+        let dirs = self.dirs.read().unwrap();
+        let Some(dir) = dirs.get(relative) else {
+            return true;
+        };
+        let changed = |relative: &Path, recorded: Option<SystemTime>| {
+            fs::metadata(self.root.join(relative))
+                .and_then(|m| m.modified())
+                .ok()
+                != recorded
+        };
+        changed(relative, dir.modified)
+            || dir
+                .children
+                .iter()
+                .filter(|child| child.kind == MemberKind::Dir)
+                .any(|child| {
+                    let child = relative.join(&child.name);
+                    dirs.get(&child)
+                        .is_none_or(|recorded| changed(&child, recorded.modified))
+                })
+    }
+
+    ///索引中位于 path（绝对路径）下的所有条目，路径相对于 path，depth 为相对于 path 的层数。
+    /// 目录按路径排序，同一个目录下的条目按建立索引时的顺序。
+    /// 返回的是调用时这些目录的快照，之后对索引的更新不影响它
+    pub fn entries_under(&self, path: &Path) -> impl Iterator<Item = ArchiveMember> {
+        let dirs: Vec<(PathBuf, usize, Arc<IndexedDir>)> = match path.strip_prefix(&self.root) {
+            Ok(base) => {
+                let base_len = base.components().count();
+                self.dirs
+                    .read()
+                    .unwrap()
+                    .range(base.to_path_buf()..)
+                    .take_while(|(dir, _)| dir.starts_with(base))
+                    .map(|(dir, indexed)| {
+                        let depth = dir.components().count() - base_len + 1;
+                        let dir = dir.strip_prefix(base).unwrap_or(dir).to_path_buf();
+                        (dir, depth, Arc::clone(indexed))
+                    })
+                    .collect()
+            }
+            Err(_) => Vec::new(),
+        };
+        dirs.into_iter().flat_map(|(dir, depth, indexed)| {
+            (0..indexed.children.len()).map(move |i| {
+                let record = &indexed.children[i];
+                ArchiveMember {
+                    path: dir.join(&record.name),
+                    kind: record.kind,
+                    size: record.size,
+                    modified: record.modified,
+                    mode: Some(record.mode),
+                    depth,
+                }
+            })
+        })
+    }
+
+    //写入临时文件后再重命名，避免写入过程中被中断时留下不完整的索引
+    pub fn save(&self, database: &Path) -> Result<()> {
+        if let Some(parent) = database.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("无法创建目录'{}'", parent.display()))?;
+        }
+        let mut temp = database.as_os_str().to_owned();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+
+        let write = || -> io::Result<()> {
+            let mut w = BufWriter::new(File::create(&temp)?);
+            w.write_all(MAGIC)?;
+            write_bytes(&mut w, self.root.as_os_str())?;
+            write_time(&mut w, Some(self.built))?;
+            w.write_all(&[u8::from(self.hidden), u8::from(self.no_ignore)])?;
+            let dirs = self.dirs.read().unwrap();
+            w.write_all(&(dirs.len() as u64).to_le_bytes())?;
+            for (path, dir) in dirs.iter() {
+                write_bytes(&mut w, path.as_os_str())?;
+                write_time(&mut w, dir.modified)?;
+                w.write_all(&(dir.children.len() as u64).to_le_bytes())?;
+                for record in &dir.children {
+                    write_bytes(&mut w, &record.name)?;
+                    w.write_all(&[kind_to_byte(record.kind)])?;
+                    w.write_all(&record.size.to_le_bytes())?;
+                    write_time(&mut w, record.modified)?;
+                    w.write_all(&record.mode.to_le_bytes())?;
+                }
+            }
+            w.into_inner()?.sync_all()
+        };
+        write()
+            .and_then(|_| fs::rename(&temp, database))
+            .with_context(|| format!("无法写入索引文件'{}'", database.display()))
+    }
+
+    pub fn load(database: &Path) -> Result<Self> {
+        let read = || -> io::Result<Self> {
+            let mut r = BufReader::new(File::open(database)?);
+            let mut magic = [0; 8];
+            r.read_exact(&mut magic)?;
+            if &magic != MAGIC {
+                return Err(invalid_data());
+            }
+            let root = PathBuf::from(read_bytes(&mut r)?);
+            let built = read_time(&mut r)?.unwrap_or(UNIX_EPOCH);
+            let mut flags = [0; 2];
+            r.read_exact(&mut flags)?;
+
+            let mut dirs = BTreeMap::new();
+            for _ in 0..read_u64(&mut r)? {
+                let path = PathBuf::from(read_bytes(&mut r)?);
+                let modified = read_time(&mut r)?;
+                let len = read_u64(&mut r)?;
+                let mut children = Vec::new();
+                for _ in 0..len {
+                    let name = read_bytes(&mut r)?;
+                    let mut kind = [0; 1];
+                    r.read_exact(&mut kind)?;
+                    children.push(Record {
+                        name,
+                        kind: kind_from_byte(kind[0]).ok_or_else(invalid_data)?,
+                        size: read_u64(&mut r)?,
+                        modified: read_time(&mut r)?,
+                        mode: read_u32(&mut r)?,
+                    });
+                }
+                dirs.insert(path, Arc::new(IndexedDir { modified, children }));
+            }
+            Ok(Self {
+                root,
+                built,
+                hidden: flags[0] != 0,
+                no_ignore: flags[1] != 0,
+                dirs: RwLock::new(dirs),
+            })
+        };
+        read().with_context(|| {
+            format!(
+                "无法读取索引文件'{}'，请先运行 file-find index build",
+                database.display()
+            )
+        })
+    }
+}
+
+//从索引中移除目录以及其中的所有子目录
+fn remove_tree(dirs: &mut Dirs, relative: &Path) {
+    let removed: Vec<PathBuf> = dirs
+        .range(relative.to_path_buf()..)
+        .take_while(|(dir, _)| dir.starts_with(relative))
+        .map(|(dir, _)| dir.clone())
+        .collect();
+    for dir in removed {
+        dirs.remove(&dir);
+    }
+}
+
+impl Record {
+    fn new(name: OsString, metadata: Option<&fs::Metadata>) -> Self {
+        let kind = match metadata.map(|m| m.file_type()) {
+            Some(ft) if ft.is_dir() => MemberKind::Dir,
+            Some(ft) if ft.is_file() => MemberKind::File,
+            Some(ft) if ft.is_symlink() => MemberKind::Symlink,
+            _ => MemberKind::Other,
+        };
+        Self {
+            name,
+            kind,
+            size: metadata.map_or(0, |m| m.len()),
+            modified: metadata.and_then(|m| m.modified().ok()),
+            mode: metadata.map_or(0, mode_of),
+        }
+    }
+
+    //按 dir 中的当前状态更新记录的元数据，读取失败（例如刚被删除）时保留原来的记录
+    fn restat(&self, dir: &Path) -> Self {
+        match fs::symlink_metadata(dir.join(&self.name)) {
+            Ok(metadata) => Self::new(self.name.clone(), Some(&metadata)),
+            Err(_) => self.clone(),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn mode_of(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn mode_of(_: &fs::Metadata) -> u32 {
+    0
+}
+
+fn kind_to_byte(kind: MemberKind) -> u8 {
+    match kind {
+        MemberKind::File => 0,
+        MemberKind::Dir => 1,
+        MemberKind::Symlink => 2,
+        MemberKind::Other => 3,
+    }
+}
+
+fn kind_from_byte(byte: u8) -> Option<MemberKind> {
+    match byte {
+        0 => Some(MemberKind::File),
+        1 => Some(MemberKind::Dir),
+        2 => Some(MemberKind::Symlink),
+        3 => Some(MemberKind::Other),
+        _ => None,
+    }
+}
+
+/*
+索引文件中的数据都以小端序保存：
+1.路径和名称：u32 长度加上原始字节。
+2.时间：u8 标记是否存在，存在时接着是 UNIX 纪元以来的秒数（u64）和纳秒数（u32）。
+*/
+fn write_bytes<W: Write>(w: &mut W, s: &std::ffi::OsStr) -> io::Result<()> {
+    let bytes = s.as_encoded_bytes();
+    let len = u32::try_from(bytes.len()).map_err(|_| invalid_data())?;
+    w.write_all(&len.to_le_bytes())?;
+    w.write_all(bytes)
+}
+
+fn write_time<W: Write>(w: &mut W, time: Option<SystemTime>) -> io::Result<()> {
+    match time.and_then(|time| time.duration_since(UNIX_EPOCH).ok()) {
+        Some(since) => {
+            w.write_all(&[1])?;
+            w.write_all(&since.as_secs().to_le_bytes())?;
+            w.write_all(&since.subsec_nanos().to_le_bytes())
+        }
+        None => w.write_all(&[0]),
+    }
+}
+
+fn read_u32<R: Read>(r: &mut R) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_bytes<R: Read>(r: &mut R) -> io::Result<OsString> {
+    let len = read_u32(r)?;
+    //长度来自文件，不能直接按它分配内存：损坏的索引文件中可能是任意值，
+    // 只读取文件中实际剩下的字节，分配的内存不会超过文件的大小
+    let mut bytes = Vec::new();
+    r.take(u64::from(len)).read_to_end(&mut bytes)?;
+    if bytes.len() != len as usize {
+        return Err(invalid_data());
+    }
+    os_string_from_bytes(bytes)
+}
+
+#[cfg(unix)]
+fn os_string_from_bytes(bytes: Vec<u8>) -> io::Result<OsString> {
+    use std::os::unix::ffi::OsStringExt;
+    Ok(OsString::from_vec(bytes))
+}
+
+//其他系统上的编码字节不一定是合法的 OsString，只接受 UTF-8
+#[cfg(not(unix))]
+fn os_string_from_bytes(bytes: Vec<u8>) -> io::Result<OsString> {
+    String::from_utf8(bytes)
+        .map(OsString::from)
+        .map_err(|_| invalid_data())
+}
+
+fn read_time<R: Read>(r: &mut R) -> io::Result<Option<SystemTime>> {
+    let mut flag = [0; 1];
+    r.read_exact(&mut flag)?;
+    if flag[0] == 0 {
+        return Ok(None);
+    }
+    let secs = read_u64(r)?;
+    let nanos = read_u32(r)?;
+    Ok(Some(UNIX_EPOCH + Duration::new(secs, nanos)))
+}
+
+fn invalid_data() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "索引文件格式不正确")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{create_tree, list_tree};
+
+    //把目录的修改时间改到过去，这样测试中的修改一定会改变目录的修改时间
+    fn backdate_dirs(root: &Path) {
+        let past = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        let dirs = list_tree(root)
+            .into_iter()
+            .filter(|path| path.ends_with('/'))
+            .map(|path| root.join(path))
+            .chain([root.to_path_buf()]);
+        for dir in dirs {
+            File::open(dir).unwrap().set_modified(past).unwrap();
+        }
+    }
+
+    fn build(root: &Path, files: &[&str]) -> Index {
+        create_tree(root, files);
+        backdate_dirs(root);
+        Index::build(root, false, false).unwrap().0
+    }
+
+    //path 下的条目：(路径, 类型, 大小, 层数)，按路径排序
+    fn listing(index: &Index, path: &Path) -> Vec<(String, MemberKind, u64, usize)> {
+        let mut entries: Vec<_> = index
+            .entries_under(path)
+            .map(|member| {
+                (
+                    member.path.display().to_string(),
+                    member.kind,
+                    member.size,
+                    member.depth,
+                )
+            })
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        entries
+    }
+
+    fn paths(index: &Index, path: &Path) -> Vec<String> {
+        listing(index, path)
+            .into_iter()
+            .map(|entry| entry.0)
+            .collect()
+    }
+
+    #[test]
+    fn build_skips_hidden_and_ignored_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        create_tree(root, &["a", ".hidden", "skip.log", "sub/b"]);
+        fs::write(root.join(".fdignore"), "*.log\n").unwrap();
+        let index = Index::build(root, false, false).unwrap().0;
+        assert_eq!(paths(&index, &index.root), ["a", "sub", "sub/b"]);
+
+        let index = Index::build(root, true, true).unwrap().0;
+        assert_eq!(
+            paths(&index, &index.root),
+            [".fdignore", ".hidden", "a", "skip.log", "sub", "sub/b"]
+        );
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        let index = build(&root, &["a", "sub/b", "sub/deep/c", "empty/"]);
+        let database = dir.path().join("cache/index");
+        index.save(&database).unwrap();
+        assert!(!dir.path().join("cache/index.tmp").exists());
+
+        let loaded = Index::load(&database).unwrap();
+        assert_eq!(loaded.root, index.root);
+        assert_eq!(loaded.built, index.built);
+        assert_eq!((loaded.hidden, loaded.no_ignore), (false, false));
+        assert_eq!(loaded.dirs(), index.dirs());
+        for (loaded, original) in loaded
+            .entries_under(&index.root)
+            .zip(index.entries_under(&index.root))
+        {
+            assert_eq!(loaded.path, original.path);
+            assert_eq!(loaded.kind, original.kind);
+            assert_eq!(loaded.size, original.size);
+            assert_eq!(loaded.modified, original.modified);
+            assert_eq!(loaded.mode, original.mode);
+            assert_eq!(loaded.depth, original.depth);
+        }
+        assert_eq!(listing(&loaded, &index.root), listing(&index, &index.root));
+    }
+
+    #[test]
+    fn load_rejects_corrupt_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        let index = build(&root, &["a", "sub/b"]);
+        let database = dir.path().join("index");
+        index.save(&database).unwrap();
+        let bytes = fs::read(&database).unwrap();
+
+        //任何位置截断的文件都不能被读取
+        for len in 0..bytes.len() {
+            fs::write(&database, &bytes[..len]).unwrap();
+            assert!(Index::load(&database).is_err(), "截断到 {len} 字节");
+        }
+
+        let mut wrong_magic = bytes.clone();
+        wrong_magic[0] = b'X';
+        fs::write(&database, wrong_magic).unwrap();
+        assert!(Index::load(&database).is_err());
+
+        //根目录路径的长度被改成 u32::MAX，不能按这个长度分配内存
+        let mut huge_len = bytes.clone();
+        huge_len[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        fs::write(&database, huge_len).unwrap();
+        assert!(Index::load(&database).is_err());
+
+        fs::write(&database, &bytes).unwrap();
+        assert!(Index::load(&database).is_ok());
+    }
+
+    #[test]
+    fn update_reuses_unchanged_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let index = build(root, &["a/x", "b/y", "c/z"]);
+
+        fs::write(root.join("b/new"), "new").unwrap();
+        fs::remove_file(root.join("b/y")).unwrap();
+        fs::remove_dir_all(root.join("c")).unwrap();
+        create_tree(root, &["d/w"]);
+        //只修改文件内容不会改变 a 的修改时间，但是大小要更新
+        fs::write(root.join("a/x"), "longer content").unwrap();
+
+        let (updated, stats) = index.update().unwrap();
+        assert_eq!(
+            paths(&updated, &updated.root),
+            ["a", "a/x", "b", "b/new", "d", "d/w"]
+        );
+        let x = listing(&updated, &updated.root)
+            .into_iter()
+            .find(|entry| entry.0 == "a/x")
+            .unwrap();
+        assert_eq!(x.2, "longer content".len() as u64);
+        //根目录、b 和新的 d 需要读取，a 沿用
+        assert_eq!((stats.dirs_read, stats.dirs_reused), (3, 1));
+        assert_eq!(stats.entries, 6);
+        assert!(!updated.dirs().contains(&updated.root.join("c")));
+    }
+
+    #[test]
+    fn stale_when_old_or_top_level_dirs_changed() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let index = build(root, &["a/deep/x", "b"]);
+        let root = index.root.clone();
+        assert!(!index.is_stale(&root));
+        assert!(!index.is_stale(&root.join("a")));
+        //搜索路径不在索引中
+        assert!(index.is_stale(dir.path().parent().unwrap()));
+
+        //更深层的变化不检查
+        fs::write(root.join("a/deep/y"), "y").unwrap();
+        assert!(!index.is_stale(&root));
+        fs::write(root.join("a/y"), "y").unwrap();
+        assert!(index.is_stale(&root));
+        assert!(index.is_stale(&root.join("a")));
+
+        let fresh = Index::build(&root, false, false).unwrap().0;
+        assert!(!fresh.is_stale(&root));
+        let old = Index {
+            built: SystemTime::now() - STALE_AGE - Duration::from_secs(1),
+            ..fresh
+        };
+        assert!(old.is_stale(&root));
+    }
+
+    #[test]
+    fn entries_under_subdirectory() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = build(dir.path(), &["foo/a", "foo/sub/b", "foobar/c", "foo-bar/d"]);
+        let root = index.root.clone();
+
+        assert_eq!(
+            listing(&index, &root.join("foo"))
+                .into_iter()
+                .map(|(path, kind, _, depth)| (path, kind, depth))
+                .collect::<Vec<_>>(),
+            [
+                ("a".to_owned(), MemberKind::File, 1),
+                ("sub".to_owned(), MemberKind::Dir, 1),
+                ("sub/b".to_owned(), MemberKind::File, 2),
+            ]
+        );
+        assert_eq!(paths(&index, &root.join("foo/sub")), ["b"]);
+        assert_eq!(paths(&index, &root.join("foobar")), ["c"]);
+        assert_eq!(
+            paths(&index, &root),
+            [
+                "foo",
+                "foo-bar",
+                "foo-bar/d",
+                "foo/a",
+                "foo/sub",
+                "foo/sub/b",
+                "foobar",
+                "foobar/c"
+            ]
+        );
+        assert!(paths(&index, dir.path().parent().unwrap()).is_empty());
+        assert!(paths(&index, &root.join("missing")).is_empty());
+    }
+
+    #[test]
+    fn refresh_replaces_only_changed_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = build(dir.path(), &["a/x", "b/y", "gone/z"]);
+        let root = index.root.clone();
+        //搜索开始时取得的条目不受之后更新的影响
+        let snapshot = index.entries_under(&root);
+
+        fs::remove_dir_all(root.join("gone")).unwrap();
+        create_tree(&root, &["new/deep/w", "c"]);
+        let added = index.refresh(Path::new(""));
+        assert_eq!(added, [PathBuf::from("new"), PathBuf::from("new/deep")]);
+        assert_eq!(
+            paths(&index, &root),
+            ["a", "a/x", "b", "b/y", "c", "new", "new/deep", "new/deep/w"]
+        );
+
+        //目录被删除后刷新它会把它从索引中移除
+        fs::remove_dir_all(root.join("b")).unwrap();
+        assert!(index.refresh(Path::new("b")).is_empty());
+        assert!(!index.dirs().contains(&root.join("b")));
+
+        let mut old: Vec<_> = snapshot
+            .map(|member| member.path.display().to_string())
+            .collect();
+        old.sort();
+        assert_eq!(old, ["a", "a/x", "b", "b/y", "gone", "gone/z"]);
+    }
+}
@@ -0,0 +1,63 @@
+//! file-find 的库部分。命令行程序（src/main.rs）只是 [`app::run`] 的一层包装，
+//! 其他程序可以用 [`SearchBuilder`] 在进程内搜索，而不需要启动子进程：
+//!
+//! ```no_run
+//! use file_find::{FileType, SearchBuilder, SizeFilter};
+//!
+//! let search = SearchBuilder::new(["src"])
+//!     .pattern(r"\.rs$")
+//!     .file_type(FileType {
+//!         files: true,
+//!         ..Default::default()
+//!     })
+//!     .size(SizeFilter::from_string("+1k").unwrap())
+//!     .build()
+//!     .unwrap();
+//! for entry in search.flatten() {
+//!     println!("{}", entry.path().display());
+//! }
+//! ```
+
+//命令行程序用到的模块
+pub mod app;
+pub mod error;
+pub mod error_codes;
+
+pub(crate) mod action;
+pub(crate) mod archives;
+pub(crate) mod cli;
+pub(crate) mod config;
+pub(crate) mod count;
+pub(crate) mod dir_entry;
+pub(crate) mod duplicates;
+pub(crate) mod filesystem;
+pub(crate) mod filetypes;
+pub(crate) mod filter;
+pub(crate) mod find_compat;
+pub(crate) mod fmt;
+pub(crate) mod hash;
+pub(crate) mod hyperlink;
+pub(crate) mod index;
+pub(crate) mod output;
+pub(crate) mod regex_helper;
+pub(crate) mod search;
+#[cfg(target_os = "linux")]
+pub(crate) mod serve;
+pub(crate) mod stats;
+#[cfg(feature = "stream")]
+pub(crate) mod stream;
+#[cfg(test)]
+mod test_util;
+pub(crate) mod trash;
+pub(crate) mod walk;
+
+pub use crate::archives::{ArchiveMember, MemberKind};
+pub use crate::dir_entry::DirEntry;
+pub use crate::filetypes::FileType;
+#[cfg(unix)]
+pub use crate::filter::OwnerFilter;
+pub use crate::filter::{FilterExpr, GitStatus, SizeFilter, TimeFilter};
+pub use crate::hash::HashAlgorithm;
+pub use crate::search::{Search, SearchBuilder};
+#[cfg(feature = "stream")]
+pub use crate::stream::SearchStream;
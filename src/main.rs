@@ -1,19 +1,12 @@
-use clap::Parser; // 引入派生宏
-
-pub mod cli;
-pub mod config;
-pub mod dir_entry;
-pub mod error;
-pub mod error_codes;
-pub mod filesystem;
-pub mod filetypes;
-pub mod filter;
-pub mod fmt;
-pub mod hyperlink;
-pub mod output;
-pub mod regex_helper;
-use crate::cli::Opts;
+use file_find::{app, error::print_error, error_codes::ExitCode};
 
 fn main() {
-    Opts::parse(); // 自动解析命令行参数
+    let result = app::run();
+    match result {
+        Ok(exit_code) => exit_code.exit(),
+        Err(err) => {
+            print_error(format!("{err:#}"));
+            ExitCode::GeneralError.exit();
+        }
+    }
 }
@@ -1,11 +1,22 @@
 use std::{
     borrow::Cow,
     io::{self, Write},
+    path::Path,
 };
 
 use lscolors::{LsColors, Style};
 
-use crate::{config::Config, dir_entry::DirEntry, fmt::FormatTemplate, hyperlink::PathUrl};
+use crate::{
+    config::Config, dir_entry::DirEntry, filesystem::strip_current_dir, fmt::FormatTemplate,
+    hyperlink::PathUrl,
+};
+
+//统计、重复文件等汇总信息的输出格式
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReportFormat {
+    Text,
+    Json,
+}
 
 fn replace_path_separator(path: &str, new_path_separator: &str) -> String {
     path.replace(std::path::MAIN_SEPARATOR, new_path_separator)
@@ -32,6 +43,32 @@ pub fn print_entry<W: Write>(stdout: &mut W, entry: &DirEntry, config: &Config)
         write!(stdout, "\x1B]8;;\x1B\\")?;
     }
 
+    if config.show_line_number {
+        if let Some(line) = entry.matched_line() {
+            write!(stdout, ":{line}")?;
+        }
+    }
+
+    if config.null_separator {
+        write!(stdout, "\0")
+    } else {
+        writeln!(stdout)
+    }
+}
+
+//只输出路径，用于已经不存在的条目（例如 --watch-removed 输出被删除的条目）
+pub fn print_path<W: Write>(stdout: &mut W, path: &Path, config: &Config) -> io::Result<()> {
+    let path = if config.strip_cwd_prefix {
+        strip_current_dir(path)
+    } else {
+        path
+    };
+    let mut path_string = path.to_string_lossy();
+    if let Some(ref separator) = config.path_separator {
+        *path_string.to_mut() = replace_path_separator(&path_string, separator);
+    }
+    write!(stdout, "{path_string}")?;
+
     if config.null_separator {
         write!(stdout, "\0")
     } else {
@@ -47,6 +84,7 @@ fn print_entry_format<W: Write>(
 ) -> io::Result<()> {
     let output = format.generate(
         entry.stripped_path(config),
+        entry,
         config.path_separator.as_deref(),
     );
 
@@ -60,7 +98,7 @@ fn print_trailing_slash<W: Write>(
     config: &Config,
     style: Option<&Style>,
 ) -> io::Result<()> {
-    if entry.file_type().map_or(false, |ft| ft.is_dir()) {
+    if entry.is_dir() {
         write!(
             stdout,
             "{}",
@@ -1,5 +1,17 @@
+use anyhow::{anyhow, Result};
+use regex::bytes::{Regex, RegexBuilder};
 use regex_syntax::{hir::Hir, ParserBuilder};
 
+///构造匹配文件名或路径的正则表达式。
+/// 智能大小写：模式中包含大写字母时才区分大小写
+pub fn build_regex(pattern: &str) -> Result<Regex> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(!pattern_has_uppercase_char(pattern))
+        .dot_matches_new_line(true)
+        .build()
+        .map_err(|e| anyhow!("'{}'不是有效的正则表达式: {}", pattern, e))
+}
+
 ///检查正则表达式模式是否包含大写字符。
 /// 它通过解析模式到正则表达式的高阶中间表示（HIR），然后递归地分析其结构来完成这一任务。
 pub fn pattern_has_uppercase_char(pattern: &str) -> bool {
@@ -37,6 +49,7 @@ fn hir_has_uppercase_char(hir: &Hir) -> bool {
 
 ///检查一个正则表达式模式是否匹配以 . 开头的字符串。
 /// 它通过解析正则表达式的高阶中间表示（HIR）来分析模式的结构。
+#[allow(dead_code)]
 pub fn pattern_matches_strings_with_leading_dot(pattern: &str) -> bool {
     let mut parser = ParserBuilder::new().utf8(false).build();
 
@@ -46,6 +59,7 @@ pub fn pattern_matches_strings_with_leading_dot(pattern: &str) -> bool {
         .unwrap_or(false)
 }
 
+#[allow(dead_code)]
 fn hir_matches_strings_with_leading_dot(hir: &Hir) -> bool {
     use regex_syntax::hir::*;
 
@@ -0,0 +1,186 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+
+#[cfg(unix)]
+use crate::filter::OwnerFilter;
+use crate::filter::{FilterExpr, GitFilter, GitStatus, SizeFilter, TimeFilter};
+use crate::{config::Config, filesystem, filetypes::FileType, regex_helper, walk};
+
+pub use crate::walk::Search;
+
+/*
+在进程内搜索的入口，默认配置与不带选项运行命令行程序时相同：
+忽略隐藏文件，读取 .gitignore、.fdignore 等忽略文件，不跟随符号链接，
+模式使用智能大小写（模式中有大写字母时才区分大小写）。
+各项设置在 build 时统一检查，检查通过后开始遍历。
+*/
+#[derive(Default)]
+pub struct SearchBuilder {
+    roots: Vec<PathBuf>,
+    patterns: Vec<String>,
+    //git 过滤需要知道搜索路径，在 build 时才构造
+    git_statuses: Vec<GitStatus>,
+    git_tracked: bool,
+    git_revisions: Option<String>,
+    config: Config,
+}
+
+impl SearchBuilder {
+    pub fn new<I, P>(roots: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        Self {
+            roots: roots.into_iter().map(Into::into).collect(),
+            ..Default::default()
+        }
+    }
+
+    ///条目的名称（full_path 时为完整路径）需要匹配的正则表达式，多次调用时需要匹配所有模式
+    pub fn pattern(mut self, pattern: impl Into<String>) -> Self {
+        self.patterns.push(pattern.into());
+        self
+    }
+
+    ///用完整路径而不是文件名匹配模式
+    pub fn full_path(mut self, full_path: bool) -> Self {
+        self.config.search_full_path = full_path;
+        self
+    }
+
+    pub fn file_type(mut self, file_type: FileType) -> Self {
+        self.config.file_types = Some(file_type);
+        self
+    }
+
+    ///文件大小条件，多次调用时需要满足所有条件
+    pub fn size(mut self, filter: SizeFilter) -> Self {
+        self.config.size_constraints.push(filter);
+        self
+    }
+
+    ///修改时间条件，多次调用时需要满足所有条件
+    pub fn time(mut self, filter: TimeFilter) -> Self {
+        self.config.time_constraints.push(filter);
+        self
+    }
+
+    #[cfg(unix)]
+    pub fn owner(mut self, filter: OwnerFilter) -> Self {
+        self.config.owner_constraint = Some(filter);
+        self
+    }
+
+    ///过滤表达式，与其他条件同时满足，例如 FilterExpr::from_string("-name x -o -size +1M")
+    pub fn filter(mut self, expr: FilterExpr) -> Self {
+        self.config.filter_expr = Some(expr);
+        self
+    }
+
+    ///只保留处于该 git 状态的条目，多次调用时满足其一即可。每个搜索路径可以属于不同的仓库
+    pub fn git_status(mut self, status: GitStatus) -> Self {
+        self.git_statuses.push(status);
+        self
+    }
+
+    ///只保留被 git 跟踪的条目
+    pub fn git_tracked(mut self, tracked: bool) -> Self {
+        self.git_tracked = tracked;
+        self
+    }
+
+    ///只保留从 rev（例如 origin/main）到 HEAD 的提交中改动过的条目
+    pub fn git_changed_since(mut self, rev: &str) -> Self {
+        self.git_revisions = Some(format!("{rev}..HEAD"));
+        self
+    }
+
+    ///只保留提交或提交范围（例如 HEAD~3、A..B、A...B）中的提交改动过的条目
+    pub fn git_changed_in(mut self, revisions: impl Into<String>) -> Self {
+        self.git_revisions = Some(revisions.into());
+        self
+    }
+
+    ///是否包括隐藏文件
+    pub fn hidden(mut self, hidden: bool) -> Self {
+        self.config.ignore_hidden = !hidden;
+        self
+    }
+
+    ///是否读取 .gitignore、.fdignore 等忽略文件
+    pub fn ignore_files(mut self, read: bool) -> Self {
+        self.config.read_fdignore = read;
+        self.config.read_vcsignore = read;
+        self
+    }
+
+    pub fn follow_links(mut self, follow: bool) -> Self {
+        self.config.follow_links = follow;
+        self
+    }
+
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.config.max_depth = Some(depth);
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.config.threads = threads;
+        self
+    }
+
+    ///最多返回的结果数量，达到后停止遍历
+    pub fn max_results(mut self, max: usize) -> Self {
+        self.config.max_results = Some(max);
+        self
+    }
+
+    ///检查设置并在后台开始遍历，返回结果的迭代器。设置无效时返回错误，不会开始遍历：
+    ///
+    /// ```
+    /// use file_find::SearchBuilder;
+    ///
+    /// //搜索路径不存在
+    /// assert!(SearchBuilder::new(["no/such/dir"]).build().is_err());
+    /// //模式不是有效的正则表达式
+    /// assert!(SearchBuilder::new(["."]).pattern("(").build().is_err());
+    /// //线程数必须大于 0
+    /// assert!(SearchBuilder::new(["."]).threads(0).build().is_err());
+    /// assert!(SearchBuilder::new(["."]).max_depth(0).build().is_ok());
+    /// ```
+    pub fn build(mut self) -> Result<Search> {
+        if self.roots.is_empty() {
+            return Err(anyhow!("至少需要指定一个搜索路径"));
+        }
+        if let Some(root) = self
+            .roots
+            .iter()
+            .find(|root| !filesystem::is_existing_directory(root))
+        {
+            return Err(anyhow!("搜索路径'{}'不是一个目录", root.display()));
+        }
+
+        let patterns = self
+            .patterns
+            .iter()
+            .map(|pattern| regex_helper::build_regex(pattern))
+            .collect::<Result<Vec<_>>>()?;
+        self.config.case_sensitive = self
+            .patterns
+            .iter()
+            .any(|pattern| regex_helper::pattern_has_uppercase_char(pattern));
+        if !self.git_statuses.is_empty() || self.git_tracked || self.git_revisions.is_some() {
+            self.config.git_filter = Some(GitFilter::new(
+                &self.roots,
+                &self.git_statuses,
+                self.git_tracked,
+                self.git_revisions.as_deref(),
+            )?);
+        }
+        self.config.validate()?;
+
+        walk::search(&self.roots, patterns, self.config)
+    }
+}
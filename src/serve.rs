@@ -0,0 +1,522 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    env,
+    io::{self, BufRead, BufReader, IsTerminal, Write},
+    os::{
+        fd::AsFd,
+        unix::{
+            fs::FileTypeExt,
+            net::{UnixListener, UnixStream},
+        },
+    },
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::Parser;
+use nix::{
+    errno::Errno,
+    poll::{poll, PollFd, PollFlags, PollTimeout},
+    sys::inotify::{AddWatchFlags, InitFlags, Inotify, WatchDescriptor},
+};
+use serde_json::{json, Value};
+
+use crate::{cli::Opts, error::print_error, error_codes::ExitCode, index::Index};
+
+//收到事件后等待这么久再更新索引，让一批连续的事件只触发一次更新
+const COALESCE_DELAY: Duration = Duration::from_millis(100);
+
+/*
+serve 的协议：客户端连接后发送一行 JSON 请求，例如
+    {"args": ["-p", "foo", "-t", "f"], "cwd": "/home/user/project", "color": false}
+args 是命令行参数（不包括程序名），cwd 是解析相对路径用的当前目录，color 表示是否输出颜色。
+守护进程的响应是若干行 JSON：
+1.{"result": "..."}：一行搜索结果，与命令行的一行输出相同。
+2.{"error": "..."}：错误信息。
+3.{"unavailable": "..."}：守护进程无法处理这个请求（例如搜索路径不在索引中），客户端应该自己搜索。
+4.{"exit_code": 0}：最后一行，搜索的退出码。
+*/
+
+//所有索引共用一把锁，请求只在选择索引时持有它。目录的变化直接更新到共享的索引中，
+// 丢失事件后重新建立的索引替换其中的 Arc
+type Indexes = Arc<Mutex<Vec<Arc<Index>>>>;
+
+///建立 roots 的索引，然后一直在 socket 上响应搜索请求，同时根据 inotify 事件更新索引
+pub fn serve(socket: &Path, roots: &[String], hidden: bool, no_ignore: bool) -> Result<ExitCode> {
+    let mut indexes = Vec::new();
+    for root in roots {
+        let (index, stats) = Index::build(Path::new(root), hidden, no_ignore)?;
+        eprintln!(
+            "已索引'{}'中的 {} 个条目",
+            index.root().display(),
+            stats.entries
+        );
+        indexes.push(Arc::new(index));
+    }
+
+    let inotify =
+        Inotify::init(InitFlags::IN_CLOEXEC).map_err(|err| anyhow!("无法初始化 inotify: {err}"))?;
+    let mut watcher = IndexWatcher {
+        inotify,
+        watches: HashMap::new(),
+    };
+    for (i, index) in indexes.iter().enumerate() {
+        for dir in index.dirs() {
+            watcher.add_watch(i, index, dir);
+        }
+    }
+
+    //上次没有正常退出时会留下套接字文件，连接不上时可以安全地删除。
+    // 只删除套接字，路径写错时不会误删普通文件
+    if let Ok(metadata) = socket.symlink_metadata() {
+        if !metadata.file_type().is_socket() {
+            bail!("'{}'已经存在并且不是套接字", socket.display());
+        }
+        if UnixStream::connect(socket).is_err() {
+            std::fs::remove_file(socket)
+                .with_context(|| format!("无法删除'{}'", socket.display()))?;
+        }
+    }
+    let listener =
+        UnixListener::bind(socket).with_context(|| format!("无法监听'{}'", socket.display()))?;
+
+    let indexes: Indexes = Arc::new(Mutex::new(indexes));
+    let watched = Arc::clone(&indexes);
+    thread::spawn(move || watcher.run(&watched));
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let indexes = Arc::clone(&indexes);
+                thread::spawn(move || handle(stream, &indexes));
+            }
+            Err(err) => print_error(format!("无法接受连接: {err}")),
+        }
+    }
+    Ok(ExitCode::Success)
+}
+
+struct IndexWatcher {
+    inotify: Inotify,
+    //监视描述符对应的索引序号和目录（相对于索引的根目录）
+    watches: HashMap<WatchDescriptor, (usize, PathBuf)>,
+}
+
+impl IndexWatcher {
+    fn add_watch(&mut self, i: usize, index: &Index, dir: PathBuf) {
+        let mask = AddWatchFlags::IN_CREATE
+            | AddWatchFlags::IN_DELETE
+            | AddWatchFlags::IN_MODIFY
+            | AddWatchFlags::IN_ATTRIB
+            | AddWatchFlags::IN_CLOSE_WRITE
+            | AddWatchFlags::IN_MOVED_FROM
+            | AddWatchFlags::IN_MOVED_TO
+            | AddWatchFlags::IN_ONLYDIR
+            | AddWatchFlags::IN_DONT_FOLLOW;
+        let Ok(relative) = dir.strip_prefix(index.root()) else {
+            return;
+        };
+        match self.inotify.add_watch(dir.as_path(), mask) {
+            //同一个目录被移动后再次监视会得到相同的描述符，这里更新为新的路径
+            Ok(wd) => {
+                self.watches.insert(wd, (i, relative.to_path_buf()));
+            }
+            Err(err) => print_error(format!("无法监视'{}': {}", dir.display(), err)),
+        }
+    }
+
+    //一直等待事件，并重新读取发生变化的目录
+    fn run(mut self, indexes: &Indexes) {
+        loop {
+            let events = match self.read_burst() {
+                Ok(events) => events,
+                Err(err) => {
+                    print_error(format!("无法读取 inotify 事件，索引将不再更新: {err}"));
+                    return;
+                }
+            };
+
+            let mut changed = BTreeSet::new();
+            let mut overflowed = false;
+            for event in events {
+                if event.mask.contains(AddWatchFlags::IN_Q_OVERFLOW) {
+                    overflowed = true;
+                } else if event.mask.contains(AddWatchFlags::IN_IGNORED) {
+                    self.watches.remove(&event.wd);
+                } else if let Some(dir) = self.watches.get(&event.wd) {
+                    changed.insert(dir.clone());
+                }
+            }
+
+            //丢失了事件时无法知道哪些目录发生了变化，按目录的修改时间增量更新整个索引。
+            // 更新时不持有锁，只在替换索引时短暂加锁
+            if overflowed {
+                let current = indexes.lock().unwrap().clone();
+                for (i, index) in current.iter().enumerate() {
+                    match index.update() {
+                        Ok((updated, _)) => indexes.lock().unwrap()[i] = Arc::new(updated),
+                        Err(err) => print_error(format!("{err:#}")),
+                    }
+                }
+            }
+            //只有这个线程会替换索引，取出后直接在共享的索引上更新发生变化的目录
+            let current = indexes.lock().unwrap().clone();
+            for (i, relative) in changed {
+                let index = &current[i];
+                for dir in index.refresh(&relative) {
+                    let dir = index.root().join(dir);
+                    self.add_watch(i, index, dir);
+                }
+            }
+        }
+    }
+
+    //阻塞直到有事件，然后等待一小段时间，把这段时间内的事件一起返回
+    fn read_burst(&self) -> nix::Result<Vec<nix::sys::inotify::InotifyEvent>> {
+        let mut events = Vec::new();
+        loop {
+            match self.inotify.read_events() {
+                Ok(batch) => {
+                    events.extend(batch);
+                    break;
+                }
+                Err(Errno::EINTR) => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        thread::sleep(COALESCE_DELAY);
+        loop {
+            let mut fds = [PollFd::new(self.inotify.as_fd(), PollFlags::POLLIN)];
+            match poll(&mut fds, PollTimeout::ZERO) {
+                Ok(0) => return Ok(events),
+                Ok(_) => events.extend(self.inotify.read_events()?),
+                Err(Errno::EINTR) => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+//处理一个连接：读取一行请求，把结果写回连接
+fn handle(stream: UnixStream, indexes: &Indexes) {
+    let mut request = String::new();
+    if BufReader::new(&stream).read_line(&mut request).is_err() {
+        return;
+    }
+    let mut writer = &stream;
+    let response = match answer(&request, indexes, &stream) {
+        Ok(Some(exit_code)) => json!({ "exit_code": i32::from(exit_code) }),
+        Ok(None) => return,
+        Err(err) => json!({ "error": format!("{err:#}"), "exit_code": 1 }),
+    };
+    let _ = writeln!(writer, "{response}");
+}
+
+//返回 None 表示已经回复了 unavailable
+fn answer(request: &str, indexes: &Indexes, stream: &UnixStream) -> Result<Option<ExitCode>> {
+    let request: Value = serde_json::from_str(request).context("请求不是有效的 JSON")?;
+    let args = request["args"]
+        .as_array()
+        .ok_or_else(|| anyhow!("请求中缺少 args"))?
+        .iter()
+        .map(|arg| arg.as_str().map(ToOwned::to_owned))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| anyhow!("args 中的参数必须是字符串"))?;
+    let cwd = request["cwd"]
+        .as_str()
+        .ok_or_else(|| anyhow!("请求中缺少 cwd"))?;
+    let color = request["color"].as_bool().unwrap_or(false);
+
+    let opts = Opts::try_parse_from(std::iter::once("file-find".to_owned()).chain(args))?;
+
+    //会修改文件系统、需要一直运行或者要在客户端的当前目录中执行命令的请求由客户端自己处理
+    if opts.command.is_some()
+        || opts.filter_exec.is_some()
+        || opts.watch
+        || opts.from_index.is_some()
+        || opts.search_archives
+        || opts.rename.is_some()
+        || opts.delete
+        || opts.trash
+        || opts.copy_to.is_some()
+        || opts.move_to.is_some()
+        || opts.link_to.is_some()
+        || opts.archive.is_some()
+    {
+        return unavailable(stream, "守护进程不处理这些选项");
+    }
+
+    //不切换进程的当前目录，相对的搜索路径拼上客户端的当前目录，输出时再去掉这部分
+    let relative = Path::new(&opts.path).is_relative();
+    let search_path = Path::new(cwd).join(&opts.path);
+    let Ok(canonical) = search_path.canonicalize() else {
+        return unavailable(stream, "搜索路径不存在");
+    };
+    //只在选择索引时持有锁，搜索期间索引可以继续更新
+    let index = {
+        let indexes = indexes.lock().unwrap();
+        let Some(index) = indexes
+            .iter()
+            .filter(|index| canonical.starts_with(index.root()))
+            .filter(|index| index.covers(opts.hidden, opts.no_ignore))
+            .max_by_key(|index| index.root().as_os_str().len())
+        else {
+            return unavailable(stream, "搜索路径不在索引中");
+        };
+        Arc::clone(index)
+    };
+
+    let opts = Opts {
+        path: search_path.to_string_lossy().into_owned(),
+        ..opts
+    };
+    let exit_code = crate::app::search(&opts, ResultWriter::new(stream), |config| {
+        config.index = Some(index);
+        config.ls_colors = color.then(|| lscolors::LsColors::from_env().unwrap_or_default());
+        if relative {
+            config.base_dir = Some(PathBuf::from(cwd));
+        }
+    })?;
+    Ok(Some(exit_code))
+}
+
+fn unavailable(mut stream: &UnixStream, reason: &str) -> Result<Option<ExitCode>> {
+    writeln!(stream, "{}", json!({ "unavailable": reason }))?;
+    Ok(None)
+}
+
+//把输出的每一行包装成 {"result": "..."} 写入连接
+struct ResultWriter<W> {
+    inner: W,
+    line: Vec<u8>,
+}
+
+impl<W: Write> ResultWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            line: Vec::new(),
+        }
+    }
+}
+
+impl<W: Write> Write for ResultWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            if byte == b'\n' {
+                let line = String::from_utf8_lossy(&self.line);
+                writeln!(self.inner, "{}", json!({ "result": line }))?;
+                self.line.clear();
+            } else {
+                self.line.push(byte);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+///把当前的命令行参数发送给 socket 上的守护进程并输出结果。
+/// 守护进程没有运行或者无法处理这个请求时返回 None，调用者照常搜索
+pub fn query(socket: &Path) -> Result<Option<ExitCode>> {
+    let Ok(stream) = UnixStream::connect(socket) else {
+        return Ok(None);
+    };
+    let args: Vec<String> = env::args_os()
+        .skip(1)
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    let request = json!({
+        "args": args,
+        "cwd": env::current_dir()?.to_string_lossy(),
+        "color": io::stdout().is_terminal(),
+    });
+    writeln!(&stream, "{request}")?;
+
+    let stdout = io::stdout();
+    read_responses(BufReader::new(&stream), &mut stdout.lock())
+}
+
+//把守护进程返回的结果写入 w，返回 None 表示守护进程无法处理这个请求
+fn read_responses<R: BufRead, W: Write>(r: R, w: &mut W) -> Result<Option<ExitCode>> {
+    for line in r.lines() {
+        let response: Value =
+            serde_json::from_str(&line?).context("守护进程的响应不是有效的 JSON")?;
+        if let Some(result) = response["result"].as_str() {
+            if let Err(err) = writeln!(w, "{result}") {
+                //下游管道已关闭（例如 `| head`），安静地结束即可
+                if err.kind() == io::ErrorKind::BrokenPipe {
+                    return Ok(Some(ExitCode::Success));
+                }
+                return Err(err.into());
+            }
+            continue;
+        }
+        if response.get("unavailable").is_some() {
+            return Ok(None);
+        }
+        if let Some(err) = response["error"].as_str() {
+            print_error(err);
+        }
+        if let Some(code) = response["exit_code"].as_i64() {
+            w.flush()?;
+            return Ok(Some(exit_code_from(code)));
+        }
+    }
+    Err(anyhow!("守护进程意外断开了连接"))
+}
+
+//守护进程发送的是退出码的数值，只需要区分成功、被 Ctrl-C 中断和其他错误
+fn exit_code_from(code: i64) -> ExitCode {
+    match code {
+        0 => ExitCode::Success,
+        130 => ExitCode::KilledBySigint,
+        _ => ExitCode::GeneralError,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::create_tree;
+
+    fn indexes(root: &Path) -> Indexes {
+        let (index, _) = Index::build(root, false, false).unwrap();
+        Arc::new(Mutex::new(vec![Arc::new(index)]))
+    }
+
+    //在 cwd 中用 args 发送一个请求，返回守护进程回复的每一行
+    fn request(indexes: &Indexes, cwd: &Path, args: &[&str]) -> Vec<Value> {
+        let request = json!({ "args": args, "cwd": cwd.to_string_lossy(), "color": false });
+        send(indexes, &request.to_string())
+    }
+
+    fn send(indexes: &Indexes, request: &str) -> Vec<Value> {
+        let (client, server) = UnixStream::pair().unwrap();
+        writeln!(&client, "{request}").unwrap();
+        handle(server, indexes);
+        BufReader::new(&client)
+            .lines()
+            .map(|line| serde_json::from_str(&line.unwrap()).unwrap())
+            .collect()
+    }
+
+    //回复中的结果（排序后）和最后一行
+    fn results(responses: &[Value]) -> (Vec<&str>, &Value) {
+        let (last, results) = responses.split_last().unwrap();
+        let mut results: Vec<&str> = results
+            .iter()
+            .map(|response| response["result"].as_str().unwrap())
+            .collect();
+        results.sort();
+        (results, last)
+    }
+
+    #[test]
+    fn answers_from_the_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        create_tree(&root, &["a.txt", "sub/b.txt", "sub/c.rs"]);
+        let indexes = indexes(&root);
+        //建立索引之后新建的文件不在结果中，说明结果来自索引
+        create_tree(&root, &["late.txt"]);
+
+        let responses = request(&indexes, &root, &["-p", r"\.txt$"]);
+        let (found, last) = results(&responses);
+        assert_eq!(found, ["a.txt", "sub/b.txt"]);
+        assert_eq!(last, &json!({ "exit_code": 0 }));
+
+        //相对路径按客户端的当前目录解析，输出时去掉这部分
+        let responses = request(&indexes, &root.join("sub"), &["-p", r"\.txt$"]);
+        assert_eq!(results(&responses).0, ["b.txt"]);
+        let responses = request(&indexes, &root.join("sub"), &["-p", "a", "-P", ".."]);
+        assert_eq!(results(&responses).0, ["../a.txt"]);
+
+        let absolute = root.join("sub").to_string_lossy().into_owned();
+        let responses = request(&indexes, Path::new("/"), &["-p", "c", "-P", &absolute]);
+        assert_eq!(
+            results(&responses).0,
+            [root.join("sub/c.rs").to_string_lossy()]
+        );
+    }
+
+    #[test]
+    fn unavailable_requests_fall_back_to_the_client() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap().join("indexed");
+        create_tree(&root, &["a", "outside/"]);
+        let indexes = indexes(&root);
+        let unavailable = |responses: Vec<Value>| {
+            assert_eq!(responses.len(), 1, "{responses:?}");
+            assert!(responses[0]["unavailable"].is_string(), "{responses:?}");
+        };
+
+        unavailable(request(&indexes, &root, &["-p", "a", "--delete"]));
+        //搜索路径不在索引中，或者不存在
+        unavailable(request(&indexes, &root, &["-p", "a", "-P", ".."]));
+        unavailable(request(&indexes, &root, &["-p", "a", "-P", "missing"]));
+        //索引中没有隐藏文件
+        unavailable(request(&indexes, &root, &["-p", "a", "-H"]));
+    }
+
+    #[test]
+    fn reports_invalid_requests() {
+        let dir = tempfile::tempdir().unwrap();
+        let indexes = indexes(dir.path());
+
+        for responses in [
+            send(&indexes, "not json"),
+            send(&indexes, r#"{"cwd": "/"}"#),
+            request(&indexes, dir.path(), &["--no-such-option"]),
+        ] {
+            assert_eq!(responses.len(), 1, "{responses:?}");
+            assert!(responses[0]["error"].is_string());
+            assert_eq!(responses[0]["exit_code"], 1);
+        }
+    }
+
+    #[test]
+    fn result_writer_wraps_each_line() {
+        let mut out = Vec::new();
+        let mut writer = ResultWriter::new(&mut out);
+        writer.write_all(b"a\nb").unwrap();
+        writer.write_all("c\"\u{4e2d}\n".as_bytes()).unwrap();
+        //没有换行结尾的内容不会输出
+        writer.write_all(b"partial").unwrap();
+        writer.flush().unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"result\":\"a\"}\n{\"result\":\"bc\\\"\u{4e2d}\"}\n"
+        );
+    }
+
+    #[test]
+    fn client_prints_results_and_maps_exit_codes() {
+        let read = |responses: &str| {
+            let mut out = Vec::new();
+            let exit_code = read_responses(responses.as_bytes(), &mut out);
+            (exit_code, String::from_utf8(out).unwrap())
+        };
+
+        let (exit_code, out) = read("{\"result\":\"a\"}\n{\"result\":\"b\"}\n{\"exit_code\":0}\n");
+        assert_eq!(exit_code.unwrap(), Some(ExitCode::Success));
+        assert_eq!(out, "a\nb\n");
+
+        let (exit_code, _) = read("{\"exit_code\":130}\n");
+        assert_eq!(exit_code.unwrap(), Some(ExitCode::KilledBySigint));
+        let (exit_code, _) = read("{\"error\":\"boom\",\"exit_code\":1}\n");
+        assert_eq!(exit_code.unwrap(), Some(ExitCode::GeneralError));
+        let (exit_code, _) = read("{\"unavailable\":\"no\"}\n");
+        assert_eq!(exit_code.unwrap(), None);
+
+        //没有退出码就断开连接，或者响应不是 JSON
+        assert!(read("{\"result\":\"a\"}\n").0.is_err());
+        assert!(read("garbage\n").0.is_err());
+    }
+}
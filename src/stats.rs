@@ -0,0 +1,280 @@
+use std::{
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, OnceLock, Weak,
+    },
+    time::Instant,
+};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use serde_json::json;
+
+use crate::{dir_entry::DirEntry, filter::format_bytes, output::ReportFormat};
+
+/*
+一次搜索的统计信息（--stats）。
+工作线程和接收线程会同时更新这些计数器，因此全部使用原子类型。
+被隐藏文件规则和 .gitignore、.fdignore 等忽略文件跳过的条目不会交给回调，
+遍历器只为每个这样的条目输出一条 debug 日志，所以由 SkipLogger 从日志中统计。
+被跳过的目录只算一次，不包括其中的内容。
+*/
+pub struct Stats {
+    started: Instant,
+
+    matched_files: AtomicU64,
+    matched_dirs: AtomicU64,
+    matched_symlinks: AtomicU64,
+    matched_others: AtomicU64,
+    matched_bytes: AtomicU64,
+
+    dirs_visited: AtomicU64,
+    skipped_hidden: AtomicU64,
+    skipped_by_ignore: AtomicU64,
+
+    skipped_by_pattern: AtomicU64,
+    skipped_by_type: AtomicU64,
+    skipped_by_size: AtomicU64,
+    skipped_by_time: AtomicU64,
+    skipped_by_owner: AtomicU64,
+    skipped_by_git: AtomicU64,
+    skipped_by_expr: AtomicU64,
+    skipped_by_contents: AtomicU64,
+    skipped_by_command: AtomicU64,
+
+    permission_errors: AtomicU64,
+    other_errors: AtomicU64,
+}
+
+//条目被过滤掉的原因
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SkipReason {
+    Pattern,
+    FileType,
+    Size,
+    Time,
+    Owner,
+    Git,
+    Expr,
+    Contents,
+    Command,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            matched_files: AtomicU64::new(0),
+            matched_dirs: AtomicU64::new(0),
+            matched_symlinks: AtomicU64::new(0),
+            matched_others: AtomicU64::new(0),
+            matched_bytes: AtomicU64::new(0),
+            dirs_visited: AtomicU64::new(0),
+            skipped_hidden: AtomicU64::new(0),
+            skipped_by_ignore: AtomicU64::new(0),
+            skipped_by_pattern: AtomicU64::new(0),
+            skipped_by_type: AtomicU64::new(0),
+            skipped_by_size: AtomicU64::new(0),
+            skipped_by_time: AtomicU64::new(0),
+            skipped_by_owner: AtomicU64::new(0),
+            skipped_by_git: AtomicU64::new(0),
+            skipped_by_expr: AtomicU64::new(0),
+            skipped_by_contents: AtomicU64::new(0),
+            skipped_by_command: AtomicU64::new(0),
+            permission_errors: AtomicU64::new(0),
+            other_errors: AtomicU64::new(0),
+        }
+    }
+
+    ///记录遍历器产出的一个条目，统计会被继续深入的目录
+    pub fn record_visit(&self, entry: &DirEntry, max_depth: Option<usize>) {
+        let depth = entry.depth().unwrap_or(0);
+        let is_dir = entry.file_type().is_some_and(|ft| ft.is_dir());
+        if is_dir && max_depth.is_none_or(|max| depth < max) {
+            self.dirs_visited.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    ///开始统计遍历器因为隐藏文件规则和忽略文件跳过的条目，之前统计的 Stats 不再更新
+    pub fn capture_walker_skips(self: &Arc<Self>) {
+        *SKIP_LOGGER.stats.lock().unwrap() = Arc::downgrade(self);
+        //已经安装了其他日志记录器时无法统计，也不要打开 debug 日志打扰它
+        static INSTALLED: OnceLock<bool> = OnceLock::new();
+        if *INSTALLED.get_or_init(|| log::set_logger(&SKIP_LOGGER).is_ok()) {
+            log::set_max_level(LevelFilter::Debug);
+        }
+    }
+
+    pub fn record_skip(&self, reason: SkipReason) {
+        let counter = match reason {
+            SkipReason::Pattern => &self.skipped_by_pattern,
+            SkipReason::FileType => &self.skipped_by_type,
+            SkipReason::Size => &self.skipped_by_size,
+            SkipReason::Time => &self.skipped_by_time,
+            SkipReason::Owner => &self.skipped_by_owner,
+            SkipReason::Git => &self.skipped_by_git,
+            SkipReason::Expr => &self.skipped_by_expr,
+            SkipReason::Contents => &self.skipped_by_contents,
+            SkipReason::Command => &self.skipped_by_command,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_match(&self, entry: &DirEntry) {
+        if entry.is_file() {
+            self.matched_files.fetch_add(1, Ordering::Relaxed);
+            if let Some(size) = entry.size() {
+                self.matched_bytes.fetch_add(size, Ordering::Relaxed);
+            }
+        } else if entry.is_dir() {
+            self.matched_dirs.fetch_add(1, Ordering::Relaxed);
+        } else if entry.is_symlink() {
+            self.matched_symlinks.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.matched_others.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn record_error(&self, err: &ignore::Error) {
+        let is_permission_error = err
+            .io_error()
+            .is_some_and(|e| e.kind() == io::ErrorKind::PermissionDenied);
+        if is_permission_error {
+            self.permission_errors.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.other_errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    ///将统计结果输出到 stderr，避免与搜索结果混在一起。
+    pub fn print(&self, format: ReportFormat) -> io::Result<()> {
+        let stderr = io::stderr();
+        let mut stderr = stderr.lock();
+        match format {
+            ReportFormat::Text => self.print_text(&mut stderr),
+            ReportFormat::Json => self.print_json(&mut stderr),
+        }
+    }
+
+    fn print_text<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let bytes = load(&self.matched_bytes);
+
+        writeln!(
+            w,
+            "Matched:     {} files, {} directories, {} symlinks, {} others",
+            load(&self.matched_files),
+            load(&self.matched_dirs),
+            load(&self.matched_symlinks),
+            load(&self.matched_others),
+        )?;
+        writeln!(w, "Total size:  {} ({} bytes)", format_bytes(bytes), bytes)?;
+        writeln!(w, "Visited:     {} directories", load(&self.dirs_visited))?;
+        writeln!(
+            w,
+            "Skipped:     {} hidden, {} by ignore rules, {} by pattern, {} by type, {} by size, {} by time, {} by owner, {} by git, {} by expression, {} by contents, {} by command",
+            load(&self.skipped_hidden),
+            load(&self.skipped_by_ignore),
+            load(&self.skipped_by_pattern),
+            load(&self.skipped_by_type),
+            load(&self.skipped_by_size),
+            load(&self.skipped_by_time),
+            load(&self.skipped_by_owner),
+            load(&self.skipped_by_git),
+            load(&self.skipped_by_expr),
+            load(&self.skipped_by_contents),
+            load(&self.skipped_by_command),
+        )?;
+        writeln!(
+            w,
+            "Errors:      {} permission denied, {} other",
+            load(&self.permission_errors),
+            load(&self.other_errors),
+        )?;
+        writeln!(
+            w,
+            "Elapsed:     {:.3}s",
+            self.started.elapsed().as_secs_f64()
+        )
+    }
+
+    fn print_json<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let report = json!({
+            "matched": {
+                "files": load(&self.matched_files),
+                "directories": load(&self.matched_dirs),
+                "symlinks": load(&self.matched_symlinks),
+                "others": load(&self.matched_others),
+                "bytes": load(&self.matched_bytes),
+            },
+            "directories_visited": load(&self.dirs_visited),
+            "skipped": {
+                "hidden": load(&self.skipped_hidden),
+                "ignore": load(&self.skipped_by_ignore),
+                "pattern": load(&self.skipped_by_pattern),
+                "type": load(&self.skipped_by_type),
+                "size": load(&self.skipped_by_size),
+                "time": load(&self.skipped_by_time),
+                "owner": load(&self.skipped_by_owner),
+                "git": load(&self.skipped_by_git),
+                "expression": load(&self.skipped_by_expr),
+                "contents": load(&self.skipped_by_contents),
+                "command": load(&self.skipped_by_command),
+            },
+            "errors": {
+                "permission_denied": load(&self.permission_errors),
+                "other": load(&self.other_errors),
+            },
+            "elapsed_ms": self.started.elapsed().as_millis() as u64,
+        });
+        writeln!(w, "{report}")
+    }
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/*
+遍历器（ignore::walk）跳过条目时输出形如 "ignoring <路径>: <匹配>" 的 debug 日志，
+隐藏文件的匹配显示为 Ignore(IgnoreMatch(Hidden))，其余的来自忽略文件。
+只处理这一种日志，其他日志直接丢弃。
+*/
+struct SkipLogger {
+    stats: Mutex<Weak<Stats>>,
+}
+
+static SKIP_LOGGER: SkipLogger = SkipLogger {
+    stats: Mutex::new(Weak::new()),
+};
+
+impl Log for SkipLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() == Level::Debug && metadata.target() == "ignore::walk"
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let message = record.args().to_string();
+        if !message.starts_with("ignoring ") {
+            return;
+        }
+        let Some(stats) = self.stats.lock().unwrap().upgrade() else {
+            return;
+        };
+        let counter = if message.ends_with("(Hidden))") {
+            &stats.skipped_hidden
+        } else {
+            &stats.skipped_by_ignore
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn flush(&self) {}
+}
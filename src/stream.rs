@@ -0,0 +1,120 @@
+//! 搜索结果的异步接口（需要启用 `stream` feature）。
+//!
+//! 遍历仍然在后台线程中进行，不会阻塞异步运行时的线程：
+//!
+//! ```no_run
+//! use file_find::SearchBuilder;
+//! use futures::StreamExt;
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! let mut stream = SearchBuilder::new(["/srv"]).pattern(r"\.log$").build()?.into_stream();
+//! while let Some(entry) = stream.next().await {
+//!     println!("{}", entry?.path().display());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    thread,
+};
+
+use futures::{channel::mpsc, executor::block_on, SinkExt, Stream};
+
+use crate::dir_entry::DirEntry;
+use crate::walk::{Search, StopHandle};
+
+//转发线程和 SearchStream 之间的通道容量，工作线程那边另有一个有界通道
+const STREAM_CAPACITY: usize = 0x100;
+
+/*
+搜索结果的 Stream：
+1.一个转发线程从 Search 中取出结果，发送到有界的 futures 通道中，
+  消费者处理得慢时转发线程阻塞，工作线程的通道随之被填满，遍历暂停，内存占用不会无限增长。
+2.SearchStream 被丢弃时让工作线程停止遍历，转发线程随后发现通道关闭并退出。
+*/
+pub struct SearchStream {
+    rx: mpsc::Receiver<Result<DirEntry, ignore::Error>>,
+    stop: StopHandle,
+}
+
+impl Search {
+    ///转换为 Stream，供异步代码使用
+    pub fn into_stream(self) -> SearchStream {
+        let stop = self.stop_handle();
+        let (mut tx, rx) = mpsc::channel(STREAM_CAPACITY);
+        thread::spawn(move || {
+            for result in self {
+                //接收端已经被丢弃
+                if block_on(tx.send(result)).is_err() {
+                    break;
+                }
+            }
+        });
+        SearchStream { rx, stop }
+    }
+}
+
+impl Stream for SearchStream {
+    type Item = Result<DirEntry, ignore::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.rx).poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.rx.size_hint()
+    }
+}
+
+impl Drop for SearchStream {
+    fn drop(&mut self) {
+        self.stop.stop();
+    }
+}
+
+#[cfg(all(test, feature = "stream"))]
+mod tests {
+    use std::{
+        fs::{self, File},
+        time::{Duration, Instant},
+    };
+
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::search::SearchBuilder;
+
+    #[test]
+    fn dropping_the_stream_stops_the_walk() {
+        //条目数超过两个通道的容量，遍历不可能在消费者取走结果之前结束
+        let dir = tempfile::tempdir().unwrap();
+        for i in 0..200 {
+            let sub = dir.path().join(i.to_string());
+            fs::create_dir(&sub).unwrap();
+            for j in 0..120 {
+                File::create(sub.join(j.to_string())).unwrap();
+            }
+        }
+
+        let mut stream = SearchBuilder::new([dir.path()])
+            .build()
+            .unwrap()
+            .into_stream();
+        assert!(block_on(stream.next()).is_some_and(|result| result.is_ok()));
+        let stop = stream.stop.clone();
+
+        //消费者不取结果时通道被填满，遍历线程暂停：它、转发线程中的 Search 和 stream 都还持有状态
+        thread::sleep(Duration::from_millis(300));
+        assert_eq!(stop.other_users(), 3);
+
+        drop(stream);
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while stop.other_users() > 0 {
+            assert!(Instant::now() < deadline, "丢弃 stream 后遍历没有停止");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
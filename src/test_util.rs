@@ -0,0 +1,62 @@
+//测试中共用的辅助函数
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use regex::bytes::Regex;
+
+use crate::{config::Config, dir_entry::DirEntry, walk};
+
+///在 root 下按 files 创建文件，以 / 结尾的表示目录
+pub fn create_tree(root: &Path, files: &[&str]) {
+    for file in files {
+        let path = root.join(file);
+        if file.ends_with('/') {
+            fs::create_dir_all(&path).unwrap();
+        } else {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, file).unwrap();
+        }
+    }
+}
+
+///在 root 中搜索名称匹配 pattern 的条目，包括隐藏文件，不读取忽略文件
+pub fn search(root: &Path, pattern: &str, config: Config) -> Vec<DirEntry> {
+    let config = Config {
+        ignore_hidden: false,
+        read_fdignore: false,
+        read_vcsignore: false,
+        ..config
+    };
+    let mut entries: Vec<DirEntry> = walk::search(
+        &[root.to_path_buf()],
+        vec![Regex::new(pattern).unwrap()],
+        config,
+    )
+    .unwrap()
+    .map(Result::unwrap)
+    .collect();
+    entries.sort();
+    entries
+}
+
+///root 下所有条目相对于 root 的路径，按字典序排列，目录以 / 结尾
+pub fn list_tree(root: &Path) -> Vec<String> {
+    let mut paths = Vec::new();
+    let mut stack = vec![PathBuf::from(root)];
+    while let Some(dir) = stack.pop() {
+        for child in fs::read_dir(&dir).unwrap() {
+            let path = child.unwrap().path();
+            let mut relative = path.strip_prefix(root).unwrap().display().to_string();
+            if path.symlink_metadata().unwrap().is_dir() {
+                relative.push('/');
+                stack.push(path);
+            }
+            paths.push(relative);
+        }
+    }
+    paths.sort();
+    paths
+}
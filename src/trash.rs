@@ -0,0 +1,520 @@
+use std::{
+    env,
+    ffi::OsString,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::Local;
+
+//.trashinfo 中删除时间的格式
+const DELETION_DATE_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+/*
+freedesktop.org 回收站规范的实现（https://specifications.freedesktop.org/trash-spec/）。
+每个回收站目录包含 files 和 info 两个子目录：
+1.files：被删除的文件本身。
+2.info：与 files 中的文件同名、后缀为 .trashinfo 的文件，记录原路径和删除时间。
+与家目录位于同一个文件系统上的文件放入 $XDG_DATA_HOME/Trash，
+其他文件系统上的文件放入该文件系统挂载点下的 .Trash/$uid 或 .Trash-$uid，避免跨文件系统复制。
+*/
+#[derive(Debug)]
+struct TrashDir {
+    path: PathBuf,
+    //挂载点下的回收站记录相对于挂载点的路径，家目录回收站为 None，记录绝对路径
+    topdir: Option<PathBuf>,
+}
+
+///回收站中的一个条目
+#[derive(Debug)]
+pub struct TrashedItem {
+    pub original_path: PathBuf,
+    pub deletion_date: String,
+    file: PathBuf,
+    info: PathBuf,
+}
+
+impl TrashDir {
+    fn files(&self) -> PathBuf {
+        self.path.join("files")
+    }
+
+    fn info(&self) -> PathBuf {
+        self.path.join("info")
+    }
+
+    //家目录回收站：$XDG_DATA_HOME/Trash，默认为 ~/.local/share/Trash
+    fn home() -> io::Result<Self> {
+        let data_home = env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "无法确定家目录"))?;
+        Ok(Self {
+            path: data_home.join("Trash"),
+            topdir: None,
+        })
+    }
+
+    //创建 files 和 info 子目录，回收站目录只允许当前用户访问
+    fn create(&self) -> io::Result<()> {
+        for dir in [self.files(), self.info()] {
+            create_private_dir_all(&dir)?;
+        }
+        Ok(())
+    }
+
+    //选择放置 path 的回收站
+    fn for_path(path: &Path) -> io::Result<Self> {
+        let home = Self::home()?;
+        home.create()?;
+
+        let device = device_of(&path.symlink_metadata()?);
+        if device.is_none() || device == device_of(&fs::metadata(&home.path)?) {
+            return Ok(home);
+        }
+
+        let topdir = mount_point_of(path)?;
+        let trash = topdir_trash(&topdir)?;
+        trash.create()?;
+        Ok(trash)
+    }
+
+    //把 path 移动到回收站，先以独占方式创建 .trashinfo 文件占住名称，再移动文件
+    fn put(&self, path: &Path) -> io::Result<()> {
+        let name = path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "路径没有文件名"))?;
+        let recorded_path = match self.topdir {
+            Some(ref topdir) => path.strip_prefix(topdir).unwrap_or(path),
+            None => path,
+        };
+        let contents = format!(
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            percent_encode(recorded_path),
+            Local::now().format(DELETION_DATE_FORMAT)
+        );
+
+        for n in 1.. {
+            let mut trashed_name = name.to_owned();
+            if n > 1 {
+                trashed_name.push(format!(".{n}"));
+            }
+            let file = self.files().join(&trashed_name);
+            trashed_name.push(".trashinfo");
+            let info = self.info().join(trashed_name);
+
+            if file.symlink_metadata().is_ok() {
+                continue;
+            }
+            let mut info_file = match OpenOptions::new().write(true).create_new(true).open(&info) {
+                Ok(info_file) => info_file,
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            };
+
+            let result = info_file
+                .write_all(contents.as_bytes())
+                .and_then(|_| fs::rename(path, &file));
+            if result.is_err() {
+                let _ = fs::remove_file(&info);
+            }
+            return result;
+        }
+        unreachable!()
+    }
+
+    //读取回收站中的所有条目，无法解析的 .trashinfo 文件会被跳过
+    fn items(&self) -> Vec<TrashedItem> {
+        let Ok(read_dir) = fs::read_dir(self.info()) else {
+            return Vec::new();
+        };
+
+        let mut items = Vec::new();
+        for info in read_dir.filter_map(Result::ok).map(|entry| entry.path()) {
+            if info.extension().is_none_or(|ext| ext != "trashinfo") {
+                continue;
+            }
+            let Some(name) = info.file_stem() else {
+                continue;
+            };
+            let Ok(contents) = fs::read_to_string(&info) else {
+                continue;
+            };
+            let Some((original_path, deletion_date)) = parse_trash_info(&contents) else {
+                continue;
+            };
+
+            items.push(TrashedItem {
+                original_path: match self.topdir {
+                    Some(ref topdir) => topdir.join(original_path),
+                    None => original_path,
+                },
+                deletion_date,
+                file: self.files().join(name),
+                info,
+            });
+        }
+        items
+    }
+}
+
+impl TrashedItem {
+    ///恢复到原来的位置。原位置已经存在同名条目时返回错误，不会覆盖它。
+    pub fn restore(&self) -> io::Result<()> {
+        if self.original_path.symlink_metadata().is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "原位置已经存在同名条目",
+            ));
+        }
+        if let Some(parent) = self.original_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(&self.file, &self.original_path)?;
+        fs::remove_file(&self.info)
+    }
+}
+
+///把 path 移动到回收站
+pub fn trash(path: &Path) -> io::Result<()> {
+    let path = absolute_path(path)?;
+    TrashDir::for_path(&path)?.put(&path)
+}
+
+///所有回收站中的条目，包括家目录回收站和各个挂载点下的回收站
+pub fn trashed_items() -> Vec<TrashedItem> {
+    let mut items = Vec::new();
+    if let Ok(home) = TrashDir::home() {
+        items.extend(home.items());
+    }
+    for topdir in mount_points() {
+        for trash in topdir_trash_candidates(&topdir) {
+            items.extend(trash.items());
+        }
+    }
+    items
+}
+
+//绝对路径，只规范化父目录，不跟随 path 本身的符号链接
+fn absolute_path(path: &Path) -> io::Result<PathBuf> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "路径没有文件名"))?;
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.canonicalize()?,
+        _ => env::current_dir()?,
+    };
+    Ok(parent.join(name))
+}
+
+fn topdir_trash_candidates(topdir: &Path) -> Vec<TrashDir> {
+    let uid = current_uid();
+    let mut candidates = Vec::new();
+    let shared = topdir.join(".Trash");
+    if is_valid_shared_trash(&shared) {
+        candidates.push(TrashDir {
+            path: shared.join(&uid),
+            topdir: Some(topdir.to_path_buf()),
+        });
+    }
+    candidates.push(TrashDir {
+        path: topdir.join(format!(".Trash-{uid}")),
+        topdir: Some(topdir.to_path_buf()),
+    });
+    candidates
+}
+
+//挂载点下的回收站：优先使用管理员创建的 .Trash/$uid，不可用时使用 .Trash-$uid
+fn topdir_trash(topdir: &Path) -> io::Result<TrashDir> {
+    let mut last_err = None;
+    for trash in topdir_trash_candidates(topdir) {
+        match create_private_dir_all(&trash.path) {
+            Ok(()) => return Ok(trash),
+            Err(err) => last_err = Some(err),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| io::Error::other("无法创建回收站目录")))
+}
+
+//规范要求 .Trash 是目录、不是符号链接，并且设置了粘滞位
+#[cfg(unix)]
+fn is_valid_shared_trash(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.symlink_metadata()
+        .is_ok_and(|m| m.is_dir() && m.permissions().mode() & 0o1000 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_valid_shared_trash(_: &Path) -> bool {
+    false
+}
+
+#[cfg(unix)]
+fn create_private_dir_all(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(path)
+}
+
+#[cfg(not(unix))]
+fn create_private_dir_all(path: &Path) -> io::Result<()> {
+    fs::create_dir_all(path)
+}
+
+#[cfg(unix)]
+fn current_uid() -> String {
+    nix::unistd::getuid().to_string()
+}
+
+#[cfg(not(unix))]
+fn current_uid() -> String {
+    String::new()
+}
+
+#[cfg(unix)]
+fn device_of(metadata: &fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.dev())
+}
+
+#[cfg(not(unix))]
+fn device_of(_: &fs::Metadata) -> Option<u64> {
+    None
+}
+
+//沿着父目录向上查找，直到设备号发生变化，得到 path 所在文件系统的挂载点
+fn mount_point_of(path: &Path) -> io::Result<PathBuf> {
+    let device = device_of(&path.symlink_metadata()?);
+    let mut mount_point = path.parent().unwrap_or(path);
+    while let Some(parent) = mount_point.parent() {
+        if device_of(&fs::metadata(parent)?) != device {
+            break;
+        }
+        mount_point = parent;
+    }
+    Ok(mount_point.to_path_buf())
+}
+
+//从 /proc/self/mounts 读取所有挂载点，读取失败时返回空列表
+fn mount_points() -> Vec<PathBuf> {
+    let Ok(mounts) = fs::read_to_string("/proc/self/mounts") else {
+        return Vec::new();
+    };
+    //同一个位置可能被挂载了多次
+    let mut mount_points: Vec<_> = mounts
+        .lines()
+        .filter_map(|line| line.split(' ').nth(1))
+        .map(|field| PathBuf::from(unescape_mount_field(field)))
+        .filter(|path| path != Path::new("/"))
+        .collect();
+    mount_points.sort();
+    mount_points.dedup();
+    mount_points
+}
+
+//挂载点中的空格等字符以 \040 这样的八进制转义形式出现
+fn unescape_mount_field(field: &str) -> String {
+    let mut out = String::with_capacity(field.len());
+    let mut rest = field;
+    while let Some(pos) = rest.find('\\') {
+        out.push_str(&rest[..pos]);
+        let code = rest
+            .get(pos + 1..pos + 4)
+            .and_then(|digits| u8::from_str_radix(digits, 8).ok());
+        match code {
+            Some(code) => {
+                out.push(char::from(code));
+                rest = &rest[pos + 4..];
+            }
+            None => {
+                out.push('\\');
+                rest = &rest[pos + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn parse_trash_info(contents: &str) -> Option<(PathBuf, String)> {
+    let mut lines = contents.lines().map(str::trim);
+    if lines.next()? != "[Trash Info]" {
+        return None;
+    }
+
+    let mut path = None;
+    let mut deletion_date = String::new();
+    for line in lines {
+        if let Some(value) = line.strip_prefix("Path=") {
+            path = Some(percent_decode(value)?);
+        } else if let Some(value) = line.strip_prefix("DeletionDate=") {
+            deletion_date = value.to_owned();
+        }
+    }
+    Some((path?, deletion_date))
+}
+
+//按 RFC 2396 对路径进行百分号编码，保留路径分隔符
+fn percent_encode(path: &Path) -> String {
+    let mut out = String::new();
+    for &byte in path.as_os_str().as_encoded_bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.!~*'()/".contains(&byte) {
+            out.push(char::from(byte));
+        } else {
+            out.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    out
+}
+
+fn percent_decode(value: &str) -> Option<PathBuf> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    Some(PathBuf::from(bytes_to_os_string(bytes)))
+}
+
+#[cfg(unix)]
+fn bytes_to_os_string(bytes: Vec<u8>) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+    OsString::from_vec(bytes)
+}
+
+#[cfg(not(unix))]
+fn bytes_to_os_string(bytes: Vec<u8>) -> OsString {
+    OsString::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{create_tree, list_tree};
+
+    #[cfg(unix)]
+    fn set_mode(path: &Path, mode: u32) {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn chooses_shared_trash_only_when_valid() {
+        let uid = current_uid();
+        let dir = tempfile::tempdir().unwrap();
+        let topdir = dir.path();
+
+        //没有 .Trash 时使用 .Trash-$uid
+        assert_eq!(
+            topdir_trash(topdir).unwrap().path,
+            topdir.join(format!(".Trash-{uid}"))
+        );
+
+        //.Trash 没有设置粘滞位时不能使用
+        let shared = topdir.join(".Trash");
+        fs::create_dir(&shared).unwrap();
+        set_mode(&shared, 0o777);
+        assert_eq!(
+            topdir_trash(topdir).unwrap().path,
+            topdir.join(format!(".Trash-{uid}"))
+        );
+
+        set_mode(&shared, 0o1777);
+        let trash = topdir_trash(topdir).unwrap();
+        assert_eq!(trash.path, shared.join(&uid));
+        assert_eq!(trash.topdir.as_deref(), Some(topdir));
+        assert!(trash.path.is_dir());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn rejects_symlinked_shared_trash() {
+        let dir = tempfile::tempdir().unwrap();
+        let (topdir, elsewhere) = (dir.path().join("top"), dir.path().join("elsewhere"));
+        fs::create_dir(&topdir).unwrap();
+        fs::create_dir(&elsewhere).unwrap();
+        set_mode(&elsewhere, 0o1777);
+        std::os::unix::fs::symlink(&elsewhere, topdir.join(".Trash")).unwrap();
+
+        assert_eq!(
+            topdir_trash(&topdir).unwrap().path,
+            topdir.join(format!(".Trash-{}", current_uid()))
+        );
+    }
+
+    #[test]
+    fn trash_info_round_trip_and_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let topdir = dir.path();
+        create_tree(topdir, &["docs/a b%.txt", "other/a b%.txt"]);
+        let trash = TrashDir {
+            path: topdir.join(".Trash-test"),
+            topdir: Some(topdir.to_path_buf()),
+        };
+        trash.create().unwrap();
+
+        trash.put(&topdir.join("docs/a b%.txt")).unwrap();
+        trash.put(&topdir.join("other/a b%.txt")).unwrap();
+        //挂载点下的回收站记录相对路径，特殊字符按百分号编码
+        let info = fs::read_to_string(trash.info().join("a b%.txt.trashinfo")).unwrap();
+        assert!(info.starts_with("[Trash Info]\nPath=docs/a%20b%25.txt\nDeletionDate="));
+        //同名的条目加上编号
+        assert_eq!(list_tree(&trash.files()), ["a b%.txt", "a b%.txt.2"]);
+
+        let mut items = trash.items();
+        items.sort_by(|a, b| a.original_path.cmp(&b.original_path));
+        let originals: Vec<_> = items
+            .iter()
+            .map(|item| item.original_path.clone())
+            .collect();
+        assert_eq!(
+            originals,
+            [topdir.join("docs/a b%.txt"), topdir.join("other/a b%.txt")]
+        );
+
+        //原位置已经有同名条目时不覆盖
+        fs::write(topdir.join("docs/a b%.txt"), "new").unwrap();
+        let err = items[0].restore().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(
+            fs::read_to_string(topdir.join("docs/a b%.txt")).unwrap(),
+            "new"
+        );
+
+        //原来的目录已经不存在时重新创建
+        fs::remove_dir_all(topdir.join("other")).unwrap();
+        items[1].restore().unwrap();
+        assert_eq!(
+            fs::read_to_string(topdir.join("other/a b%.txt")).unwrap(),
+            "other/a b%.txt"
+        );
+        assert_eq!(trash.items().len(), 1);
+    }
+
+    #[test]
+    fn percent_encoding_round_trip() {
+        let path = Path::new("/tmp/日志 #1/a+b.txt");
+        let encoded = percent_encode(path);
+        assert_eq!(encoded, "/tmp/%E6%97%A5%E5%BF%97%20%231/a%2Bb.txt");
+        assert_eq!(percent_decode(&encoded).unwrap(), path);
+        assert!(percent_decode("bad%2").is_none());
+        assert!(percent_decode("bad%zz").is_none());
+        assert!(parse_trash_info("Path=/tmp/a\n").is_none());
+        assert_eq!(
+            parse_trash_info("[Trash Info]\nPath=/tmp/a%20b\nDeletionDate=2024-01-02T03:04:05\n"),
+            Some((PathBuf::from("/tmp/a b"), "2024-01-02T03:04:05".to_owned()))
+        );
+    }
+}
@@ -0,0 +1,778 @@
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::{bounded, Receiver, RecvTimeoutError, Sender};
+use ignore::{WalkBuilder, WalkParallel, WalkState};
+use regex::bytes::Regex;
+
+#[cfg(target_os = "linux")]
+mod watch;
+
+use crate::{
+    archives,
+    config::Config,
+    count::Counter,
+    dir_entry::DirEntry,
+    duplicates,
+    error::{print_error, print_warning},
+    error_codes::ExitCode,
+    filesystem,
+    filter::Verdict,
+    index::Index,
+    output,
+    stats::{SkipReason, Stats},
+};
+
+/*
+工作线程通过通道发送给接收线程的结果：
+1.Entry：通过了所有过滤条件的条目。
+2.Error：遍历过程中遇到的错误（例如权限不足）。
+*/
+#[allow(clippy::large_enum_variant)]
+pub enum WorkerResult {
+    Entry(DirEntry),
+    Error(ignore::Error),
+}
+
+//通道容量，避免接收线程处理不过来时内存无限增长
+const CHANNEL_CAPACITY: usize = 0x4000;
+
+//在缓冲模式下最多缓冲的条目数
+const MAX_BUFFER_LENGTH: usize = 1000;
+
+//在缓冲模式下最多等待的时间，超过后改为流式输出
+const MAX_BUFFER_TIME: Duration = Duration::from_millis(100);
+
+/*
+接收线程的模式：
+1.Buffering：先将结果缓冲起来，如果搜索很快结束，就能输出排好序的结果。
+2.Streaming：搜索耗时较长或结果较多时，直接输出收到的每个结果。
+*/
+#[derive(PartialEq)]
+enum ReceiverMode {
+    Buffering,
+    Streaming,
+}
+
+struct ReceiverBuffer<'a, W> {
+    config: &'a Config,
+    stats: &'a Stats,
+    quit_flag: &'a AtomicBool,
+    rx: Receiver<WorkerResult>,
+    stdout: W,
+    mode: ReceiverMode,
+    deadline: Instant,
+    buffer: Vec<DirEntry>,
+    num_results: usize,
+    counter: Option<Counter>,
+    //需要拿到全部结果后再处理的模式（例如 --duplicates、--rename）收集的结果
+    collected: Option<Vec<DirEntry>>,
+}
+
+impl<'a, W: Write> ReceiverBuffer<'a, W> {
+    fn new(state: &'a WorkerState, rx: Receiver<WorkerResult>, stdout: W) -> Self {
+        let config = &state.config;
+        let counter = config.count.then(|| Counter::new(config.count_by));
+        let collected = (config.duplicates.is_some() || config.action.is_some()).then(Vec::new);
+
+        //只计数或者收集全部结果时不需要排序，直接进入流式模式
+        let mode = if counter.is_some() || collected.is_some() {
+            ReceiverMode::Streaming
+        } else {
+            ReceiverMode::Buffering
+        };
+
+        Self {
+            config: &state.config,
+            stats: &state.stats,
+            quit_flag: &state.quit_flag,
+            rx,
+            stdout,
+            mode,
+            deadline: Instant::now() + MAX_BUFFER_TIME,
+            buffer: Vec::with_capacity(MAX_BUFFER_LENGTH),
+            num_results: 0,
+            counter,
+            collected,
+        }
+    }
+
+    //一直处理结果，直到所有工作线程结束或者需要提前退出。
+    fn process(&mut self) -> ExitCode {
+        loop {
+            if let Err(ec) = self.poll() {
+                self.quit_flag.store(true, Ordering::Relaxed);
+                return ec;
+            }
+        }
+    }
+
+    fn recv(&self) -> Result<WorkerResult, RecvTimeoutError> {
+        match self.mode {
+            ReceiverMode::Buffering => {
+                let timeout = self.deadline.saturating_duration_since(Instant::now());
+                self.rx.recv_timeout(timeout)
+            }
+            ReceiverMode::Streaming => self.rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        }
+    }
+
+    //处理一个结果。返回 Err 表示接收线程应当停止。
+    fn poll(&mut self) -> Result<(), ExitCode> {
+        match self.recv() {
+            Ok(WorkerResult::Entry(entry)) => match self.mode {
+                ReceiverMode::Buffering => {
+                    self.buffer.push(entry);
+                    if self.buffer.len() > MAX_BUFFER_LENGTH {
+                        self.stream()?;
+                    }
+                }
+                ReceiverMode::Streaming => self.print(entry)?,
+            },
+            Ok(WorkerResult::Error(err)) => self.stats.record_error(&err),
+            Err(RecvTimeoutError::Timeout) => self.stream()?,
+            Err(RecvTimeoutError::Disconnected) => return self.stop(),
+        }
+        Ok(())
+    }
+
+    fn print(&mut self, entry: DirEntry) -> Result<(), ExitCode> {
+        self.stats.record_match(&entry);
+
+        if let Some(ref mut counter) = self.counter {
+            counter.add(&entry, self.config);
+        } else if let Some(ref mut collected) = self.collected {
+            collected.push(entry);
+        } else if let Err(err) = output::print_entry(&mut self.stdout, &entry, self.config) {
+            //下游管道已关闭（例如 `| head`），安静地结束即可
+            if err.kind() == io::ErrorKind::BrokenPipe {
+                return Err(ExitCode::Success);
+            }
+            print_error(format!("无法写入输出: {err}"));
+            return Err(ExitCode::GeneralError);
+        }
+        self.num_results += 1;
+
+        //达到 --limit 指定的数量后立即结束，process 会设置 quit_flag 让工作线程停止遍历
+        if self
+            .config
+            .max_results
+            .is_some_and(|max| self.num_results >= max)
+        {
+            return self.finish();
+        }
+        Ok(())
+    }
+
+    //切换到流式模式，并输出已经缓冲的所有结果。
+    fn stream(&mut self) -> Result<(), ExitCode> {
+        self.mode = ReceiverMode::Streaming;
+
+        let buffer = std::mem::take(&mut self.buffer);
+        for entry in buffer {
+            self.print(entry)?;
+        }
+        self.flush()
+    }
+
+    //所有工作线程都已结束。缓冲模式下的结果先排序再输出。
+    fn stop(&mut self) -> Result<(), ExitCode> {
+        if self.mode == ReceiverMode::Buffering {
+            self.buffer.sort();
+            self.stream()?;
+        }
+        self.finish()
+    }
+
+    //输出计数结果并刷新输出，然后返回最终的退出码。
+    fn finish(&mut self) -> Result<(), ExitCode> {
+        if let Some(ref counter) = self.counter {
+            if counter.print(&mut self.stdout).is_err() {
+                return Err(ExitCode::GeneralError);
+            }
+        }
+        if let Some(entries) = self.collected.take() {
+            if let Some(format) = self.config.duplicates {
+                if let Err(err) =
+                    duplicates::print_duplicates(&mut self.stdout, entries, self.config, format)
+                {
+                    print_error(format!("无法写入输出: {err}"));
+                    return Err(ExitCode::GeneralError);
+                }
+            } else if let Some(ref action) = self.config.action {
+                let exit_code =
+                    action.run(&mut self.stdout, entries, self.config, self.config.dry_run);
+                if exit_code != ExitCode::Success {
+                    return Err(exit_code);
+                }
+            }
+        }
+        self.flush()?;
+
+        if self.config.quit {
+            Err(ExitCode::HasResult(self.num_results > 0))
+        } else {
+            Err(ExitCode::Success)
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), ExitCode> {
+        if self.stdout.flush().is_err() {
+            return Err(ExitCode::GeneralError);
+        }
+        Ok(())
+    }
+}
+
+//所有线程共享的状态
+struct WorkerState {
+    roots: Vec<Arc<Path>>,
+    patterns: Vec<Regex>,
+    config: Config,
+    //遍历器跳过的条目由日志记录器统计，它只持有 Weak 引用，所以放在 Arc 中
+    stats: Arc<Stats>,
+    quit_flag: AtomicBool,
+    //监视模式下记录初次搜索时遍历过的目录和输出过的条目
+    watched_dirs: Option<Mutex<Vec<PathBuf>>>,
+    matched: Option<Mutex<HashSet<PathBuf>>>,
+}
+
+impl WorkerState {
+    fn new(paths: &[PathBuf], patterns: Vec<Regex>, config: Config) -> Self {
+        Self {
+            roots: paths.iter().map(|path| Arc::from(path.as_path())).collect(),
+            patterns,
+            stats: Arc::new(Stats::new()),
+            quit_flag: AtomicBool::new(false),
+            watched_dirs: config.watch.then(|| Mutex::new(Vec::new())),
+            matched: config.watch.then(|| Mutex::new(HashSet::new())),
+            config,
+        }
+    }
+
+    fn build_walker(&self, paths: &[PathBuf]) -> Result<WalkParallel> {
+        let config = &self.config;
+        let first_path = paths
+            .first()
+            .ok_or_else(|| anyhow!("至少需要指定一个搜索路径"))?;
+
+        let mut builder = self.walk_builder(first_path);
+        builder.max_depth(config.max_depth).threads(config.threads);
+        if config.stats.is_some() {
+            self.stats.capture_walker_skips();
+        }
+
+        for path in &paths[1..] {
+            builder.add(path);
+        }
+
+        Ok(builder.build_parallel())
+    }
+
+    //监视模式下重新遍历子目录时使用相同的规则
+    fn walk_builder(&self, path: &Path) -> WalkBuilder {
+        walk_builder(&self.config, path)
+    }
+
+    //模式匹配的对象：完整路径或者文件名
+    fn search_str(&self, entry: &DirEntry) -> Option<OsString> {
+        if self.config.search_full_path {
+            let path = filesystem::path_absolute_form(entry.path()).ok()?;
+            Some(path.into_os_string())
+        } else {
+            entry.path().file_name().map(ToOwned::to_owned)
+        }
+    }
+
+    //判断条目是否满足所有过滤条件，不满足时返回被过滤的原因。
+    // verdict 为已经求出的过滤表达式的结果，没有时在需要的时候再求值
+    fn check_entry(&self, entry: &DirEntry, verdict: Option<Verdict>) -> Result<(), SkipReason> {
+        let config = &self.config;
+
+        //没有搜索模式时不需要名称，例如 find-compat 输出的搜索路径 . 本身
+        if !self.patterns.is_empty() {
+            let search_str = self.search_str(entry).ok_or(SkipReason::Pattern)?;
+            if !self
+                .patterns
+                .iter()
+                .all(|pat| pat.is_match(search_str.as_encoded_bytes()))
+            {
+                return Err(SkipReason::Pattern);
+            }
+        }
+
+        if let Some(ref file_types) = config.file_types {
+            if file_types.should_ignore(entry) {
+                return Err(SkipReason::FileType);
+            }
+        }
+
+        if !config.size_constraints.is_empty() {
+            let within = entry.is_file()
+                && entry.size().is_some_and(|size| {
+                    config.size_constraints.iter().all(|sc| sc.is_within(size))
+                });
+            if !within {
+                return Err(SkipReason::Size);
+            }
+        }
+
+        if !config.time_constraints.is_empty() {
+            let applies = entry.modified().is_some_and(|modified| {
+                config
+                    .time_constraints
+                    .iter()
+                    .all(|tf| tf.applies_to(&modified))
+            });
+            if !applies {
+                return Err(SkipReason::Time);
+            }
+        }
+
+        #[cfg(unix)]
+        if let Some(ref owner) = config.owner_constraint {
+            if !entry.metadata().is_some_and(|m| owner.matches(m)) {
+                return Err(SkipReason::Owner);
+            }
+        }
+
+        if let Some(ref git_filter) = config.git_filter {
+            if !git_filter.matches(entry) {
+                return Err(SkipReason::Git);
+            }
+        }
+
+        if let Some(ref expr) = config.filter_expr {
+            if !verdict.unwrap_or_else(|| expr.evaluate(entry)).matched {
+                return Err(SkipReason::Expr);
+            }
+        }
+
+        Ok(())
+    }
+
+    //找到包含该路径的搜索路径。搜索路径互相嵌套时取最长的那个。
+    fn root_of(&self, path: &Path) -> Option<&Arc<Path>> {
+        self.roots
+            .iter()
+            .filter(|root| path.starts_with(root))
+            .max_by_key(|root| root.as_os_str().len())
+    }
+
+    //保存第一个搜索模式中各个捕获组匹配到的内容，供格式模板中的 {1}、{name} 使用
+    fn save_captures(&self, entry: &mut DirEntry) {
+        let (Some(pattern), Some(search_str)) = (self.patterns.first(), self.search_str(entry))
+        else {
+            return;
+        };
+        let Some(captures) = pattern.captures(search_str.as_encoded_bytes()) else {
+            return;
+        };
+        let captures = captures
+            .iter()
+            .map(|group| {
+                group
+                    .map(|group| OsString::from(String::from_utf8_lossy(group.as_bytes()).as_ref()))
+            })
+            .collect();
+        entry.set_captures(captures);
+    }
+
+    //检查文件内容，记录第一处匹配的行号。内容过滤开销最大，所以放在其他过滤条件之后。
+    fn check_contents(&self, entry: &mut DirEntry) -> Result<(), SkipReason> {
+        let Some(ref content_filter) = self.config.content_filter else {
+            return Ok(());
+        };
+        if !entry.file_type().is_some_and(|ft| ft.is_file()) {
+            return Err(SkipReason::Contents);
+        }
+        match content_filter.matching_line(entry.path()) {
+            Some(line) => {
+                entry.set_matched_line(line);
+                Ok(())
+            }
+            None => Err(SkipReason::Contents),
+        }
+    }
+
+    fn spawn_senders(&self, walker: WalkParallel, tx: Sender<WorkerResult>) {
+        walker.run(|| {
+            let tx = tx.clone();
+
+            Box::new(move |entry| {
+                if self.quit_flag.load(Ordering::Relaxed) {
+                    return WalkState::Quit;
+                }
+
+                let entry = match entry {
+                    Ok(e) => DirEntry::normol(e),
+                    Err(ignore::Error::WithPath {
+                        path,
+                        err: inner_err,
+                    }) => match inner_err.as_ref() {
+                        ignore::Error::Io(io_error)
+                            if io_error.kind() == io::ErrorKind::NotFound
+                                && path
+                                    .symlink_metadata()
+                                    .is_ok_and(|m| m.file_type().is_symlink()) =>
+                        {
+                            DirEntry::borken_symlink(path)
+                        }
+                        _ => {
+                            let err = ignore::Error::WithPath {
+                                path,
+                                err: inner_err,
+                            };
+                            return match tx.send(WorkerResult::Error(err)) {
+                                Ok(_) => WalkState::Continue,
+                                Err(_) => WalkState::Quit,
+                            };
+                        }
+                    },
+                    Err(err) => {
+                        return match tx.send(WorkerResult::Error(err)) {
+                            Ok(_) => WalkState::Continue,
+                            Err(_) => WalkState::Quit,
+                        };
+                    }
+                };
+
+                if self.config.stats.is_some() {
+                    self.stats.record_visit(&entry, self.config.max_depth);
+                }
+
+                if let Some(ref watched_dirs) = self.watched_dirs {
+                    if self.should_watch(&entry) {
+                        watched_dirs
+                            .lock()
+                            .unwrap()
+                            .push(entry.path().to_path_buf());
+                    }
+                }
+
+                //搜索路径本身不作为结果输出
+                if entry.depth() == Some(0) && !self.config.include_roots {
+                    return WalkState::Continue;
+                }
+
+                //归档本身是否匹配不影响其中的条目
+                if self.config.search_archives
+                    && entry.is_file()
+                    && archives::is_archive(entry.path())
+                    && self.search_archive(&entry, &tx) == WalkState::Quit
+                {
+                    return WalkState::Quit;
+                }
+
+                //过滤表达式中的 -prune 让遍历跳过该目录中的内容，目录本身仍然可能作为结果。
+                // 不管其他条件是否满足都要知道是否跳过，所以先求值，结果留给 check_entry 使用
+                let verdict = self
+                    .config
+                    .filter_expr
+                    .as_ref()
+                    .filter(|expr| expr.has_prune() && entry.is_dir())
+                    .map(|expr| expr.evaluate(&entry));
+                match self.send_entry(entry, verdict, &tx) {
+                    WalkState::Continue if verdict.is_some_and(|v| v.prune) => WalkState::Skip,
+                    state => state,
+                }
+            })
+        });
+    }
+
+    //检查过滤条件，通过的条目发送给接收线程
+    fn send_entry(
+        &self,
+        entry: DirEntry,
+        verdict: Option<Verdict>,
+        tx: &Sender<WorkerResult>,
+    ) -> WalkState {
+        let entry = match self.prepare_entry(entry, verdict) {
+            Ok(entry) => entry,
+            Err(reason) => {
+                self.stats.record_skip(reason);
+                return WalkState::Continue;
+            }
+        };
+
+        if let Some(ref matched) = self.matched {
+            matched.lock().unwrap().insert(entry.path().to_path_buf());
+        }
+
+        match tx.send(WorkerResult::Entry(entry)) {
+            Ok(_) => WalkState::Continue,
+            Err(_) => WalkState::Quit,
+        }
+    }
+
+    //检查过滤条件，并为通过的条目准备好输出时需要的信息
+    fn prepare_entry(
+        &self,
+        mut entry: DirEntry,
+        verdict: Option<Verdict>,
+    ) -> Result<DirEntry, SkipReason> {
+        self.check_entry(&entry, verdict)?;
+        self.check_contents(&mut entry)?;
+        //外部命令的开销最大，放在所有过滤条件之后
+        if let Some(ref command_filter) = self.config.command_filter {
+            if !command_filter.matches(&entry) {
+                return Err(SkipReason::Command);
+            }
+        }
+
+        if let Some(root) = self.root_of(entry.path()) {
+            entry.set_root(Arc::clone(root));
+        }
+
+        //在工作线程中提前计算模板需要的哈希，让哈希计算与遍历并行
+        if let Some(ref format) = self.config.format {
+            for algorithm in format.hash_algorithms() {
+                entry.content_hash(algorithm);
+            }
+            if format.has_captures() {
+                self.save_captures(&mut entry);
+            }
+        }
+        Ok(entry)
+    }
+
+    //把归档中的条目当作归档所在目录下的子条目处理，同样受 --max-depth 限制
+    fn search_archive(&self, archive: &DirEntry, tx: &Sender<WorkerResult>) -> WalkState {
+        let base_depth = archive.depth().unwrap_or(0);
+        let members = match archives::members(archive.path(), self.config.max_archive_depth) {
+            Ok(members) => members,
+            Err(err) => {
+                let err = ignore::Error::WithPath {
+                    path: archive.path().to_path_buf(),
+                    err: Box::new(ignore::Error::Io(err)),
+                };
+                return match tx.send(WorkerResult::Error(err)) {
+                    Ok(_) => WalkState::Continue,
+                    Err(_) => WalkState::Quit,
+                };
+            }
+        };
+
+        for mut member in members {
+            if self.quit_flag.load(Ordering::Relaxed) {
+                return WalkState::Quit;
+            }
+            member.depth += base_depth;
+            if self.config.max_depth.is_some_and(|max| member.depth > max) {
+                continue;
+            }
+            if self.send_entry(DirEntry::archived(member), None, tx) == WalkState::Quit {
+                return WalkState::Quit;
+            }
+        }
+        WalkState::Continue
+    }
+
+    //用索引中记录的条目代替遍历文件系统得到的条目，经过相同的过滤条件。
+    // 条目的路径以搜索路径开头，与遍历时得到的路径相同
+    fn send_indexed(&self, index: &Index, paths: &[PathBuf], tx: Sender<WorkerResult>) {
+        for path in paths {
+            let Ok(absolute) = path.canonicalize() else {
+                continue;
+            };
+            for mut record in index.entries_under(&absolute) {
+                if self.quit_flag.load(Ordering::Relaxed) {
+                    return;
+                }
+                if self.config.max_depth.is_some_and(|max| record.depth > max) {
+                    continue;
+                }
+                if self.config.ignore_hidden
+                    && record
+                        .path
+                        .iter()
+                        .any(|part| part.as_encoded_bytes().starts_with(b"."))
+                {
+                    continue;
+                }
+
+                record.path = path.join(&record.path);
+                let entry = DirEntry::indexed(record);
+                if self.send_entry(entry, None, &tx) == WalkState::Quit {
+                    return;
+                }
+            }
+        }
+    }
+
+    fn receive<W: Write>(&self, rx: Receiver<WorkerResult>, stdout: W) -> ExitCode {
+        ReceiverBuffer::new(self, rx, io::BufWriter::new(stdout)).process()
+    }
+
+    fn scan<W: Write + Send>(&self, paths: &[PathBuf], stdout: W) -> Result<ExitCode> {
+        //在索引中搜索时不需要遍历文件系统
+        let walker = match self.config.index {
+            Some(_) => None,
+            None => Some(self.build_walker(paths)?),
+        };
+        let (tx, rx) = bounded(CHANNEL_CAPACITY);
+
+        let exit_code = thread::scope(|scope| {
+            let receiver = scope.spawn(|| self.receive(rx, stdout));
+            match (walker, &self.config.index) {
+                (Some(walker), _) => self.spawn_senders(walker, tx),
+                (None, Some(index)) => self.send_indexed(index, paths, tx),
+                (None, None) => {}
+            }
+            receiver.join().unwrap_or(ExitCode::GeneralError)
+        });
+
+        if let Some(format) = self.config.stats {
+            self.stats.print(format)?;
+        }
+        if let Some(ref command_filter) = self.config.command_filter {
+            if let Err(err) = command_filter.save_cache() {
+                print_warning(format!("{err:#}"));
+            }
+        }
+
+        #[cfg(target_os = "linux")]
+        if self.config.watch && exit_code == ExitCode::Success {
+            return self.watch();
+        }
+
+        Ok(exit_code)
+    }
+
+    //目录中的变化会影响结果时才需要监视，已经达到 --max-depth 的目录不需要
+    fn should_watch(&self, entry: &DirEntry) -> bool {
+        entry.is_dir()
+            && entry
+                .depth()
+                .is_some_and(|depth| self.config.max_depth.is_none_or(|max| depth < max))
+    }
+}
+
+///按配置设置忽略规则的 WalkBuilder。归档匹配的目录时也用它遍历目录中的内容，
+/// 与搜索时一样跳过隐藏的和被忽略的条目
+pub(crate) fn walk_builder(config: &Config, path: &Path) -> WalkBuilder {
+    let mut builder = WalkBuilder::new(path);
+    builder
+        .hidden(config.ignore_hidden)
+        .ignore(config.read_fdignore)
+        .parents(config.read_fdignore || config.read_vcsignore)
+        .git_ignore(config.read_vcsignore)
+        .git_global(config.read_vcsignore)
+        .git_exclude(config.read_vcsignore)
+        .require_git(false)
+        .follow_links(config.follow_links);
+
+    if config.read_fdignore {
+        builder.add_custom_ignore_filename(".fdignore");
+    }
+    builder
+}
+
+///递归遍历 `paths` 中的所有路径，输出文件名（或完整路径）匹配所有 `patterns`
+/// 并且满足配置中各项过滤条件的条目。
+pub fn scan(paths: &[PathBuf], patterns: Vec<Regex>, config: Config) -> Result<ExitCode> {
+    scan_to(paths, patterns, config, io::stdout())
+}
+
+/*
+库接口的搜索结果：工作线程在后台遍历，结果经过有界通道传给迭代器，
+消费者处理得慢时通道被填满，工作线程随之暂停。迭代器被丢弃时通知工作线程停止遍历。
+结果的顺序不固定，遍历中遇到的错误（例如权限不足）也作为结果返回。
+*/
+pub struct Search {
+    state: Arc<WorkerState>,
+    rx: Receiver<WorkerResult>,
+    remaining: Option<usize>,
+}
+
+impl Iterator for Search {
+    type Item = Result<DirEntry, ignore::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == Some(0) {
+            return None;
+        }
+        match self.rx.recv().ok()? {
+            WorkerResult::Entry(entry) => {
+                //达到结果数量上限后让工作线程停止遍历
+                if let Some(ref mut remaining) = self.remaining {
+                    *remaining -= 1;
+                    if *remaining == 0 {
+                        self.state.quit_flag.store(true, Ordering::Relaxed);
+                    }
+                }
+                Some(Ok(entry))
+            }
+            WorkerResult::Error(err) => Some(Err(err)),
+        }
+    }
+}
+
+///在其他线程中让遍历停止的句柄
+#[cfg(feature = "stream")]
+#[derive(Clone)]
+pub(crate) struct StopHandle(Arc<WorkerState>);
+
+#[cfg(feature = "stream")]
+impl StopHandle {
+    pub(crate) fn stop(&self) {
+        self.0.quit_flag.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(all(test, feature = "stream"))]
+impl StopHandle {
+    //除了这个句柄之外还在使用遍历状态的地方：遍历线程、Search 和其他句柄
+    pub(crate) fn other_users(&self) -> usize {
+        Arc::strong_count(&self.0) - 1
+    }
+}
+
+#[cfg(feature = "stream")]
+impl Search {
+    pub(crate) fn stop_handle(&self) -> StopHandle {
+        StopHandle(Arc::clone(&self.state))
+    }
+}
+
+impl Drop for Search {
+    fn drop(&mut self) {
+        self.state.quit_flag.store(true, Ordering::Relaxed);
+    }
+}
+
+///在后台线程中开始遍历，返回结果的迭代器
+pub fn search(paths: &[PathBuf], patterns: Vec<Regex>, config: Config) -> Result<Search> {
+    let state = Arc::new(WorkerState::new(paths, patterns, config));
+    let walker = state.build_walker(paths)?;
+    let (tx, rx) = bounded(CHANNEL_CAPACITY);
+
+    let worker = Arc::clone(&state);
+    thread::spawn(move || worker.spawn_senders(walker, tx));
+
+    let remaining = state.config.max_results;
+    Ok(Search {
+        state,
+        rx,
+        remaining,
+    })
+}
+
+///与 scan 相同，但结果写入 stdout 参数（例如 serve 中客户端的连接）
+pub fn scan_to<W: Write + Send>(
+    paths: &[PathBuf],
+    patterns: Vec<Regex>,
+    config: Config,
+    stdout: W,
+) -> Result<ExitCode> {
+    WorkerState::new(paths, patterns, config).scan(paths, stdout)
+}
@@ -0,0 +1,411 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    ffi::OsString,
+    io::{self, Write},
+    os::fd::AsFd,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use nix::{
+    errno::Errno,
+    poll::{poll, PollFd, PollFlags, PollTimeout},
+    sys::inotify::{AddWatchFlags, InitFlags, Inotify, WatchDescriptor},
+};
+
+use super::WorkerState;
+use crate::{dir_entry::DirEntry, error::print_error, error_codes::ExitCode, output};
+
+//收到事件后等待这么久没有新的事件，才处理这一批事件。保存文件等操作会连续产生很多事件
+const COALESCE_DELAY: u16 = 100;
+
+//一直有新的事件时最多等待这么久，避免持续写入的文件让结果迟迟不输出
+const MAX_COALESCE_TIME: Duration = Duration::from_secs(1);
+
+//每批事件按目录分组，记录目录中发生变化的条目名称
+type Changes = BTreeMap<PathBuf, BTreeSet<OsString>>;
+
+/*
+监视模式（--watch）：
+初次搜索结束后，监视搜索时遍历过的每个目录，一批事件结束后重新检查发生变化的条目：
+1.新出现的或者修改后满足过滤条件的条目，输出一次。
+2.之前输出过、现在不存在或不再满足过滤条件的条目，在 --watch-removed 时输出。
+新建或移入的目录会连同其中的内容一起检查，并加入监视。重新检查时使用与初次搜索相同的忽略规则。
+*/
+struct Watcher<'a, W> {
+    state: &'a WorkerState,
+    inotify: Inotify,
+    dirs: HashMap<WatchDescriptor, PathBuf>,
+    watched: HashMap<PathBuf, WatchDescriptor>,
+    //当前满足过滤条件的条目
+    matched: HashSet<PathBuf>,
+    stdout: W,
+}
+
+impl WorkerState {
+    //初次搜索结束后一直监视搜索路径，直到被中断或者无法继续输出
+    pub(super) fn watch(&self) -> Result<ExitCode> {
+        let inotify = Inotify::init(InitFlags::IN_CLOEXEC | InitFlags::IN_NONBLOCK)
+            .map_err(|err| anyhow!("无法初始化 inotify: {err}"))?;
+        let matched = self
+            .matched
+            .as_ref()
+            .map(|matched| std::mem::take(&mut *matched.lock().unwrap()))
+            .unwrap_or_default();
+        let dirs = self
+            .watched_dirs
+            .as_ref()
+            .map(|dirs| std::mem::take(&mut *dirs.lock().unwrap()))
+            .unwrap_or_default();
+
+        let stdout = io::stdout();
+        let mut watcher = Watcher {
+            state: self,
+            inotify,
+            dirs: HashMap::new(),
+            watched: HashMap::new(),
+            matched,
+            stdout: io::BufWriter::new(stdout.lock()),
+        };
+        for dir in dirs {
+            watcher.add_watch(dir);
+        }
+        Ok(watcher.run())
+    }
+}
+
+impl<W: Write> Watcher<'_, W> {
+    fn run(&mut self) -> ExitCode {
+        loop {
+            let changes = match self.wait_changes() {
+                Ok(changes) => changes,
+                Err(err) => {
+                    print_error(format!("无法读取 inotify 事件: {err}"));
+                    return ExitCode::GeneralError;
+                }
+            };
+            let result = changes
+                .iter()
+                .try_for_each(|(dir, names)| self.rescan(dir, names))
+                .and_then(|_| self.flush());
+            if let Err(exit_code) = result {
+                return exit_code;
+            }
+        }
+    }
+
+    fn add_watch(&mut self, dir: PathBuf) {
+        let mut mask = AddWatchFlags::IN_CREATE
+            | AddWatchFlags::IN_DELETE
+            | AddWatchFlags::IN_MODIFY
+            | AddWatchFlags::IN_ATTRIB
+            | AddWatchFlags::IN_CLOSE_WRITE
+            | AddWatchFlags::IN_MOVED_FROM
+            | AddWatchFlags::IN_MOVED_TO
+            | AddWatchFlags::IN_ONLYDIR;
+        if !self.state.config.follow_links {
+            mask |= AddWatchFlags::IN_DONT_FOLLOW;
+        }
+
+        match self.inotify.add_watch(dir.as_path(), mask) {
+            Ok(wd) => {
+                self.dirs.insert(wd, dir.clone());
+                self.watched.insert(dir, wd);
+            }
+            //监视数量超过 fs.inotify.max_user_watches 时返回 ENOSPC
+            Err(err) => print_error(format!("无法监视'{}': {}", dir.display(), err)),
+        }
+    }
+
+    //等待下一批事件，按目录整理出发生变化的条目
+    fn wait_changes(&mut self) -> nix::Result<Changes> {
+        let mut changes = Changes::new();
+        let mut started: Option<Instant> = None;
+        loop {
+            let timeout = match started {
+                None => PollTimeout::NONE,
+                Some(started) if started.elapsed() >= MAX_COALESCE_TIME => break,
+                Some(_) => PollTimeout::from(COALESCE_DELAY),
+            };
+            let mut fds = [PollFd::new(self.inotify.as_fd(), PollFlags::POLLIN)];
+            match poll(&mut fds, timeout) {
+                Ok(0) => break,
+                Ok(_) | Err(Errno::EINTR) => {}
+                Err(err) => return Err(err),
+            }
+
+            let events = match self.inotify.read_events() {
+                Ok(events) => events,
+                Err(Errno::EAGAIN) => continue,
+                Err(err) => return Err(err),
+            };
+            started.get_or_insert_with(Instant::now);
+
+            for event in events {
+                if event.mask.contains(AddWatchFlags::IN_Q_OVERFLOW) {
+                    print_error("inotify 事件队列溢出，部分变化可能没有输出");
+                    continue;
+                }
+                //目录被删除或者移出文件系统后，监视会被自动移除
+                if event.mask.contains(AddWatchFlags::IN_IGNORED) {
+                    if let Some(dir) = self.dirs.remove(&event.wd) {
+                        if self.watched.get(&dir) == Some(&event.wd) {
+                            self.watched.remove(&dir);
+                        }
+                    }
+                    continue;
+                }
+                let (Some(dir), Some(name)) = (self.dirs.get(&event.wd), event.name) else {
+                    continue;
+                };
+                changes.entry(dir.clone()).or_default().insert(name);
+            }
+        }
+        Ok(changes)
+    }
+
+    //重新检查目录中发生变化的条目。遍历时只保留这些条目，同时应用与初次搜索相同的忽略规则
+    fn rescan(&mut self, dir: &Path, names: &BTreeSet<OsString>) -> Result<(), ExitCode> {
+        let Some(root) = self.state.root_of(dir).map(Arc::clone) else {
+            return Ok(());
+        };
+        let base_depth = dir
+            .strip_prefix(&root)
+            .map(|relative| relative.components().count())
+            .unwrap_or(0);
+
+        let filter_names = names.clone();
+        let mut builder = self.state.walk_builder(dir);
+        builder
+            .max_depth(Some(1))
+            .filter_entry(move |e| e.depth() == 0 || filter_names.contains(e.file_name()));
+
+        let mut seen = HashSet::new();
+        for result in builder.build() {
+            let Ok(e) = result else {
+                continue;
+            };
+            if e.depth() == 0 {
+                continue;
+            }
+            seen.insert(e.file_name().to_owned());
+
+            let mut entry = DirEntry::normol(e);
+            entry.set_base_depth(base_depth);
+            if self.state.should_watch(&entry) && !self.watched.contains_key(entry.path()) {
+                self.scan_new_dir(entry)?;
+            } else {
+                self.update(entry)?;
+            }
+        }
+
+        //被删除、移出或者被忽略规则排除的条目
+        for name in names.iter().filter(|name| !seen.contains(*name)) {
+            self.forget(&dir.join(name))?;
+        }
+        Ok(())
+    }
+
+    //新建或移入的目录：检查其中的所有条目，并监视其中的所有目录
+    fn scan_new_dir(&mut self, entry: DirEntry) -> Result<(), ExitCode> {
+        let path = entry.path().to_path_buf();
+        let depth = entry.depth().unwrap_or(0);
+        self.add_watch(path.clone());
+        self.update(entry)?;
+
+        let mut builder = self.state.walk_builder(&path);
+        builder.max_depth(self.state.config.max_depth.map(|max| max - depth));
+        for result in builder.build() {
+            let Ok(e) = result else {
+                continue;
+            };
+            if e.depth() == 0 {
+                continue;
+            }
+            let mut entry = DirEntry::normol(e);
+            entry.set_base_depth(depth);
+            if self.state.should_watch(&entry) && !self.watched.contains_key(entry.path()) {
+                self.add_watch(entry.path().to_path_buf());
+            }
+            self.update(entry)?;
+        }
+        Ok(())
+    }
+
+    //根据过滤条件的结果更新条目的状态，状态改变时输出
+    fn update(&mut self, entry: DirEntry) -> Result<(), ExitCode> {
+        let path = entry.path().to_path_buf();
+        match self.state.prepare_entry(entry, None) {
+            Ok(entry) => {
+                if self.matched.insert(path) {
+                    self.print_added(&entry)?;
+                }
+            }
+            Err(_) => {
+                if self.matched.remove(&path) {
+                    self.print_removed(&path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    //条目已经不存在，连同其中之前匹配的条目一起移除，并停止监视其中的目录
+    fn forget(&mut self, path: &Path) -> Result<(), ExitCode> {
+        let mut removed: Vec<_> = self
+            .matched
+            .iter()
+            .filter(|matched| matched.starts_with(path))
+            .cloned()
+            .collect();
+        removed.sort();
+        for matched in removed {
+            self.matched.remove(&matched);
+            self.print_removed(&matched)?;
+        }
+
+        let dirs: Vec<_> = self
+            .watched
+            .keys()
+            .filter(|dir| dir.starts_with(path))
+            .cloned()
+            .collect();
+        for dir in dirs {
+            if let Some(wd) = self.watched.remove(&dir) {
+                self.dirs.remove(&wd);
+                //目录已经被删除时监视已经自动移除，忽略错误
+                let _ = self.inotify.rm_watch(wd);
+            }
+        }
+        Ok(())
+    }
+
+    fn print_added(&mut self, entry: &DirEntry) -> Result<(), ExitCode> {
+        let state = self.state;
+        let config = &state.config;
+        let result = if config.watch_removed {
+            write!(self.stdout, "+ ")
+        } else {
+            Ok(())
+        };
+        check_output(result.and_then(|_| output::print_entry(&mut self.stdout, entry, config)))
+    }
+
+    fn print_removed(&mut self, path: &Path) -> Result<(), ExitCode> {
+        let state = self.state;
+        let config = &state.config;
+        if !config.watch_removed {
+            return Ok(());
+        }
+        let result = write!(self.stdout, "- ")
+            .and_then(|_| output::print_path(&mut self.stdout, path, config));
+        check_output(result)
+    }
+
+    fn flush(&mut self) -> Result<(), ExitCode> {
+        let result = self.stdout.flush();
+        check_output(result)
+    }
+}
+
+fn check_output(result: io::Result<()>) -> Result<(), ExitCode> {
+    match result {
+        Ok(()) => Ok(()),
+        //下游管道已关闭（例如 `| head`），安静地结束即可
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Err(ExitCode::Success),
+        Err(err) => {
+            print_error(format!("无法写入输出: {err}"));
+            Err(ExitCode::GeneralError)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use regex::bytes::Regex;
+
+    use super::*;
+    use crate::{config::Config, test_util::create_tree};
+
+    //处理一批事件，返回输出的行（路径相对于 root），按字典序排列
+    fn step(watcher: &mut Watcher<Vec<u8>>, root: &Path) -> Vec<String> {
+        let changes = watcher.wait_changes().unwrap();
+        for (dir, names) in &changes {
+            watcher.rescan(dir, names).unwrap();
+        }
+        watcher.flush().unwrap();
+
+        let output = String::from_utf8(std::mem::take(&mut watcher.stdout)).unwrap();
+        let prefix = format!("{}/", root.display());
+        let mut lines: Vec<_> = output
+            .lines()
+            .map(|line| line.replacen(&prefix, "", 1))
+            .collect();
+        lines.sort();
+        lines
+    }
+
+    #[test]
+    fn reports_created_moved_in_and_deleted_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        let outside = dir.path().join("outside");
+        create_tree(
+            dir.path(),
+            &["root/", "outside/moved.txt", "outside/tree/x.txt"],
+        );
+
+        let config = Config {
+            watch: true,
+            watch_removed: true,
+            ignore_hidden: false,
+            read_fdignore: false,
+            read_vcsignore: false,
+            ..Config::default()
+        };
+        let state = WorkerState::new(
+            std::slice::from_ref(&root),
+            vec![Regex::new("").unwrap()],
+            config,
+        );
+        let mut watcher = Watcher {
+            state: &state,
+            inotify: Inotify::init(InitFlags::IN_CLOEXEC | InitFlags::IN_NONBLOCK).unwrap(),
+            dirs: HashMap::new(),
+            watched: HashMap::new(),
+            matched: HashSet::new(),
+            stdout: Vec::new(),
+        };
+        watcher.add_watch(root.clone());
+
+        //新建的目录连同其中的内容一起输出，并加入监视
+        create_tree(&root, &["new.txt", "dir/inner.txt"]);
+        assert_eq!(
+            step(&mut watcher, &root),
+            ["+ dir/", "+ dir/inner.txt", "+ new.txt"]
+        );
+        assert!(watcher.watched.contains_key(&root.join("dir")));
+
+        //移入的文件和目录
+        fs::rename(outside.join("moved.txt"), root.join("dir/moved.txt")).unwrap();
+        fs::rename(outside.join("tree"), root.join("tree")).unwrap();
+        assert_eq!(
+            step(&mut watcher, &root),
+            ["+ dir/moved.txt", "+ tree/", "+ tree/x.txt"]
+        );
+
+        //删除目录时其中之前输出过的条目也一起输出，并停止监视
+        fs::remove_file(root.join("new.txt")).unwrap();
+        fs::remove_dir_all(root.join("dir")).unwrap();
+        assert_eq!(
+            step(&mut watcher, &root),
+            ["- dir", "- dir/inner.txt", "- dir/moved.txt", "- new.txt"]
+        );
+        assert!(!watcher.watched.contains_key(&root.join("dir")));
+        assert!(watcher.watched.contains_key(&root.join("tree")));
+    }
+}